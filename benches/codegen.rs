@@ -0,0 +1,39 @@
+//! How long generating the model takes for a big program, like the imports of a whole game.
+//! Run with `cargo bench --bench codegen`.
+
+use std::fmt::Write;
+use criterion::{criterion_group, criterion_main, Criterion};
+use reaction_solver::codegen::{generate_minizinc, Options};
+use reaction_solver::model::Model;
+use reaction_solver::parse_checked;
+use reaction_solver::sources::{FileKind, Sources};
+
+/// How many reactions the generated chem file has
+const REACTIONS: usize = 50_000;
+
+/// A chain of reactions, each making the next part from the one before and some ore, and
+/// a bounded one making the first part again from the last, so bounds propagate around a cycle
+fn chem_file() -> String {
+    let mut text = format!("raw ore;\n\nzone pit {{\n    capacity 4;\n    \"mine\": -(2)-> part0;\n    \"recycle\": part{REACTIONS} -(1)-> part0;\n}}\n\n");
+    for i in (0..REACTIONS).rev() {
+        writeln!(text, "\"step{i}\": 2 part{i} + 1 ore -({})-> part{};", 1 + i % 5, i + 1).unwrap();
+    }
+    writeln!(text, "\ntarget last {{\n    create 1 part{REACTIONS} in 10;\n    minimize reactions;\n}}").unwrap();
+    text
+}
+
+fn codegen(c: &mut Criterion) {
+    let text = chem_file();
+    let sources = Sources::new([("bench.chem".to_string(), text.as_str(), FileKind::Chem)]);
+    let program = parse_checked(sources.text()).expect("the generated chem file parses");
+    let model = Model::new(&program, false);
+    let target = &program.targets["last"];
+
+    let mut group = c.benchmark_group("codegen");
+    group.sample_size(10);
+    group.bench_function("50k reactions", |b| b.iter(|| generate_minizinc(&sources, &model, target, &Options::default()).unwrap()));
+    group.finish();
+}
+
+criterion_group!(benches, codegen);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+//! Run with `cargo +nightly fuzz run parse`. Parsing has to fail with diagnostics, never panic.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = reaction_solver::parse_checked(input);
+    }
+});
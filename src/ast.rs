@@ -1,57 +1,923 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use itertools::Itertools;
+use lalrpop_util::ParseError;
+use serde::{Deserialize, Serialize};
+use crate::ChemParseError;
 
 pub type ReactionTerms<'s> = HashMap<Symbol<'s>, usize>;
 
-#[derive(Debug)]
+/// Add up the terms of sums, like `2 a + b` and `a` to `3 a + b`.
+/// The amounts can be signed, so terms can cancel out, like `2 a + b` and `-2 a` to `0 a + b`.
+/// They are added up as `i128`, and checked to fit an amount again: the error is a symbol whose amounts don't.
+pub fn sum_terms<'s, A>(sums: impl IntoIterator<Item = HashMap<Symbol<'s>, A>>) -> Result<ReactionTerms<'s>, Symbol<'s>>
+where
+    i128: TryFrom<A>,
+{
+    let mut totals: HashMap<Symbol<'s>, i128> = HashMap::new();
+
+    for terms in sums {
+        for (symbol, amount) in terms {
+            let total = totals.entry(symbol).or_insert(0);
+            *total = i128::try_from(amount).ok().and_then(|amount| total.checked_add(amount)).ok_or(symbol)?;
+        }
+    }
+
+    totals
+        .into_iter()
+        .map(|(symbol, total)| usize::try_from(total).map(|amount| (symbol, amount)).map_err(|_| symbol))
+        .collect()
+}
+
+/// The error of [`sum_terms`], as a message
+pub fn sum_error(symbol: Symbol) -> String {
+    format!("the amounts of {} don't add up to an amount from 0 to {}", symbol.0, usize::MAX)
+}
+
+/// [`sum_terms`] in an action of the grammar, with the error spanning `l..r`
+pub fn sum_parsed_terms<'s, T>(l: usize, sums: impl IntoIterator<Item = ReactionTerms<'s>>, r: usize) -> Result<ReactionTerms<'s>, ChemParseError<T>> {
+    sum_terms(sums).map_err(|symbol| ParseError::User {
+        error: (l, sum_error(symbol), r)
+    })
+}
+
+/// The longest a `per-machine` reaction is made to take, in time units
+pub const MAX_PER_MACHINE_TIME: usize = 3600;
+
+/// The time and amounts of a reaction given by what one machine of it uses and makes per time unit,
+/// like `per-machine 0.5 iron-ore/s -> 0.5 iron-plate/s`: the shortest time in which every
+/// rate comes to a whole amount, here `iron-ore -(2)-> iron-plate`.
+pub fn per_machine_amounts<'s>(rates: &[(f64, Symbol<'s>)]) -> Result<(usize, Vec<ReactionTerms<'s>>), String> {
+    if let Some((rate, symbol)) = rates.iter().find(|(rate, _)| *rate <= 0.0 || rate.is_nan()) {
+        return Err(format!("the rate of {} has to be positive, not {rate}", symbol.0));
+    }
+
+    let whole = |amount: f64| (amount - amount.round()).abs() <= 1e-6 * amount.max(1.0);
+    let time = (1..=MAX_PER_MACHINE_TIME)
+        .find(|&time| rates.iter().all(|(rate, _)| whole(rate * time as f64)))
+        .ok_or_else(|| format!("the rates don't come to whole amounts in any time up to {MAX_PER_MACHINE_TIME}"))?;
+
+    let terms = rates
+        .iter()
+        .map(|&(rate, symbol)| HashMap::from([(symbol, (rate * time as f64).round() as usize)]))
+        .collect();
+    Ok((time, terms))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Goal<'s> {
-    Resources(ReactionTerms<'s>),
-    Reactions
+    Resources(#[serde(borrow)] ReactionTerms<'s>),
+    Reactions,
+    /// The time needed to meet the target's constraints,
+    /// with at most the given number of machines for each reaction
+    Time,
+    /// The total of one of the reactions' [`Cost::other`] costs
+    Cost(&'s str),
+    /// The total cost of the techs to unlock for the reactions used
+    Techs,
 }
 
+/// Another goal of a target, with a name, like `minimize small: reactions;`.
+/// Picked instead of the target's goal with `target:small`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalVariant<'s> {
+    pub name: &'s str,
+    #[serde(borrow)]
+    pub goal: Goal<'s>,
+    /// Where each resource of a resources goal is, like [`Target::goal_spans`]
+    #[serde(borrow)]
+    pub goal_spans: HashMap<Symbol<'s>, (usize, usize)>,
+    pub span: (usize, usize),
+}
+
+/// Something parsed, with where it starts and ends
+pub type Spanned<T> = (usize, T, usize);
+
+// items only live until they're put in a program, so they aren't worth boxing
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Item<'s> {
     Target(Target<'s>),
     Reaction(Reaction<'s>),
+    Transport((Symbol<'s>, f64)),
+    Zone((Zone<'s>, Vec<Reaction<'s>>)),
+    Raw(Vec<Symbol<'s>>),
+    Suite(Suite<'s>),
+    /// `alias a = b;`, making `a` another name for `b`
+    Alias((Symbol<'s>, Symbol<'s>)),
+    Tech(Tech<'s>),
+    /// `element H2O { H: 2, O: 1 }`, what a symbol is made of
+    Element((Symbol<'s>, HashMap<&'s str, usize>)),
+    Symbol((Symbol<'s>, SymbolInfo<'s>)),
+}
+
+/// How a symbol is shown in the output, like
+/// `symbol iron-plate { display "Iron plate"; icon "iron-plate.png"; }`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SymbolInfo<'s> {
+    #[serde(borrow)]
+    pub display: Option<Cow<'s, str>>,
+    /// A path or url of an image of the symbol, for outputs that can show one
+    #[serde(borrow)]
+    pub icon: Option<Cow<'s, str>>,
 }
 
 #[derive(Debug)]
-pub enum TargetItem<'s> {
-    Input(Vec<Symbol<'s>>),
-    Constraint(Vec<ReactionTerms<'s>>),
+pub enum SymbolInfoItem<'s> {
+    Display(Cow<'s, str>),
+    Icon(Cow<'s, str>),
+}
+
+/// Something to unlock before the reactions requiring it can be used,
+/// like `tech logistics cost 20 requires automation;`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tech<'s> {
+    pub name: &'s str,
+    pub cost: f64,
+    /// Techs to unlock before this one
+    #[serde(borrow)]
+    pub requires: Vec<&'s str>,
+    pub span: (usize, usize),
+}
+
+#[derive(Debug)]
+pub enum SuiteItem<'s> {
+    Targets(Vec<(&'s str, (usize, usize))>),
     InTime(usize),
     Goal(Goal<'s>),
 }
 
-#[derive(Copy, Clone, Debug)]
-pub struct Cost(pub isize);
+/// Targets to solve together and compare, with shared settings overriding their own
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Suite<'s> {
+    pub name: &'s str,
+    /// The names of the targets, and where they're mentioned
+    #[serde(borrow)]
+    pub targets: Vec<(&'s str, (usize, usize))>,
+    pub in_time: Option<usize>,
+    #[serde(borrow)]
+    pub goal: Option<Goal<'s>>,
+    pub span: (usize, usize),
+}
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub struct Symbol<'s>(pub &'s str);
+/// A `create` item of a target, like `create at-most 5 pollution in 60;`
+#[derive(Debug)]
+pub struct CreateConstraint<'s> {
+    pub comparison: Option<Comparison>,
+    pub terms: Vec<ReactionTerms<'s>>,
+    pub window: Option<usize>,
+}
 
 #[derive(Debug)]
+pub enum TargetItem<'s> {
+    Input(Vec<(Symbol<'s>, (usize, usize))>),
+    Constraint(CreateConstraint<'s>),
+    InTime(usize),
+    /// The goal, and where each of its resources is
+    Goal(Goal<'s>, HashMap<Symbol<'s>, (usize, usize)>),
+    GoalVariant(GoalVariant<'s>),
+    Group(ConstraintGroup<'s>),
+    Preference(Preference<'s>),
+    Quantum(Quantum<'s>),
+    TransportWeight(f64),
+    TransportBudget(f64),
+    Budget(&'s str, f64),
+    Enable(Vec<&'s str>),
+    LinearConstraint(LinearConstraint<'s>),
+}
+
+/// What a term of a [`LinearConstraint`] is a multiple of
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Quantity<'s> {
+    /// Just the number
+    One,
+    /// The objective of the target itself
+    Own,
+    /// The objective of another target, which is solved first
+    Of(&'s str, (usize, usize)),
+    /// How much of a symbol the reactions create, minus what they use, over the target's time
+    Net(#[serde(borrow)] Symbol<'s>, (usize, usize)),
+}
+
+/// A linear constraint on what a target's reactions create and on objectives,
+/// like `constraint 2 * plastic + sulfur <= 500;` or `constraint objective <= 1.1 * objective(base);`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinearConstraint<'s> {
+    #[serde(borrow)]
+    pub lhs: Vec<(f64, Quantity<'s>)>,
+    #[serde(borrow)]
+    pub rhs: Vec<(f64, Quantity<'s>)>,
+    /// Whether the left side is at most the right side, or at least
+    pub at_most: bool,
+    pub span: (usize, usize),
+}
+
+impl<'s> LinearConstraint<'s> {
+    /// The targets whose objectives this constraint uses, and where
+    pub fn references(&self) -> impl Iterator<Item=(&'s str, (usize, usize))> + '_ {
+        self.lhs.iter().chain(&self.rhs).filter_map(|(_, r)| match r {
+            Quantity::Of(name, span) => Some((*name, *span)),
+            _ => None,
+        })
+    }
+}
+
+/// A soft constraint, which may be violated at a cost of `penalty` per unit per time unit
+/// it falls short by, like the rates of target constraints (`prefer 100 science` in 10 time units
+/// falls short by 1 when creating 90)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Preference<'s> {
+    #[serde(borrow)]
+    pub terms: ReactionTerms<'s>,
+    pub penalty: usize,
+}
+
+/// What has to come in whole multiples of a [`Quantum`]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum QuantumSubject<'s> {
+    /// The total production rate of a symbol
+    Symbol(#[serde(borrow)] Symbol<'s>),
+    /// The rate of the reaction with this label
+    Reaction(#[serde(borrow)] Cow<'s, str>),
+}
+
+/// Requires a rate to be an integer multiple of `quantum`,
+/// for example because it has to fit on a whole number of belts
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Quantum<'s> {
+    #[serde(borrow)]
+    pub subject: QuantumSubject<'s>,
+    pub quantum: usize,
+    pub span: (usize, usize),
+}
+
+/// A named set of constraints in a target, which can be switched on or off when solving
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConstraintGroup<'s> {
+    #[serde(borrow)]
+    pub name: Cow<'s, str>,
+    pub enabled: bool,
+    pub priority: Priority,
+    #[serde(borrow)]
+    pub constraints: ReactionTerms<'s>,
+    /// The comparisons of its constraints, like those of [`Target::comparisons`]
+    #[serde(borrow)]
+    pub comparisons: HashMap<Symbol<'s>, Comparison>,
+    /// The time windows of its constraints, like those of [`Target::windows`]
+    #[serde(borrow)]
+    pub windows: HashMap<Symbol<'s>, usize>,
+}
+
+/// Which groups of constraints are given up first when a target is unsatisfiable,
+/// like `group "bonus" priority low { ... }`. Hard groups never are.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Priority {
+    Low,
+    High,
+    #[default]
+    Hard,
+}
+
+/// Annotations before a reaction, like `#[deprecated("use advanced smelting")]`
+#[derive(Debug)]
+pub enum ReactionAnnotation<'s> {
+    Deprecated(Cow<'s, str>),
+    Optional(&'s str),
+}
+
+#[derive(Debug)]
+pub enum ReactionAttribute<'s> {
+    FixedCost(f64),
+    MinBatch(f64),
+    Machines(f64),
+    CostCurve(Vec<(f64, f64)>),
+    Costs(Vec<(&'s str, f64)>),
+    Tag(Cow<'s, str>),
+    Machine(Cow<'s, str>),
+    Requires(Vec<&'s str>),
+    Enthalpy(f64),
+}
+
+/// What a reaction costs
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cost<'s> {
+    /// The time one reaction takes, always positive
+    pub time: usize,
+    /// Named costs per machine per time unit, like `cost { power: 90 }`
+    #[serde(borrow)]
+    pub other: HashMap<&'s str, f64>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Symbol<'s>(pub &'s str);
+
+/// The attribute of a symbol like `steam@500` or `steam@165..500`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Attribute {
+    Value(u64),
+    /// An inclusive range, only allowed as a reaction input
+    Range(u64, u64),
+}
+
+impl<'s> Symbol<'s> {
+    /// The symbol without its attribute, and the attribute if it has one
+    pub fn attribute(&self) -> (&'s str, Option<Attribute>) {
+        let Some((base, attribute)) = self.0.split_once('@') else {
+            return (self.0, None);
+        };
+
+        let attribute = match attribute.split_once("..") {
+            Some((lo, hi)) => lo.parse().ok().zip(hi.parse().ok()).map(|(lo, hi)| Attribute::Range(lo, hi)),
+            None => attribute.parse().ok().map(Attribute::Value),
+        };
+
+        (base, attribute)
+    }
+
+    /// Whether this symbol stands for any of a range of symbols
+    pub fn is_range(&self) -> bool {
+        matches!(self.attribute().1, Some(Attribute::Range(..)))
+    }
+
+    /// Whether this is the same symbol with an attribute inside the range of `range`
+    pub fn in_range(&self, range: &Symbol) -> bool {
+        match (self.attribute(), range.attribute()) {
+            ((base, Some(Attribute::Value(v))), (range_base, Some(Attribute::Range(lo, hi)))) => {
+                base == range_base && (lo..=hi).contains(&v)
+            }
+            _ => false,
+        }
+    }
+
+    /// This symbol as part of a minizinc identifier
+    pub fn mzn_name(&self) -> String {
+        self.0.replace('-', "_").replace("..", "_to_").replace('@', "_at_")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Target<'s> {
+    #[serde(borrow)]
     pub inputs: Vec<Symbol<'s>>,
+    #[serde(borrow)]
     pub constraints: ReactionTerms<'s>,
     pub in_time: usize,
     pub name: &'s str,
+    #[serde(borrow)]
     pub goal: Option<Goal<'s>>,
+    #[serde(borrow)]
+    pub groups: Vec<ConstraintGroup<'s>>,
+    #[serde(borrow)]
+    pub preferences: Vec<Preference<'s>>,
+    #[serde(borrow)]
+    pub quanta: Vec<Quantum<'s>>,
+    /// How heavily total transport costs count in the objective
+    pub transport_weight: Option<f64>,
+    /// An upper bound on total transport costs
+    pub transport_budget: Option<f64>,
+    /// Upper bounds on the totals of named reaction costs
+    #[serde(borrow)]
+    pub budgets: HashMap<&'s str, f64>,
+    /// Features enabling optional reactions
+    #[serde(borrow)]
+    pub features: Vec<&'s str>,
+    #[serde(borrow)]
+    pub linear_constraints: Vec<LinearConstraint<'s>>,
+    /// Where inputs are listed in the chem file, the first time if several times
+    #[serde(borrow)]
+    pub input_spans: HashMap<Symbol<'s>, (usize, usize)>,
+    /// Where the constraints on each symbol are, the first time if several times
+    #[serde(borrow)]
+    pub constraint_spans: HashMap<Symbol<'s>, (usize, usize)>,
+    /// How what's created of a symbol compares to its constraint, if not at least (see [`Target::comparison`])
+    #[serde(borrow)]
+    pub comparisons: HashMap<Symbol<'s>, Comparison>,
+    /// The time the constraint on a symbol has to be met in, if not `in_time` (see [`Target::window`])
+    #[serde(borrow)]
+    pub windows: HashMap<Symbol<'s>, usize>,
+    pub goal_span: Option<(usize, usize)>,
+    /// Where each resource of a resources goal is, the first time if several times
+    #[serde(borrow)]
+    pub goal_spans: HashMap<Symbol<'s>, (usize, usize)>,
+    /// Named goals to pick instead of `goal`
+    #[serde(borrow)]
+    pub variants: Vec<GoalVariant<'s>>,
     pub span: (usize, usize),
+    pub name_span: (usize, usize),
 }
 
-#[derive(Debug)]
+impl<'s> Target<'s> {
+    /// The target's constraints, together with those of all enabled groups
+    pub fn active_constraints(&self) -> ReactionTerms<'s> {
+        let groups = self.groups.iter().filter(|g| g.enabled).map(|g| g.constraints.clone());
+        sum_terms(std::iter::once(self.constraints.clone()).chain(groups))
+            .expect("checked by Target::check_sums")
+    }
+
+    /// Whether the target's constraints can be added up with those of all its groups,
+    /// which [`Target::active_constraints`] relies on. The error is the message saying why not
+    pub fn check_sums(&self) -> Result<(), String> {
+        let groups = self.groups.iter().map(|g| g.constraints.clone());
+        sum_terms(std::iter::once(self.constraints.clone()).chain(groups))
+            .map(|_| ())
+            .map_err(sum_error)
+    }
+
+    /// How what the target creates of a symbol has to compare to the amount in its constraint
+    pub fn comparison(&self, symbol: &Symbol<'s>) -> Comparison {
+        self.comparisons.get(symbol).copied().unwrap_or_default()
+    }
+
+    /// The time the constraint on a symbol has to be met in, like 60 for `create 100 science in 60;`
+    pub fn window(&self, symbol: &Symbol<'s>) -> usize {
+        self.windows.get(symbol).copied().unwrap_or(self.in_time)
+    }
+}
+
+/// How what a target creates of a symbol compares to its constraint, like `create at-most 5 pollution;`.
+/// Creating more than needed is fine by default, but for some symbols any surplus is undesirable.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[default]
+    AtLeast,
+    AtMost,
+    Exactly,
+}
+
+impl Comparison {
+    /// The operator comparing what's created to the constraint with, in the model
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Comparison::AtLeast => ">=",
+            Comparison::AtMost => "<=",
+            Comparison::Exactly => "=",
+        }
+    }
+}
+
+/// How the constraints on each symbol compare, and the time they're met in, while parsing a target.
+/// Constraints on the same symbol are added up, so they have to agree on both.
+#[derive(Debug, Default)]
+pub struct ConstraintKinds<'s> {
+    comparisons: HashMap<Symbol<'s>, Comparison>,
+    windows: HashMap<Symbol<'s>, Option<usize>>,
+}
+
+impl<'s> ConstraintKinds<'s> {
+    /// The kinds of the constraints of a target, and of all its groups
+    pub fn of(target: &Target<'s>) -> Self {
+        let mut res = Self::default();
+        let symbols = target.constraints.keys().chain(target.groups.iter().flat_map(|g| g.constraints.keys()));
+        for &symbol in symbols {
+            res.comparisons.insert(symbol, target.comparison(&symbol));
+            res.windows.insert(symbol, target.windows.get(&symbol).copied());
+        }
+        res
+    }
+
+    /// Record the kind of a constraint on some symbols, where no comparison or window means the default.
+    /// Returns a symbol whose other constraints are of another kind, and how they differ.
+    pub fn add(
+        &mut self,
+        comparison: Option<Comparison>,
+        window: Option<usize>,
+        symbols: impl IntoIterator<Item=Symbol<'s>>,
+    ) -> Result<(), (Symbol<'s>, &'static str)> {
+        let comparison = comparison.unwrap_or_default();
+        for symbol in symbols {
+            if *self.comparisons.entry(symbol).or_insert(comparison) != comparison {
+                return Err((symbol, "compare in different ways"));
+            }
+            if *self.windows.entry(symbol).or_insert(window) != window {
+                return Err((symbol, "are met in different times"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`ConstraintKinds::add`], for the constraints of a group
+    pub fn add_group(&mut self, group: &ConstraintGroup<'s>) -> Result<(), (Symbol<'s>, &'static str)> {
+        for &symbol in group.constraints.keys() {
+            self.add(group.comparisons.get(&symbol).copied(), group.windows.get(&symbol).copied(), [symbol])?;
+        }
+
+        Ok(())
+    }
+
+    /// The comparisons and windows that aren't the default, for [`Target::comparisons`] and [`Target::windows`]
+    pub fn finish(self) -> (HashMap<Symbol<'s>, Comparison>, HashMap<Symbol<'s>, usize>) {
+        (
+            self.comparisons.into_iter().filter(|&(_, comparison)| comparison != Comparison::AtLeast).collect(),
+            self.windows.into_iter().filter_map(|(symbol, window)| Some((symbol, window?))).collect(),
+        )
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Comparison::AtLeast => "at-least",
+            Comparison::AtMost => "at-most",
+            Comparison::Exactly => "exactly",
+        })
+    }
+}
+
+/// Values for `${NAME}` placeholders, defined on the command line or in a manifest,
+/// or otherwise taken from the environment
+#[derive(Debug, Default)]
+pub struct Constants {
+    pub defined: HashMap<String, f64>,
+    /// Whether constants that aren't defined are taken from environment variables
+    environment: bool,
+    /// The `if defined(NAME) { ... }` blocks being parsed, by whether their condition holds,
+    /// and whether their items are kept (when the blocks around them are kept too)
+    blocks: RefCell<Vec<(bool, bool)>>,
+    /// The condition of the last block parsed, for its `else` block
+    last_condition: Cell<bool>,
+}
+
+impl Constants {
+    pub fn new(defined: HashMap<String, f64>) -> Self {
+        Self {
+            defined,
+            environment: true,
+            blocks: RefCell::new(Vec::new()),
+            last_condition: Cell::new(false),
+        }
+    }
+
+    /// Only the constants defined, never taken from the environment,
+    /// so what's parsed with them doesn't depend on where it's parsed
+    pub fn only(defined: HashMap<String, f64>) -> Self {
+        Self { environment: false, ..Self::new(defined) }
+    }
+
+    /// Whether a constant is set, with `--define` or as an environment variable
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.get(name).is_ok()
+    }
+
+    /// Start parsing a conditional block, returning whether its items are kept
+    pub fn enter_block(&self, condition: bool) -> bool {
+        let kept = condition && !self.skipping();
+        self.blocks.borrow_mut().push((condition, kept));
+        kept
+    }
+
+    /// Start parsing the `else` block of the block parsed last, returning whether its items are kept
+    pub fn enter_else(&self) -> bool {
+        self.enter_block(!self.last_condition.get())
+    }
+
+    pub fn leave_block(&self) {
+        if let Some((condition, _)) = self.blocks.borrow_mut().pop() {
+            self.last_condition.set(condition);
+        }
+    }
+
+    /// Whether the items being parsed are left out, so the constants in them don't have to be defined
+    pub fn skipping(&self) -> bool {
+        self.blocks.borrow().last().is_some_and(|&(_, kept)| !kept)
+    }
+
+    pub fn get(&self, name: &str) -> Result<f64, String> {
+        if let Some(&value) = self.defined.get(name) {
+            return Ok(value);
+        }
+        if !self.environment {
+            return Err(format!("'{name}' is not defined"));
+        }
+
+        match std::env::var(name) {
+            Ok(value) => value.trim().parse().map_err(|_| {
+                format!("environment variable '{name}' is '{value}', which is not a number")
+            }),
+            Err(_) => Err(format!("'{name}' is not defined, set it with --define {name}=... or as an environment variable")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Program<'s> {
+    #[serde(borrow)]
     pub targets: HashMap<&'s str, Target<'s>>,
+    #[serde(borrow)]
     pub reactions: Vec<Reaction<'s>>,
+    /// Cost of transporting one unit of a symbol
+    #[serde(borrow)]
+    pub transport: HashMap<Symbol<'s>, f64>,
+    #[serde(borrow)]
+    pub zones: Vec<Zone<'s>>,
+    /// Symbols declared as raw materials, even if some reaction produces them.
+    /// They are inputs of every target. Once any are declared, every symbol
+    /// that reactions use but none produces has to be.
+    #[serde(borrow)]
+    pub raw: HashSet<Symbol<'s>>,
+    #[serde(borrow)]
+    pub suites: HashMap<&'s str, Suite<'s>>,
+    #[serde(borrow)]
+    pub techs: Vec<Tech<'s>>,
+    /// How many of each element a unit of a symbol is made of.
+    /// Reactions using only symbols with a composition should conserve elements.
+    #[serde(borrow)]
+    pub compositions: HashMap<Symbol<'s>, HashMap<&'s str, usize>>,
+    #[serde(borrow)]
+    pub symbols: HashMap<Symbol<'s>, SymbolInfo<'s>>,
 }
 
-#[derive(Debug)]
+impl<'s> Program<'s> {
+    /// Combine the items of (possibly several) files into a program,
+    /// or an error with the span it applies to
+    pub fn from_items(items: impl IntoIterator<Item=Spanned<Item<'s>>>) -> Result<Self, (usize, String, usize)> {
+        let mut targets = HashMap::new();
+        let mut reactions = Vec::new();
+        let mut transport = HashMap::new();
+        let mut zones: Vec<Zone> = Vec::new();
+        let mut raw = HashSet::new();
+        let mut suites = HashMap::new();
+        let mut aliases = HashMap::new();
+        let mut techs: Vec<Tech> = Vec::new();
+        let mut compositions = HashMap::new();
+        let mut symbols = HashMap::new();
+
+        for (l, i, r) in items {
+            match i {
+                Item::Target(t) => {
+                    targets.insert(t.name, t);
+                }
+                Item::Reaction(i) => reactions.push(i),
+                Item::Transport((symbol, cost)) => {
+                    if transport.insert(symbol, cost).is_some() {
+                        return Err((l, format!("duplicate transport cost for {}", symbol.0), r));
+                    }
+                }
+                Item::Zone((zone, zone_reactions)) => {
+                    if zones.iter().any(|z| z.name == zone.name) {
+                        return Err((zone.span.0, format!("duplicate zone {}", zone.name), zone.span.1));
+                    }
+                    zones.push(zone);
+                    reactions.extend(zone_reactions);
+                }
+                Item::Raw(symbols) => raw.extend(symbols),
+                Item::Suite(suite) => {
+                    let span = suite.span;
+                    if let Some(suite) = suites.insert(suite.name, suite) {
+                        return Err((span.0, format!("duplicate suite {}", suite.name), span.1));
+                    }
+                }
+                Item::Tech(tech) => {
+                    if techs.iter().any(|t| t.name == tech.name) {
+                        return Err((tech.span.0, format!("duplicate tech {}", tech.name), tech.span.1));
+                    }
+                    techs.push(tech);
+                }
+                Item::Element((symbol, elements)) => {
+                    if compositions.insert(symbol, elements).is_some() {
+                        return Err((l, format!("duplicate elements of {}", symbol.0), r));
+                    }
+                }
+                Item::Symbol((symbol, info)) => {
+                    if symbols.insert(symbol, info).is_some() {
+                        return Err((l, format!("duplicate symbol {}", symbol.0), r));
+                    }
+                }
+                Item::Alias((alias, symbol)) => {
+                    if aliases.insert(alias, (symbol, (l, r))).is_some() {
+                        return Err((l, format!("duplicate alias {}", alias.0), r));
+                    }
+                }
+            }
+        }
+
+        let unknown_tech = |name: &str| !techs.iter().any(|t| t.name == name);
+        for reaction in &reactions {
+            if let Some(name) = reaction.requires.iter().find(|name| unknown_tech(name)) {
+                return Err((reaction.span.0, format!("unknown tech {name}"), reaction.span.1));
+            }
+        }
+        for tech in &techs {
+            if let Some(name) = tech.requires.iter().find(|name| unknown_tech(name)) {
+                return Err((tech.span.0, format!("unknown tech {name}"), tech.span.1));
+            }
+        }
+
+        let mut program = Program {
+            reactions,
+            targets,
+            transport,
+            zones,
+            raw,
+            suites,
+            techs,
+            compositions,
+            symbols,
+        };
+        program.resolve_aliases(&aliases)?;
+        for target in program.targets.values().sorted_by_key(|target| target.span) {
+            target.check_sums().map_err(|error| (target.span.0, error, target.span.1))?;
+        }
+
+        Ok(program)
+    }
+
+    /// The name to show a symbol by in the output, its display name if it has one
+    pub fn display_name(&self, symbol: Symbol<'s>) -> &str {
+        self.symbols.get(&symbol).and_then(|info| info.display.as_deref()).unwrap_or(symbol.0)
+    }
+
+    /// Replace every alias by the symbol it (eventually) stands for
+    fn resolve_aliases(&mut self, aliases: &HashMap<Symbol<'s>, (Symbol<'s>, (usize, usize))>) -> Result<(), (usize, String, usize)> {
+        let mut resolved = HashMap::new();
+        for (&alias, &(_, span)) in aliases.iter().sorted_by_key(|(_, (_, span))| *span) {
+            let mut chain = vec![alias];
+            let mut symbol = alias;
+            while let Some(&(next, _)) = aliases.get(&symbol) {
+                if chain.contains(&next) {
+                    chain.push(next);
+                    return Err((span.0, format!("cyclic alias: {}", chain.iter().map(|s| s.0).join(" = ")), span.1));
+                }
+                chain.push(next);
+                symbol = next;
+            }
+            resolved.insert(alias, symbol);
+        }
+        if resolved.is_empty() {
+            return Ok(());
+        }
+
+        let symbol = |s: Symbol<'s>| resolved.get(&s).copied().unwrap_or(s);
+        // aliases of the same symbol in one set of terms are merged
+        let terms = |terms: &mut ReactionTerms<'s>, (l, r): (usize, usize)| {
+            let merged = std::mem::take(terms)
+                .into_iter()
+                .map(|(s, amount)| HashMap::from([(symbol(s), amount)]));
+            *terms = sum_terms(merged).map_err(|s| (l, sum_error(s), r))?;
+            Ok::<_, (usize, String, usize)>(())
+        };
+
+        for reaction in &mut self.reactions {
+            terms(&mut reaction.inputs, reaction.span)?;
+            terms(&mut reaction.outputs, reaction.span)?;
+            for branch in &mut reaction.branches {
+                terms(&mut branch.outputs, reaction.span)?;
+            }
+        }
+        for target in self.targets.values_mut() {
+            target.inputs.iter_mut().for_each(|s| *s = symbol(*s));
+            target.input_spans = std::mem::take(&mut target.input_spans).into_iter().map(|(s, span)| (symbol(s), span)).collect();
+            target.constraint_spans = std::mem::take(&mut target.constraint_spans).into_iter().map(|(s, span)| (symbol(s), span)).collect();
+            target.goal_spans = std::mem::take(&mut target.goal_spans).into_iter().map(|(s, span)| (symbol(s), span)).collect();
+            target.comparisons = std::mem::take(&mut target.comparisons).into_iter().map(|(s, c)| (symbol(s), c)).collect();
+            target.windows = std::mem::take(&mut target.windows).into_iter().map(|(s, w)| (symbol(s), w)).collect();
+            terms(&mut target.constraints, target.span)?;
+            for group in &mut target.groups {
+                terms(&mut group.constraints, target.span)?;
+            }
+            for preference in &mut target.preferences {
+                terms(&mut preference.terms, target.span)?;
+            }
+            if let Some(Goal::Resources(rt)) = &mut target.goal {
+                terms(rt, target.span)?;
+            }
+            for variant in &mut target.variants {
+                variant.goal_spans = std::mem::take(&mut variant.goal_spans).into_iter().map(|(s, span)| (symbol(s), span)).collect();
+                if let Goal::Resources(rt) = &mut variant.goal {
+                    terms(rt, variant.span)?;
+                }
+            }
+            for quantum in &mut target.quanta {
+                if let QuantumSubject::Symbol(s) = &mut quantum.subject {
+                    *s = symbol(*s);
+                }
+            }
+            for constraint in &mut target.linear_constraints {
+                for (_, r) in constraint.lhs.iter_mut().chain(&mut constraint.rhs) {
+                    if let Quantity::Net(s, _) = r {
+                        *s = symbol(*s);
+                    }
+                }
+            }
+        }
+        self.transport = std::mem::take(&mut self.transport).into_iter().map(|(s, cost)| (symbol(s), cost)).collect();
+        self.raw = std::mem::take(&mut self.raw).into_iter().map(symbol).collect();
+        self.compositions = std::mem::take(&mut self.compositions).into_iter().map(|(s, elements)| (symbol(s), elements)).collect();
+        self.symbols = std::mem::take(&mut self.symbols).into_iter().map(|(s, info)| (symbol(s), info)).collect();
+
+        Ok(())
+    }
+}
+
+impl Item<'_> {
+    /// Move all spans in this item, for items parsed from a file that
+    /// doesn't start at the beginning of the sources
+    pub fn shift_spans(&mut self, offset: usize) {
+        let shift = |span: &mut (usize, usize)| {
+            span.0 += offset;
+            span.1 += offset;
+        };
+
+        match self {
+            Item::Target(t) => {
+                shift(&mut t.span);
+                shift(&mut t.name_span);
+                t.quanta.iter_mut().for_each(|q| shift(&mut q.span));
+                t.input_spans.values_mut().for_each(shift);
+                t.constraint_spans.values_mut().for_each(shift);
+                t.goal_span.iter_mut().for_each(shift);
+                t.goal_spans.values_mut().for_each(shift);
+                for variant in &mut t.variants {
+                    shift(&mut variant.span);
+                    variant.goal_spans.values_mut().for_each(shift);
+                }
+                for constraint in &mut t.linear_constraints {
+                    shift(&mut constraint.span);
+                    for (_, r) in constraint.lhs.iter_mut().chain(&mut constraint.rhs) {
+                        if let Quantity::Of(_, span) | Quantity::Net(_, span) = r {
+                            shift(span);
+                        }
+                    }
+                }
+            }
+            Item::Reaction(r) => shift(&mut r.span),
+            Item::Zone((zone, reactions)) => {
+                shift(&mut zone.span);
+                reactions.iter_mut().for_each(|r| shift(&mut r.span));
+            }
+            Item::Suite(suite) => {
+                shift(&mut suite.span);
+                suite.targets.iter_mut().for_each(|(_, span)| shift(span));
+            }
+            Item::Tech(tech) => shift(&mut tech.span),
+            Item::Transport(_) | Item::Raw(_) | Item::Alias(_) | Item::Element(_) | Item::Symbol(_) => {}
+        }
+    }
+}
+
+/// A site that reactions can be placed in.
+/// Symbols have to be balanced per zone, or transferred between zones.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Zone<'s> {
+    pub name: &'s str,
+    /// The maximum number of machines in this zone
+    pub capacity: Option<f64>,
+    pub span: (usize, usize),
+}
+
+/// One possible outcome of a reaction, happening with some probability
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Branch<'s> {
+    pub probability: f64,
+    #[serde(borrow)]
+    pub outputs: ReactionTerms<'s>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Reaction<'s> {
+    #[serde(borrow)]
     pub inputs: ReactionTerms<'s>,
+    #[serde(borrow)]
     pub outputs: ReactionTerms<'s>,
-    pub cost: Cost,
-    pub label: Option<Cow<'s, str>>
+    /// Alternative outputs of which one happens, when the reaction's outcome is random
+    #[serde(borrow)]
+    pub branches: Vec<Branch<'s>>,
+    #[serde(borrow)]
+    pub cost: Cost<'s>,
+    #[serde(borrow)]
+    pub label: Option<Cow<'s, str>>,
+    /// The zone this reaction is placed in, if any
+    #[serde(borrow)]
+    pub zone: Option<&'s str>,
+    /// Added to the objective once if this reaction is used at all
+    pub fixed_cost: Option<f64>,
+    /// If used at all, this reaction needs at least this many machines
+    pub min_batch: Option<f64>,
+    /// The number of machines available for this reaction, used when minimizing time
+    pub machines: Option<f64>,
+    /// Breakpoints `(machines, cost)` of a piecewise-linear cost added to the objective.
+    /// The number of machines is limited to the range of the curve.
+    pub cost_curve: Option<Vec<(f64, f64)>>,
+    /// Free-form tags to group reactions by in the output
+    #[serde(borrow)]
+    pub tags: Vec<Cow<'s, str>>,
+    /// The type of machine performing this reaction
+    #[serde(borrow)]
+    pub machine: Option<Cow<'s, str>>,
+    /// Why this reaction shouldn't be used anymore, warned about when a solution does
+    #[serde(borrow)]
+    pub deprecated: Option<Cow<'s, str>>,
+    /// The feature this reaction needs, it can only be used by targets enabling it
+    #[serde(borrow)]
+    pub feature: Option<&'s str>,
+    /// Techs to unlock before this reaction can be used
+    #[serde(borrow)]
+    pub requires: Vec<&'s str>,
+    /// The heat one reaction absorbs in kJ, negative when it releases heat
+    pub enthalpy: Option<f64>,
+    pub span: (usize, usize),
 }
 
 impl<'s> Reaction<'s> {
@@ -60,12 +926,39 @@ impl<'s> Reaction<'s> {
             "machine_{}_into_{}",
             self.inputs
                 .iter()
-                .map(|(symbol, scalar)| format!("{scalar}{}", symbol.0.replace("-", "_")))
+                .sorted_by_key(|(symbol, _)| symbol.0)
+                .map(|(symbol, scalar)| format!("{scalar}{}", symbol.mzn_name()))
                 .format("_"),
-            self.outputs
-                .iter()
-                .map(|(symbol, scalar)| format!("{scalar}{}", symbol.0.replace("-", "_")))
-                .format("_")
+            std::iter::once(&self.outputs)
+                .chain(self.branches.iter().map(|b| &b.outputs))
+                .filter(|outputs| !outputs.is_empty())
+                .map(|outputs| outputs
+                    .iter()
+                    .sorted_by_key(|(symbol, _)| symbol.0)
+                    .map(|(symbol, scalar)| format!("{scalar}{}", symbol.mzn_name()))
+                    .format("_"))
+                .format("_or_")
         )
     }
+
+    /// How much of each symbol one reaction produces. With `strict`, branches count
+    /// with the least they can produce, otherwise with the amount they produce on average.
+    pub fn effective_outputs(&self, strict: bool) -> HashMap<Symbol<'s>, f64> {
+        let mut res: HashMap<Symbol, f64> = self.outputs.iter().map(|(&s, &i)| (s, i as f64)).collect();
+
+        let branch_symbols = self.branches.iter().flat_map(|b| b.outputs.keys()).unique();
+        for &symbol in branch_symbols {
+            let amounts = self.branches.iter().map(|b| b.outputs.get(&symbol).copied().unwrap_or(0) as f64);
+            let amount = if strict {
+                amounts.fold(f64::INFINITY, f64::min)
+            } else {
+                amounts.zip(&self.branches).map(|(i, b)| i * b.probability).sum()
+            };
+
+            *res.entry(symbol).or_insert(0.0) += amount;
+        }
+
+        res.retain(|_, amount| *amount > 0.0);
+        res
+    }
 }
@@ -0,0 +1,175 @@
+//! The solvers models can be solved with. Integrations with solvers, like proprietary ones,
+//! implement [`Backend`] and are added with [`register`] when the program starts,
+//! after which `--backend` picks them by name. They can live in crates of their own,
+//! using this one as a library.
+
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use itertools::Itertools;
+use crate::ast::Target;
+use crate::codegen::{maximizes, Invalid, Options};
+use crate::lp::{LinearProgram, Unsupported};
+use crate::minizinc::{find_minizinc, minizinc_command, model_path, run_minizinc, run_portfolio, solver_command, write_model, Outcome};
+use crate::model::Model;
+use crate::options::SolveOptions;
+use crate::sources::Sources;
+use crate::stamp::version_of;
+use crate::workdir::work_dir;
+
+pub trait Backend: Sync {
+    /// What `--backend` picks it by
+    fn name(&self) -> &str;
+
+    /// Solve a target of the model. The outcome has the values of the same variables
+    /// minizinc would output, like the machines of each reaction and the objective.
+    /// A target without a solution isn't an error, but an outcome with that status.
+    fn solve(&self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<Outcome, Error>;
+
+    /// The version of the solver, which stamps record. Only asked for once per run
+    fn version(&self) -> String;
+
+    /// Write what would be solved for `--dry-run` in the work directory, without solving it
+    fn dry_run(&self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<DryRun, Error>;
+
+    /// For solving again and again after small changes, like the debugger does. Backends keeping
+    /// the model in memory return a session changing only what changed since the last solve,
+    /// the others solve from scratch every time.
+    fn session(&self) -> Option<Box<dyn Session>> {
+        None
+    }
+}
+
+/// Solving the targets of models that change a little between solves, see [`Backend::session`]
+pub trait Session {
+    fn solve(&mut self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<Outcome, Error>;
+}
+
+/// What a backend wrote for `--dry-run`, and the commands to solve it yourself
+pub struct DryRun {
+    pub model: PathBuf,
+    pub commands: Vec<Command>,
+}
+
+/// Generating a minizinc model, and solving it with `--portfolio` or the default solver
+pub struct MiniZinc;
+
+impl Backend for MiniZinc {
+    fn name(&self) -> &str {
+        "minizinc"
+    }
+
+    fn solve(&self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<Outcome, Error> {
+        let source_map = write_model(sources, model, target, options)?;
+        match args.portfolio.is_empty() {
+            true => run_minizinc(&mut minizinc_command()?, &source_map),
+            false => {
+                let maximize = target.goal.as_ref().is_some_and(|goal| maximizes(goal, options));
+                run_portfolio(&args.portfolio, maximize, &source_map)
+            }
+        }
+    }
+
+    fn version(&self) -> String {
+        static VERSION: OnceLock<String> = OnceLock::new();
+        VERSION.get_or_init(|| version_of(&find_minizinc())).clone()
+    }
+
+    fn dry_run(&self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<DryRun, Error> {
+        write_model(sources, model, target, options)?;
+        let commands = match args.portfolio.is_empty() {
+            true => vec![minizinc_command()?],
+            false => args.portfolio.iter().map(|solver| solver_command(solver, args.portfolio.len())).try_collect()?,
+        };
+        Ok(DryRun { model: model_path()?, commands })
+    }
+}
+
+/// Why a backend couldn't solve a target
+#[derive(Debug)]
+pub enum Error {
+    /// No backend is registered with the name
+    NoBackend {
+        name: String,
+        registered: Vec<String>,
+    },
+    /// No model can be generated for the target
+    Invalid(Invalid),
+    /// The backend can't solve part of the target
+    Unsupported(Unsupported),
+    /// The solver rejected the model, because of what it was generated for in the chem file
+    Rejected {
+        message: String,
+        /// What the solver said about the span
+        label: String,
+        span: (usize, usize),
+        /// What the solver printed, if it's more than the label
+        output: Option<String>,
+    },
+    /// Writing the model or running the solver failed
+    Failed {
+        message: String,
+        /// What the solver printed
+        output: Option<String>,
+        help: Option<String>,
+    },
+}
+
+impl Error {
+    /// A [`Error::Failed`] without output or help
+    pub fn failed(message: String) -> Self {
+        Self::Failed { message, output: None, help: None }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoBackend { name, .. } => write!(f, "no backend '{name}'"),
+            Error::Invalid(e) => write!(f, "{}", e.message),
+            Error::Unsupported(e) => write!(f, "the backend doesn't support {}", e.what),
+            Error::Rejected { message, label, .. } => write!(f, "{message}: {label}"),
+            Error::Failed { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+static REGISTERED: Mutex<Vec<&'static dyn Backend>> = Mutex::new(Vec::new());
+
+/// Add a backend, which replaces an earlier registered one with the same name
+pub fn register(backend: &'static dyn Backend) {
+    let mut registered = REGISTERED.lock().expect("not poisoned");
+    registered.retain(|b| b.name() != backend.name());
+    registered.push(backend);
+}
+
+/// The backend with a name
+pub fn backend(name: &str) -> Result<&'static dyn Backend, Error> {
+    let backends = REGISTERED.lock().expect("not poisoned").clone();
+    match backends.iter().find(|b| b.name() == name) {
+        Some(&backend) => Ok(backend),
+        None => Err(Error::NoBackend {
+            name: name.to_string(),
+            registered: backends.iter().map(|b| b.name().to_string()).sorted().collect(),
+        }),
+    }
+}
+
+/// Write a linear program in MPS format to `model.mps` in the work directory, for backends
+/// solving it with another program
+pub fn write_mps(lp: &LinearProgram) -> Result<PathBuf, Error> {
+    let write = || -> io::Result<PathBuf> {
+        let path = work_dir()?.join("model.mps");
+        let mut f = BufWriter::new(File::create(&path)?);
+        lp.write_mps(&mut f)?;
+        f.flush()?;
+        Ok(path)
+    };
+
+    write().map_err(|e| Error::failed(format!("while writing the model in MPS format: {e}")))
+}
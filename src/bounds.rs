@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::ast::{Comparison, Goal, Target};
+use crate::codegen::Options;
+use crate::model::Model;
+
+/// How often [`propagate`] looks at a symbol at most. Bounds only get tighter, but around a cycle
+/// they could keep doing so a little, so this keeps propagating linear in the size of the model.
+const MAX_VISITS: usize = 8;
+
+/// Upper bounds on the machines of each reaction, where the model implies one.
+/// Declaring variables with a range makes minizinc flatten the model faster,
+/// and gives MIP solvers tighter relaxations to branch on.
+///
+/// Only bounds that follow from the model's own constraints are derived, so they never cut off solutions:
+/// - the capacity of the zone the reaction is in
+/// - a target's budget of a cost, divided by what the reaction costs,
+///   if no reaction has a negative amount of that cost to make up for it
+/// - a target creating at most or exactly some amount of a symbol no reaction uses,
+///   divided by how much of it the reaction makes
+/// - for reactions using a symbol that isn't an input, what the bounded reactions making it
+///   can make at most, divided by how much of it the reaction uses
+/// - the machines available to the reaction, when minimizing the time
+/// - the end of its cost curve
+/// - `--big-m`, when the model is bounded by it
+///
+/// When constraints may be violated (see [`Options::elastic`]), only the last two hold.
+pub fn upper_bounds(model: &Model, target: &Target, options: &Options) -> Vec<Option<f64>> {
+    let program = model.program();
+    let limits = created_limits(model, target);
+    let mut bounds = program.reactions
+        .iter()
+        .enumerate()
+        .map(|(idx, reaction)| {
+            let mut bounds = Vec::new();
+
+            if !options.elastic {
+                let zone = program.zones.iter().find(|zone| Some(zone.name) == reaction.zone);
+                bounds.extend(zone.and_then(|zone| zone.capacity));
+
+                for (name, budget) in &target.budgets {
+                    let Some(&cost) = reaction.cost.other.get(name).filter(|&&cost| cost > 0.0) else {
+                        continue;
+                    };
+                    if program.reactions.iter().all(|r| r.cost.other.get(name).copied().unwrap_or(0.0) >= 0.0) {
+                        bounds.push(budget / cost);
+                    }
+                }
+
+                if let Some(Goal::Time) = target.goal {
+                    bounds.extend(reaction.machines);
+                }
+
+                bounds.extend(limits.get(&idx));
+            }
+
+            let curve_end = reaction.cost_curve.as_ref().and_then(|curve| curve.iter().map(|&(machines, _)| machines).reduce(f64::max));
+            bounds.extend(curve_end);
+            if options.bounded {
+                bounds.push(options.big_m);
+            }
+
+            // a negative bound would make the model infeasible, which the constraints report better
+            bounds.into_iter().filter(|bound| bound.is_finite() && *bound >= 0.0).reduce(f64::min)
+        })
+        .collect::<Vec<_>>();
+
+    if !options.elastic {
+        propagate(model, target, &mut bounds);
+    }
+    bounds
+}
+
+/// The machines of reactions making a symbol that no reaction uses, when the target
+/// creates at most or exactly some amount of it, by reaction
+fn created_limits(model: &Model, target: &Target) -> HashMap<usize, f64> {
+    let mut res = HashMap::new();
+    // when minimizing the time, the rate to create is a variable
+    if let Some(Goal::Time) = target.goal {
+        return res;
+    }
+
+    for (symbol, scalar) in target.active_constraints() {
+        let Some(id) = model.id(&symbol) else {
+            continue;
+        };
+        if target.comparison(&symbol) == Comparison::AtLeast || !model.consumers(id).is_empty() || !model.flows(id).is_empty() {
+            continue;
+        }
+
+        let rate = scalar as f64 / target.window(&symbol) as f64;
+        for u in model.producers(id).iter().filter(|u| u.rate > 0.0) {
+            let bound = rate / u.rate;
+            res.entry(u.reaction).and_modify(|b: &mut f64| *b = b.min(bound)).or_insert(bound);
+        }
+    }
+    res
+}
+
+/// Bounds the reactions using a symbol that has to be made, by what the reactions making it
+/// can make at most, if they're all bounded
+fn propagate(model: &Model, target: &Target, bounds: &mut [Option<f64>]) {
+    // symbols that don't have to be made, like in the balance constraints of the model
+    let resources = match &target.goal {
+        Some(Goal::Resources(rt)) => rt.keys().collect(),
+        _ => Vec::new(),
+    };
+    let inputs: HashSet<_> = target.inputs.iter().chain(&model.program().raw).chain(resources).collect();
+    let made_here = model.symbols().map(|id| !inputs.contains(&model.symbol(id))).collect::<Vec<_>>();
+
+    // the symbols each reaction makes, which are looked at again when its bound gets tighter
+    let mut makes = vec![Vec::new(); bounds.len()];
+    for id in model.symbols() {
+        for u in model.producers(id) {
+            makes[u.reaction].push(id);
+        }
+    }
+
+    let mut queue = model.symbols().filter(|id| made_here[id.0 as usize]).collect::<VecDeque<_>>();
+    let mut queued = made_here.clone();
+    let mut visits = vec![0; made_here.len()];
+    while let Some(id) = queue.pop_front() {
+        queued[id.0 as usize] = false;
+        visits[id.0 as usize] += 1;
+
+        let Some(made) = model.producers(id).iter().map(|u| Some(bounds[u.reaction]? * u.rate)).sum::<Option<f64>>() else {
+            continue;
+        };
+        for u in model.consumers(id) {
+            let bound = made / u.rate;
+            if !bound.is_finite() || bounds[u.reaction].is_some_and(|b| bound >= b) {
+                continue;
+            }
+            bounds[u.reaction] = Some(bound);
+            for &next in &makes[u.reaction] {
+                let next_idx = next.0 as usize;
+                if made_here[next_idx] && !queued[next_idx] && visits[next_idx] < MAX_VISITS {
+                    queued[next_idx] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Building programs in Rust, instead of parsing them from chem source.
+//!
+//! The builders own their strings, and the program they build borrows from them, like a program
+//! parsed from source borrows the source. They check what the grammar checks when parsing.
+//! As there's no source, the spans of the program, and of the diagnostics, are empty.
+//!
+//! ```
+//! use reaction_solver::builder::{ProgramBuilder, ReactionBuilder, TargetBuilder};
+//!
+//! let builder = ProgramBuilder::new()
+//!     .raw("iron-ore")
+//!     .reaction(ReactionBuilder::new(3).label("smelt iron").input("iron-ore", 1).output("iron-plate", 1))
+//!     .target(TargetBuilder::new("plates").create("iron-plate", 10).minimize_reactions());
+//! let program = builder.build().unwrap();
+//! assert_eq!(program.reactions.len(), 1);
+//! ```
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use crate::ast::{sum_error, sum_terms, Comparison, ConstraintKinds, Cost, Goal, Item, Program, Reaction, ReactionTerms, Symbol, Target, Zone};
+use crate::Diagnostic;
+
+/// Builds a [`Program`] from reactions, targets, zones and raw materials
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    reactions: Vec<ReactionBuilder>,
+    targets: Vec<TargetBuilder>,
+    zones: Vec<(String, Option<f64>)>,
+    raw: Vec<String>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reaction(mut self, reaction: ReactionBuilder) -> Self {
+        self.reactions.push(reaction);
+        self
+    }
+
+    pub fn target(mut self, target: TargetBuilder) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    /// Add a zone reactions can be placed in (see [`ReactionBuilder::zone`]),
+    /// with at most `capacity` machines if given
+    pub fn zone(mut self, name: impl Into<String>, capacity: Option<f64>) -> Self {
+        self.zones.push((name.into(), capacity));
+        self
+    }
+
+    /// Declare a symbol a raw material, like `raw iron-ore;`
+    pub fn raw(mut self, symbol: impl Into<String>) -> Self {
+        self.raw.push(symbol.into());
+        self
+    }
+
+    /// The program, or everything that's wrong with it
+    pub fn build(&self) -> Result<Program<'_>, Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+        let mut items = Vec::new();
+
+        let mut zoned: HashMap<&str, Vec<Reaction>> = HashMap::new();
+        for reaction in &self.reactions {
+            match reaction.build() {
+                Ok(built) => match built.zone {
+                    Some(zone) if !self.zones.iter().any(|(name, _)| name == zone) => {
+                        diagnostics.push(error(format!("reaction {} is in zone {zone}, which doesn't exist", reaction.name())));
+                    }
+                    Some(zone) => zoned.entry(zone).or_default().push(built),
+                    None => items.push(Item::Reaction(built)),
+                },
+                Err(message) => diagnostics.push(error(message)),
+            }
+        }
+        for (name, capacity) in &self.zones {
+            let zone = Zone { name, capacity: *capacity, span: (0, 0) };
+            items.push(Item::Zone((zone, zoned.remove(name.as_str()).unwrap_or_default())));
+        }
+        if !self.raw.is_empty() {
+            items.push(Item::Raw(self.raw.iter().map(|symbol| Symbol(symbol)).collect()));
+        }
+        for target in &self.targets {
+            match target.build() {
+                Ok(built) => items.push(Item::Target(built)),
+                Err(message) => diagnostics.push(error(message)),
+            }
+        }
+
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+        Program::from_items(items.into_iter().map(|item| (0, item, 0))).map_err(|(_, message, _)| vec![error(message)])
+    }
+}
+
+/// Builds a [`Reaction`], like `"smelt iron": iron-ore -(3)-> iron-plate machine furnace;`
+#[derive(Debug, Clone)]
+pub struct ReactionBuilder {
+    label: Option<String>,
+    inputs: Vec<(String, usize)>,
+    outputs: Vec<(String, usize)>,
+    time: usize,
+    costs: Vec<(String, f64)>,
+    zone: Option<String>,
+    fixed_cost: Option<f64>,
+    min_batch: Option<f64>,
+    machines: Option<f64>,
+    tags: Vec<String>,
+    machine: Option<String>,
+}
+
+impl ReactionBuilder {
+    /// A reaction taking `time` time units, which has to be positive
+    pub fn new(time: usize) -> Self {
+        Self {
+            label: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            time,
+            costs: Vec::new(),
+            zone: None,
+            fixed_cost: None,
+            min_batch: None,
+            machines: None,
+            tags: Vec::new(),
+            machine: None,
+        }
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Use `amount` of a symbol. Amounts of the same symbol are added up
+    pub fn input(mut self, symbol: impl Into<String>, amount: usize) -> Self {
+        self.inputs.push((symbol.into(), amount));
+        self
+    }
+
+    /// Produce `amount` of a symbol. Amounts of the same symbol are added up
+    pub fn output(mut self, symbol: impl Into<String>, amount: usize) -> Self {
+        self.outputs.push((symbol.into(), amount));
+        self
+    }
+
+    /// A named cost per machine per time unit, like `cost { power: 90 }`
+    pub fn cost(mut self, name: impl Into<String>, cost: f64) -> Self {
+        self.costs.push((name.into(), cost));
+        self
+    }
+
+    /// Place the reaction in a zone of the program (see [`ProgramBuilder::zone`])
+    pub fn zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    pub fn fixed_cost(mut self, cost: f64) -> Self {
+        self.fixed_cost = Some(cost);
+        self
+    }
+
+    pub fn min_batch(mut self, batch: f64) -> Self {
+        self.min_batch = Some(batch);
+        self
+    }
+
+    pub fn machines(mut self, machines: f64) -> Self {
+        self.machines = Some(machines);
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    pub fn machine(mut self, machine: impl Into<String>) -> Self {
+        self.machine = Some(machine.into());
+        self
+    }
+
+    /// What to call the reaction in diagnostics
+    fn name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("'{label}'"),
+            None => format!("{} -({})-> {}", describe(&self.inputs), self.time, describe(&self.outputs)),
+        }
+    }
+
+    fn build(&self) -> Result<Reaction<'_>, String> {
+        if self.time == 0 {
+            return Err(format!("reaction {} has to take a positive time, not 0", self.name()));
+        }
+        let mut other = HashMap::new();
+        for (name, cost) in &self.costs {
+            if other.insert(name.as_str(), *cost).is_some() {
+                return Err(format!("duplicate cost '{name}' of reaction {}", self.name()));
+            }
+        }
+        if let Some(tag) = self.tags.iter().enumerate().find_map(|(idx, tag)| self.tags[..idx].contains(tag).then_some(tag)) {
+            return Err(format!("duplicate tag '{tag}' of reaction {}", self.name()));
+        }
+        let in_reaction = |e| format!("in reaction {}, {e}", self.name());
+
+        Ok(Reaction {
+            inputs: sum_owned_terms(borrowed(&self.inputs)).map_err(in_reaction)?,
+            outputs: sum_owned_terms(borrowed(&self.outputs)).map_err(in_reaction)?,
+            branches: Vec::new(),
+            cost: Cost {
+                time: self.time,
+                other,
+            },
+            label: self.label.as_deref().map(Cow::Borrowed),
+            zone: self.zone.as_deref(),
+            fixed_cost: self.fixed_cost,
+            min_batch: self.min_batch,
+            machines: self.machines,
+            cost_curve: None,
+            tags: self.tags.iter().map(|tag| Cow::Borrowed(tag.as_str())).collect(),
+            machine: self.machine.as_deref().map(Cow::Borrowed),
+            deprecated: None,
+            feature: None,
+            requires: Vec::new(),
+            enthalpy: None,
+            span: (0, 0),
+        })
+    }
+}
+
+/// What a [`TargetBuilder`] minimizes
+#[derive(Debug, Clone)]
+enum GoalBuilder {
+    Resources(Vec<(String, usize)>),
+    Reactions,
+    Time,
+    Cost(String),
+}
+
+/// Builds a [`Target`], like `target plates { create 10 iron-plate; minimize reactions; }`
+#[derive(Debug, Clone)]
+pub struct TargetBuilder {
+    name: String,
+    inputs: Vec<String>,
+    constraints: Vec<(String, usize, Comparison, Option<usize>)>,
+    in_time: usize,
+    goal: Option<GoalBuilder>,
+    budgets: Vec<(String, f64)>,
+}
+
+impl TargetBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            inputs: Vec::new(),
+            constraints: Vec::new(),
+            in_time: 1,
+            goal: None,
+            budgets: Vec::new(),
+        }
+    }
+
+    /// Allow the target to use a symbol without producing it, like `using water;`
+    pub fn using(mut self, symbol: impl Into<String>) -> Self {
+        self.inputs.push(symbol.into());
+        self
+    }
+
+    /// Create at least `amount` of a symbol, like `create 10 iron-plate;`
+    pub fn create(self, symbol: impl Into<String>, amount: usize) -> Self {
+        self.create_with(symbol, amount, Comparison::AtLeast, None)
+    }
+
+    /// Create an amount of a symbol compared some way, in a time of its own if given,
+    /// like `create at-most 5 pollution in 60;`
+    pub fn create_with(mut self, symbol: impl Into<String>, amount: usize, comparison: Comparison, window: Option<usize>) -> Self {
+        self.constraints.push((symbol.into(), amount, comparison, window));
+        self
+    }
+
+    /// The time the constraints have to be met in, like `in 60;`
+    pub fn in_time(mut self, time: usize) -> Self {
+        self.in_time = time;
+        self
+    }
+
+    pub fn minimize_reactions(mut self) -> Self {
+        self.goal = Some(GoalBuilder::Reactions);
+        self
+    }
+
+    pub fn minimize_time(mut self) -> Self {
+        self.goal = Some(GoalBuilder::Time);
+        self
+    }
+
+    /// Minimize a total of the reactions' named costs, like `minimize cost power;`
+    pub fn minimize_cost(mut self, name: impl Into<String>) -> Self {
+        self.goal = Some(GoalBuilder::Cost(name.into()));
+        self
+    }
+
+    /// Minimize the resources used, weighted by `amount`, like `minimize resources 2 coal + ore;`
+    pub fn minimize_resources(mut self, resources: impl IntoIterator<Item = (impl Into<String>, usize)>) -> Self {
+        self.goal = Some(GoalBuilder::Resources(resources.into_iter().map(|(symbol, amount)| (symbol.into(), amount)).collect()));
+        self
+    }
+
+    /// An upper bound on the total of a named cost, like `budget power 500;`
+    pub fn budget(mut self, name: impl Into<String>, budget: f64) -> Self {
+        self.budgets.push((name.into(), budget));
+        self
+    }
+
+    fn build(&self) -> Result<Target<'_>, String> {
+        let name = self.name.as_str();
+        let mut kinds = ConstraintKinds::default();
+        for (symbol, _, comparison, window) in &self.constraints {
+            if let Err((symbol, difference)) = kinds.add(Some(*comparison), *window, [Symbol(symbol)]) {
+                return Err(format!("the constraints on {} in {name} {difference}", symbol.0));
+            }
+        }
+        let (comparisons, windows) = kinds.finish();
+        let constraints = self.constraints.iter().map(|(symbol, amount, ..)| (symbol.as_str(), *amount));
+
+        let mut budgets = HashMap::new();
+        for (cost, budget) in &self.budgets {
+            if budgets.insert(cost.as_str(), *budget).is_some() {
+                return Err(format!("duplicate budget for '{cost}' in {name}"));
+            }
+        }
+        let goal = match &self.goal {
+            Some(GoalBuilder::Resources(resources)) => {
+                Some(Goal::Resources(sum_owned_terms(borrowed(resources)).map_err(|e| format!("in {name}, {e}"))?))
+            }
+            Some(GoalBuilder::Reactions) => Some(Goal::Reactions),
+            Some(GoalBuilder::Time) => Some(Goal::Time),
+            Some(GoalBuilder::Cost(cost)) => Some(Goal::Cost(cost)),
+            None => None,
+        };
+
+        Ok(Target {
+            inputs: self.inputs.iter().map(|symbol| Symbol(symbol)).collect(),
+            constraints: sum_owned_terms(constraints).map_err(|e| format!("in {name}, {e}"))?,
+            in_time: self.in_time,
+            name,
+            goal,
+            groups: Vec::new(),
+            preferences: Vec::new(),
+            quanta: Vec::new(),
+            transport_weight: None,
+            transport_budget: None,
+            budgets,
+            features: Vec::new(),
+            linear_constraints: Vec::new(),
+            input_spans: HashMap::new(),
+            constraint_spans: HashMap::new(),
+            comparisons,
+            windows,
+            goal_span: None,
+            goal_spans: HashMap::new(),
+            variants: Vec::new(),
+            span: (0, 0),
+            name_span: (0, 0),
+        })
+    }
+}
+
+/// The terms of a builder, added up like terms parsed from source
+fn sum_owned_terms<'a>(terms: impl IntoIterator<Item = (&'a str, usize)>) -> Result<ReactionTerms<'a>, String> {
+    sum_terms(terms.into_iter().map(|(symbol, amount)| HashMap::from([(Symbol(symbol), amount)]))).map_err(sum_error)
+}
+
+/// The terms of a builder, with their symbols borrowed
+fn borrowed(terms: &[(String, usize)]) -> impl Iterator<Item = (&str, usize)> {
+    terms.iter().map(|(symbol, amount)| (symbol.as_str(), *amount))
+}
+
+/// Terms like they're written in source, for diagnostics
+fn describe(terms: &[(String, usize)]) -> String {
+    terms.iter().map(|(symbol, amount)| format!("{amount} {symbol}")).collect::<Vec<_>>().join(" + ")
+}
+
+fn error(message: String) -> Diagnostic {
+    Diagnostic {
+        message: "invalid program".to_string(),
+        label: message,
+        span: (0, 0),
+    }
+}
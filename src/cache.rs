@@ -0,0 +1,73 @@
+//! Programs parsed by earlier runs, kept with `--cache-dir` so runs on large chem files
+//! that didn't change since don't parse them again
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use itertools::Itertools;
+use crate::ast::{Constants, Program};
+use crate::sources::Sources;
+use crate::stamp::hash;
+
+/// Changed whenever how programs are cached changes, like when fields are added to them,
+/// so entries of other builds with the same version aren't read as something else
+const FORMAT_VERSION: u32 = 1;
+
+/// The program parsed from the sources with these constants before, if it's cached.
+/// An entry that can't be read back is removed, and taken as not being cached
+pub fn load<'s>(dir: &Path, sources: &'s Sources, constants: &Constants, strict: bool) -> Option<Program<'s>> {
+    let path = entry(dir, sources, constants, strict);
+    // the program borrows its strings from the bytes, so they're kept as long as the sources
+    let bytes = sources.keep(fs::read(&path).ok()?);
+    match bincode::deserialize(bytes) {
+        Ok(program) => Some(program),
+        Err(_) => {
+            let _ = fs::remove_file(&path);
+            None
+        }
+    }
+}
+
+/// Cache the program parsed from the sources with these constants.
+/// Not being able to only makes the next run slower, so it isn't reported
+pub fn store(dir: &Path, sources: &Sources, constants: &Constants, strict: bool, program: &Program) {
+    let Ok(bytes) = bincode::serialize(program) else {
+        return;
+    };
+
+    // written next to it first, so runs at the same time never read half an entry
+    let path = entry(dir, sources, constants, strict);
+    let partial = path.with_extension(format!("{}.partial", process::id()));
+    let _ = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&partial, bytes))
+        .and_then(|_| fs::rename(&partial, &path));
+}
+
+/// The file a program is cached in, named by a hash of everything parsing it depends on:
+/// the version of this program and of the cache format, the files, and the values of the constants they use.
+/// And `--strict`, as parsing with it fails on warnings that parsing without it only prints,
+/// so a program is only cached for strict runs when parsing it gave no warnings.
+fn entry(dir: &Path, sources: &Sources, constants: &Constants, strict: bool) -> PathBuf {
+    let mut key = format!("{} {FORMAT_VERSION} strict={strict}\n", env!("CARGO_PKG_VERSION"));
+    for (name, range, kind) in sources.files() {
+        let _ = writeln!(key, "{name} {range:?} {kind:?}");
+    }
+    for name in constant_names(sources.text()).sorted().dedup() {
+        let _ = writeln!(key, "{name}={:?}", constants.get(name).ok());
+    }
+    key.push_str(sources.text());
+
+    dir.join(format!("{}.bin", hash(&key)))
+}
+
+/// The names in `${NAME}` placeholders and `if defined(NAME)` blocks. Some may be in comments,
+/// which only means a change to them is taken for a change of the program
+fn constant_names(text: &str) -> impl Iterator<Item = &str> {
+    ["${", "defined("].into_iter().flat_map(move |start| {
+        text.match_indices(start).map(move |(idx, _)| {
+            let rest = &text[idx + start.len()..];
+            &rest[..rest.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(rest.len())]
+        })
+    })
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+use itertools::Itertools;
+use plotters::prelude::*;
+use crate::ast::Program;
+use crate::model::Model;
+use crate::solution::Solution;
+
+/// Render how many machines each reaction used in a solution needs as a horizontal bar chart,
+/// the reaction needing the most machines on top
+pub fn render_chart(path: &Path, program: &Program, solution: &Solution, strict_branches: bool) -> Result<(), String> {
+    let model = Model::new(program, strict_branches);
+    let rows = (0..program.reactions.len())
+        .filter_map(|idx| {
+            let machines = solution.get(model.var_name(idx)).filter(|&m| m > 0.0)?;
+            Some((model.name(idx), machines))
+        })
+        .sorted_by(|(_, a), (_, b)| a.total_cmp(b))
+        .collect_vec();
+
+    let max = rows.iter().map(|(_, machines)| *machines).fold(0.0, f64::max);
+    let longest = rows.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0) as u32;
+
+    let root = SVGBackend::new(path, (800, 80 + 30 * rows.len() as u32)).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| e.to_string())?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("machines per reaction", ("sans-serif", 20))
+        .margin(10)
+        .x_label_area_size(40)
+        // text isn't measured in svg, so this is a rough estimate
+        .y_label_area_size(20 + 7 * longest)
+        .build_cartesian_2d(0.0..(max * 1.1).max(1.0), (0..rows.len().max(1)).into_segmented())
+        .map_err(|e| e.to_string())?;
+
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .y_labels(rows.len())
+        .y_label_formatter(&|value| match value {
+            SegmentValue::CenterOf(row) => rows.get(*row).map_or(String::new(), |(name, _)| name.to_string()),
+            _ => String::new(),
+        })
+        .x_desc("machines")
+        .draw()
+        .map_err(|e| e.to_string())?;
+
+    chart
+        .draw_series(rows.iter().enumerate().map(|(row, (_, machines))| {
+            let mut bar = Rectangle::new(
+                [(0.0, SegmentValue::Exact(row)), (*machines, SegmentValue::Exact(row + 1))],
+                BLUE.mix(0.7).filled(),
+            );
+            bar.set_margin(4, 4, 0, 0);
+            bar
+        }))
+        .map_err(|e| e.to_string())?;
+
+    root.present().map_err(|e| e.to_string())
+}
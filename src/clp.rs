@@ -0,0 +1,272 @@
+//! Solving targets with coin-or CLP, linked into the binary, for machines where minizinc or
+//! or-tools can't be installed. Only built with the `clp` feature, which needs `libClp`
+//! (and the `libCoinUtils` it depends on) where the linker finds them.
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_double, c_int, c_void};
+use std::process::Command;
+use std::slice;
+use itertools::Itertools;
+use crate::ast::Target;
+use crate::backend::{write_mps, Backend, DryRun, Error, Session};
+use crate::codegen::Options;
+use crate::lp::LinearProgram;
+use crate::minizinc::{Outcome, Status};
+use crate::model::Model;
+use crate::options::SolveOptions;
+use crate::sources::Sources;
+
+/// `Clp_Simplex` of CLP's C interface
+type Simplex = c_void;
+
+#[link(name = "Clp")]
+extern "C" {
+    fn Clp_Version() -> *const c_char;
+    fn Clp_newModel() -> *mut Simplex;
+    fn Clp_deleteModel(model: *mut Simplex);
+    fn Clp_setLogLevel(model: *mut Simplex, value: c_int);
+    fn Clp_initialSolve(model: *mut Simplex) -> c_int;
+    fn Clp_dual(model: *mut Simplex, if_values_pass: c_int) -> c_int;
+    fn Clp_chgRowLower(model: *mut Simplex, row_lower: *const c_double);
+    fn Clp_chgRowUpper(model: *mut Simplex, row_upper: *const c_double);
+    fn Clp_chgColumnLower(model: *mut Simplex, column_lower: *const c_double);
+    fn Clp_chgColumnUpper(model: *mut Simplex, column_upper: *const c_double);
+    fn Clp_chgObjCoefficients(model: *mut Simplex, obj_in: *const c_double);
+    #[allow(clippy::too_many_arguments)]
+    fn Clp_addColumns(
+        model: *mut Simplex,
+        number: c_int,
+        column_lower: *const c_double,
+        column_upper: *const c_double,
+        objective: *const c_double,
+        column_starts: *const c_int,
+        rows: *const c_int,
+        elements: *const c_double,
+    );
+    fn Clp_addRows(
+        model: *mut Simplex,
+        number: c_int,
+        row_lower: *const c_double,
+        row_upper: *const c_double,
+        row_starts: *const c_int,
+        columns: *const c_int,
+        elements: *const c_double,
+    );
+    fn Clp_status(model: *mut Simplex) -> c_int;
+    fn Clp_getColSolution(model: *mut Simplex) -> *const c_double;
+}
+
+/// Solving the target as a linear program with CLP's simplex. CLP doesn't do integers,
+/// so targets with integer variables, like with `--integer` or fixed costs, can't be solved with it.
+pub struct Clp;
+
+impl Backend for Clp {
+    fn name(&self) -> &str {
+        "clp"
+    }
+
+    fn solve(&self, sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Result<Outcome, Error> {
+        ClpSession::default().solve(sources, model, target, options, args)
+    }
+
+    fn version(&self) -> String {
+        // SAFETY: CLP returns a static, nul-terminated string
+        let version = unsafe { CStr::from_ptr(Clp_Version()) };
+        format!("CLP {}", version.to_string_lossy())
+    }
+
+    /// Writes the linear program in MPS format, which the standalone `clp` solves the same way
+    fn dry_run(&self, _sources: &Sources, model: &Model, target: &Target, options: &Options, _args: &SolveOptions) -> Result<DryRun, Error> {
+        let mps = write_mps(&linear_program(model, target, options)?)?;
+        let mut cmd = Command::new("clp");
+        cmd.arg(&mps).args(["-solve", "-solution"]).arg(mps.with_file_name("solution.txt"));
+        Ok(DryRun { model: mps, commands: vec![cmd] })
+    }
+
+    fn session(&self) -> Option<Box<dyn Session>> {
+        Some(Box::<ClpSession>::default())
+    }
+}
+
+/// Keeps the CLP model between solves, changing it into the linear program of the next one,
+/// and solving that from where the last solve ended
+#[derive(Default)]
+pub struct ClpSession {
+    loaded: Option<Loaded>,
+}
+
+impl Session for ClpSession {
+    fn solve(&mut self, _sources: &Sources, model: &Model, target: &Target, options: &Options, _args: &SolveOptions) -> Result<Outcome, Error> {
+        Ok(self.solve_lp(&linear_program(model, target, options)?))
+    }
+}
+
+impl ClpSession {
+    fn solve_lp(&mut self, lp: &LinearProgram) -> Outcome {
+        let loaded = self.loaded.get_or_insert_with(Loaded::new);
+        let column_of = loaded.change(lp);
+
+        // SAFETY: the model is valid while it's loaded, and the solution has a value for each
+        // of its columns, which is copied out before the model changes
+        let (status, values) = unsafe {
+            match loaded.solved {
+                // the dual simplex starts from the basis of the last solve
+                true => Clp_dual(loaded.simplex, 0),
+                false => Clp_initialSolve(loaded.simplex),
+            };
+            let columns = slice::from_raw_parts(Clp_getColSolution(loaded.simplex), loaded.columns.len());
+            (Clp_status(loaded.simplex), column_of.iter().map(|&column| columns[column]).collect_vec())
+        };
+        loaded.solved = true;
+
+        let status = match status {
+            0 => Status::Optimal,
+            1 => Status::Unsatisfiable,
+            2 => Status::Unbounded,
+            // stopped on iterations or because of errors
+            _ => Status::Unknown,
+        };
+        let solution = (status == Status::Optimal).then(|| lp.solution(&values));
+
+        Outcome { status, solution, errors: Vec::new(), statistics: Vec::new() }
+    }
+}
+
+/// A model loaded into CLP, deleted when it's dropped. Nothing is ever removed from it:
+/// the columns and rows of earlier linear programs that the current one doesn't have
+/// are fixed at 0 and left without bounds.
+struct Loaded {
+    simplex: *mut Simplex,
+    /// The name of the variable of each column
+    columns: Vec<String>,
+    /// The terms of each row, by column
+    rows: Vec<Vec<(usize, f64)>>,
+    /// Whether it was solved before, so the next solve can start from where that one ended
+    solved: bool,
+}
+
+impl Loaded {
+    fn new() -> Self {
+        // SAFETY: a new model is empty, and only used through this
+        let simplex = unsafe {
+            let simplex = Clp_newModel();
+            Clp_setLogLevel(simplex, 0);
+            simplex
+        };
+        Self { simplex, columns: Vec::new(), rows: Vec::new(), solved: false }
+    }
+
+    /// Change the model into a linear program, returning the column of each of its variables.
+    /// Columns of variables with the same name, and rows with the same terms, get its bounds
+    /// and objective. Its other variables and rows are added.
+    fn change(&mut self, lp: &LinearProgram) -> Vec<usize> {
+        let existing = self.columns.iter().enumerate().map(|(idx, name)| (name.as_str(), idx)).collect::<HashMap<_, _>>();
+        let mut added_columns = Vec::new();
+        let column_of = lp.variables
+            .iter()
+            .map(|var| existing.get(var.name.as_str()).copied().unwrap_or_else(|| {
+                added_columns.push(var.name.clone());
+                self.columns.len() + added_columns.len() - 1
+            }))
+            .collect_vec();
+
+        let key = |terms: &[(usize, f64)]| terms.iter().map(|&(column, coefficient)| (column, coefficient.to_bits())).collect_vec();
+        let mut unused = self.rows.iter().enumerate().map(|(idx, terms)| (key(terms), idx)).into_group_map();
+        let mut row_lower = vec![f64::MIN; self.rows.len()];
+        let mut row_upper = vec![f64::MAX; self.rows.len()];
+        let mut added_rows = Vec::new();
+        for row in &lp.rows {
+            let terms = row.terms.iter().map(|&(var, coefficient)| (column_of[var], coefficient)).collect_vec();
+            match unused.get_mut(&key(&terms)).and_then(|rows| rows.pop()) {
+                Some(idx) => {
+                    row_lower[idx] = finite(row.lower);
+                    row_upper[idx] = finite(row.upper);
+                }
+                None => added_rows.push((terms, finite(row.lower), finite(row.upper))),
+            }
+        }
+
+        // the added rows by row: where each row starts, and its columns and coefficients
+        let (mut start, mut index, mut value) = (vec![0], Vec::new(), Vec::new());
+        for (terms, _, _) in &added_rows {
+            for &(column, coefficient) in terms {
+                index.push(column as c_int);
+                value.push(coefficient);
+            }
+            start.push(index.len() as c_int);
+        }
+        let added_lower = added_rows.iter().map(|(_, lower, _)| *lower).collect_vec();
+        let added_upper = added_rows.iter().map(|(_, _, upper)| *upper).collect_vec();
+
+        // columns it doesn't have are fixed at 0
+        let columns = self.columns.len() + added_columns.len();
+        let (mut col_lower, mut col_upper, mut objective) = (vec![0.0; columns], vec![0.0; columns], vec![0.0; columns]);
+        for ((var, &column), coefficient) in lp.variables.iter().zip(&column_of).zip(lp.objective_coefficients()) {
+            col_lower[column] = finite(var.lower);
+            col_upper[column] = finite(var.upper);
+            objective[column] = coefficient;
+        }
+
+        // SAFETY: the columns are added empty, before the rows using them. The bounds of the rows are
+        // set before rows are added, so each array has a value for each row or column of the model
+        unsafe {
+            let zeros = vec![0.0; added_columns.len()];
+            Clp_addColumns(
+                self.simplex,
+                added_columns.len() as c_int,
+                zeros.as_ptr(),
+                zeros.as_ptr(),
+                zeros.as_ptr(),
+                vec![0; added_columns.len() + 1].as_ptr(),
+                [].as_ptr(),
+                [].as_ptr(),
+            );
+            Clp_chgRowLower(self.simplex, row_lower.as_ptr());
+            Clp_chgRowUpper(self.simplex, row_upper.as_ptr());
+            Clp_addRows(
+                self.simplex,
+                added_rows.len() as c_int,
+                added_lower.as_ptr(),
+                added_upper.as_ptr(),
+                start.as_ptr(),
+                index.as_ptr(),
+                value.as_ptr(),
+            );
+            Clp_chgColumnLower(self.simplex, col_lower.as_ptr());
+            Clp_chgColumnUpper(self.simplex, col_upper.as_ptr());
+            Clp_chgObjCoefficients(self.simplex, objective.as_ptr());
+        }
+        self.columns.extend(added_columns);
+        self.rows.extend(added_rows.into_iter().map(|(terms, _, _)| terms));
+        column_of
+    }
+}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        // SAFETY: the model isn't used after it's dropped
+        unsafe { Clp_deleteModel(self.simplex) };
+    }
+}
+
+/// CLP takes bounds of `f64::MAX` as unbounded
+fn finite(bound: f64) -> f64 {
+    bound.clamp(f64::MIN, f64::MAX)
+}
+
+/// The linear program of a target, unless it has integer variables, which CLP can't solve
+fn linear_program(model: &Model, target: &Target, options: &Options) -> Result<LinearProgram, Error> {
+    let lp = LinearProgram::new(model, target, options)?;
+    if let Some(var) = lp.variables.iter().find(|v| v.integer) {
+        return Err(Error::Failed {
+            message: format!("the clp backend doesn't support integer variables, like {}", var.name),
+            output: None,
+            help: Some("integers come from --integer, fixed costs, minimum batches and quanta; solve it with `--backend or-tools` or `--backend minizinc`".to_string()),
+        });
+    }
+    Ok(lp)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,51 @@
+//! A session changing the CLP model between solves finds the same solutions as solving from scratch.
+//! Only built with the `clp` feature, so they need `libClp` to link against.
+
+use itertools::Itertools;
+use crate::clp::ClpSession;
+use crate::codegen::Options;
+use crate::lp::LinearProgram;
+use crate::minizinc::Status;
+use crate::model::Model;
+use crate::parse_checked;
+
+const SMELTING: &str = r#"
+    raw ore;
+    "smelt": 2 ore -(3)-> plate;
+    "gears": 2 plate -(1)-> gear;
+"#;
+
+/// Ore can also be pressed into plates, quicker than smelting them
+const PRESSING: &str = r#"
+    "press": 3 ore -(1)-> plate;
+"#;
+
+/// The chem file of a target creating gears, with or without pressing
+fn chem_file(gears: usize, pressing: bool) -> String {
+    let pressing = if pressing { PRESSING } else { "" };
+    format!("{SMELTING}{pressing}\ntarget gears {{\n    create {gears} gear in 10;\n    minimize reactions;\n}}\n")
+}
+
+#[test]
+fn sessions_solve_like_from_scratch() {
+    let mut session = ClpSession::default();
+
+    // changing bounds, adding a reaction, and taking it out again
+    for (gears, pressing) in [(3, false), (5, false), (5, true), (2, true), (3, false)] {
+        let text = chem_file(gears, pressing);
+        let program = parse_checked(&text).expect("parses");
+        let model = Model::new(&program, false);
+        let lp = LinearProgram::new(&model, &program.targets["gears"], &Options::default()).expect("is a linear program");
+
+        let changed = session.solve_lp(&lp);
+        let scratch = ClpSession::default().solve_lp(&lp);
+        assert_eq!(changed.status, Status::Optimal, "{gears} gears, pressing: {pressing}");
+        assert_eq!(scratch.status, Status::Optimal, "{gears} gears, pressing: {pressing}");
+
+        let (changed, scratch) = (changed.solution.expect("optimal"), scratch.solution.expect("optimal"));
+        for var in scratch.values.keys().sorted() {
+            let (a, b) = (changed.get(var).expect("same variables"), scratch.get(var).expect("same variables"));
+            assert!((a - b).abs() < 1e-6, "{var} is {a} instead of {b}, for {gears} gears, pressing: {pressing}");
+        }
+    }
+}
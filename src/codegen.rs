@@ -0,0 +1,1021 @@
+use std::collections::HashSet;
+use std::io;
+use std::fmt::Write as _;
+use std::io::Write;
+use itertools::Itertools;
+use minos::{Report, ReportKind};
+use crate::ast::{Comparison, Goal, Quantity, QuantumSubject, Reaction, Symbol, Target};
+use crate::bounds::upper_bounds;
+use crate::Failure;
+use crate::model::{SymbolId, Model, Use};
+use crate::scaling::{scaled_var, Scaling};
+use crate::solution::Solution;
+use crate::sources::Sources;
+use crate::stamp::Stamp;
+
+pub struct Options<'a> {
+    /// Only allow whole numbers of machines per reaction
+    pub integer: bool,
+    /// A solution given to the solver as a starting point, rounded up with [`Options::integer`]
+    pub warm_start: Option<&'a Solution>,
+    /// Upper bound on the number of machines of a reaction,
+    /// used to link reactions to whether they are used at all
+    pub big_m: f64,
+    /// Also bound the machines of every reaction by `big_m`, so they can't grow without limit
+    pub bounded: bool,
+    /// Count reactions with random outcomes as producing the least they could,
+    /// instead of their expected outputs
+    pub strict_branches: bool,
+    /// Also output how much of each raw material is needed per time unit
+    pub bill_of_materials: bool,
+    /// Comment every constraint with where in the chem file it comes from
+    pub annotate: bool,
+    /// Instead of the goal, minimize the machine counts of reactions weighted by these
+    pub sample_weights: Option<&'a [f64]>,
+    /// `(optimum, epsilon)`: only allow solutions with an objective within
+    /// epsilon of the optimum, relative to the optimum
+    pub near_optimum: Option<(f64, f64)>,
+    /// Supports (the reactions used by a solution) that solutions may not have
+    pub excluded_supports: &'a [Vec<usize>],
+    /// Reactions that may not be used
+    pub excluded_reactions: &'a [usize],
+    /// Allow violating the constraints, minimizing by how much they are violated instead of the goal.
+    /// Used to find out what to change about an infeasible target.
+    pub elastic: bool,
+    /// Rescale the model to improve its numerical conditioning
+    pub auto_scale: bool,
+    /// Also output by how much each target and balance constraint is exceeded
+    pub slack: bool,
+    /// The machines of an existing factory, which solutions have at least as many of
+    pub frozen: Option<&'a Solution>,
+    /// Machine counts solutions have to have exactly, to check whether a saved solution meets the target
+    pub fixed: Option<&'a Solution>,
+    /// Instead of the goal, minimize how many machines are added or removed compared to this solution
+    pub baseline: Option<&'a Solution>,
+    /// What the model is made from, written at the top of it
+    pub stamp: Option<&'a Stamp>,
+    /// Only find some solution meeting the constraints, without optimizing the objective
+    pub feasible_only: bool,
+}
+
+/// Reactions with fewer machines than this don't count as used when excluding supports
+pub const SUPPORT_EPSILON: f64 = 1e-6;
+
+/// The most terms a sum in the model has, see [`chunked`]
+const SUM_CHUNK: usize = 1000;
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self {
+            integer: false,
+            warm_start: None,
+            big_m: 1_000_000.0,
+            bounded: false,
+            strict_branches: false,
+            bill_of_materials: false,
+            annotate: false,
+            sample_weights: None,
+            near_optimum: None,
+            excluded_supports: &[],
+            excluded_reactions: &[],
+            elastic: false,
+            auto_scale: false,
+            slack: false,
+            frozen: None,
+            fixed: None,
+            baseline: None,
+            stamp: None,
+            feasible_only: false,
+        }
+    }
+}
+
+/// Which chem file constructs the lines of a generated model come from
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    /// From the (1-based) line in the model on, constraints come from this span.
+    /// Sorted by line.
+    marks: Vec<(usize, Option<(usize, usize)>)>,
+}
+
+impl SourceMap {
+    /// The span in the chem file that a line of the model was generated for, if any
+    pub fn lookup(&self, line: usize) -> Option<(usize, usize)> {
+        let idx = self.marks.partition_point(|(l, _)| *l <= line);
+        self.marks[..idx].last()?.1
+    }
+}
+
+/// Counts the lines written, to build a [`SourceMap`]
+struct ModelWriter<W> {
+    inner: W,
+    line: usize,
+    source_map: SourceMap,
+}
+
+impl<W: Write> ModelWriter<W> {
+    /// The lines written from now on come from `span`
+    fn mark(&mut self, span: Option<(usize, usize)>) {
+        self.source_map.marks.push((self.line + 1, span));
+    }
+}
+
+impl<W: Write> Write for ModelWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.line += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What's wrong with a target that no model can be generated for it
+#[derive(Debug, PartialEq)]
+pub struct Invalid {
+    pub message: String,
+    /// Where it's wrong, and what's there
+    pub span: (usize, usize),
+    pub label: &'static str,
+    pub help: Option<String>,
+}
+
+/// The report of an [`Invalid`] target, on the chem file
+pub fn invalid_report(sources: &Sources, e: Invalid) -> Failure {
+    let report = Report::build(ReportKind::Error)
+        .with_message(e.message)
+        .with_label(sources.label(e.span).with_message(e.label));
+    Failure::new(
+        match e.help {
+            Some(help) => report.with_help(help),
+            None => report,
+        }.finish(),
+        sources.source(e.span)
+    )
+}
+
+/// A number of machines of a reaction as a minizinc literal of the type of its variable,
+/// which is an int with [`Options::integer`]
+fn machines_literal(value: f64, integer: bool) -> String {
+    match integer {
+        true => (value.round() as i64).to_string(),
+        false => format!("{value:?}"),
+    }
+}
+
+/// Check that a model can be generated for a target, returning its goal:
+/// it needs one, and what it refers to has to exist
+pub fn check_target<'a, 's>(model: &Model<'_, 's>, target: &'a Target<'s>) -> Result<&'a Goal<'s>, Invalid> {
+    let Some(ref goal) = target.goal else {
+        return Err(Invalid {
+            message: format!("expected 'goal' specification in target {}", target.name),
+            span: target.span,
+            label: "in this target",
+            help: None,
+        });
+    };
+
+    if let (Goal::Time, Some(symbol)) = (goal, target.windows.keys().min_by_key(|symbol| symbol.0)) {
+        return Err(Invalid {
+            message: format!("target {} minimizes the time, but the constraint on {} has a time of its own", target.name, symbol.0),
+            span: target.constraint_spans.get(symbol).copied().unwrap_or(target.span),
+            label: "this constraint",
+            help: Some("remove its `in`, or minimize something else".to_string()),
+        });
+    }
+
+    let costs = target.budgets.keys().chain(match goal {
+        Goal::Cost(name) => Some(name),
+        _ => None,
+    });
+    for name in costs.sorted() {
+        if !model.program().reactions.iter().any(|r| r.cost.other.contains_key(name)) {
+            return Err(Invalid {
+                message: format!("no reaction has a cost '{name}'"),
+                span: target.span,
+                label: "used in this target",
+                help: Some(format!("add it to reactions like `cost {{ {name}: 1 }}`")),
+            });
+        }
+    }
+
+    for quantum in &target.quanta {
+        if let QuantumSubject::Reaction(label) = &quantum.subject {
+            if model.labeled(label).is_none() {
+                return Err(Invalid {
+                    message: format!("no reaction labeled '{label}'"),
+                    span: quantum.span,
+                    label: "in this quantum constraint",
+                    help: None,
+                });
+            }
+        }
+    }
+
+    // linear constraints can only be about symbols reactions create or use
+    let unknown = target.linear_constraints
+        .iter()
+        .flat_map(|constraint| constraint.lhs.iter().chain(&constraint.rhs))
+        .find_map(|(_, r)| match r {
+            Quantity::Net(symbol, span) if model.id(symbol).is_none() => Some((symbol, *span)),
+            _ => None,
+        });
+    if let Some((symbol, span)) = unknown {
+        return Err(Invalid {
+            message: format!("no reaction creates or uses {}", symbol.0),
+            span,
+            label: "used in this constraint",
+            help: None,
+        });
+    }
+
+    Ok(goal)
+}
+
+/// Whether the model of a target maximizes its objective, instead of minimizing it
+pub fn maximizes(goal: &Goal, options: &Options) -> bool {
+    matches!(goal, Goal::Time) && !options.elastic && options.sample_weights.is_none() && options.baseline.is_none()
+}
+
+/// Generate the minizinc model of a target, together with where in the chem files its lines come from.
+/// The model starts with the chem files, as comments.
+pub fn generate_minizinc(sources: &Sources, model: &Model, target: &Target, options: &Options) -> Result<(String, SourceMap), Invalid> {
+    let goal = check_target(model, target)?;
+    let mut text = Vec::new();
+    let source_map = write_minizinc(&mut text, sources, model, target, goal, options).expect("writing to memory");
+
+    Ok((String::from_utf8(text).expect("the model is written from strings"), source_map))
+}
+
+fn write_minizinc(w: &mut impl Write, sources: &Sources, model: &Model, target: &Target, goal: &Goal, options: &Options) -> io::Result<SourceMap> {
+    let program = model.program();
+    let w = &mut ModelWriter { inner: w, line: 0, source_map: SourceMap::default() };
+    let mut relaxations = Vec::new();
+    // what each target and balance constraint is, and by how much it is exceeded
+    let mut slacks = Vec::new();
+    let reaction_origin = |reaction: usize| (format!("reaction {}", model.name(reaction)), Some(model.reaction(reaction).span));
+    let annotate = |w: &mut _, (origin, span): (String, Option<(usize, usize)>)| annotate(w, sources, options, &origin, span);
+
+    if let Some(stamp) = options.stamp {
+        writeln!(w, "% stamp: {stamp}")?;
+        writeln!(w)?;
+    }
+    for i in sources.text().lines() {
+        writeln!(w, "% {}", comment_text(i))?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "% variables")?;
+
+    let var_type = if options.integer { "int" } else { "float" };
+    let bounds = upper_bounds(model, target, options);
+    for (reaction, &bound) in bounds.iter().enumerate() {
+        annotate(w, reaction_origin(reaction))?;
+        let domain = match (bound, options.integer) {
+            (Some(bound), true) => format!("0..{}", bound.floor()),
+            (Some(bound), false) => format!("0.0..{bound:?}"),
+            (None, _) => var_type.to_string(),
+        };
+        writeln!(w, "var {domain}: {};", model.var_name(reaction))?;
+    }
+
+    let scaling = if options.auto_scale {
+        Scaling::geometric(model)
+    } else {
+        Scaling::none(model)
+    };
+    let scaled = (0..program.reactions.len()).filter(|&r| scaling.column(r) != 1.0).collect_vec();
+    if !scaled.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% scaled variables")?;
+    }
+    for reaction in scaled {
+        let var_name = model.var_name(reaction);
+        writeln!(w, "var float: {};", scaled_var(var_name))?;
+        writeln!(w, "constraint {var_name} = {} * {};", scaling.column(reaction), scaled_var(var_name))?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "% non-negative constraints")?;
+    for reaction in 0..program.reactions.len() {
+        annotate(w, reaction_origin(reaction))?;
+        writeln!(w, "constraint {} >= 0;", model.var_name(reaction))?;
+    }
+
+    // optional reactions whose feature the target doesn't enable can't be used either
+    let disabled = program.reactions
+        .iter()
+        .positions(|r| r.feature.is_some_and(|feature| !target.features.contains(&feature)));
+    let excluded = options.excluded_reactions.iter().copied().chain(disabled).unique().collect_vec();
+    if !excluded.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% excluded reactions")?;
+    }
+    for reaction in excluded {
+        annotate(w, reaction_origin(reaction))?;
+        writeln!(w, "constraint {} = 0;", model.var_name(reaction))?;
+    }
+
+    if let Some(frozen) = options.frozen {
+        let existing = (0..program.reactions.len())
+            .filter_map(|reaction| Some((reaction, frozen.get(model.var_name(reaction)).filter(|&m| m > 0.0)?)))
+            .collect_vec();
+        if !existing.is_empty() {
+            writeln!(w)?;
+            writeln!(w, "% frozen reactions")?;
+        }
+        for (reaction, machines) in existing {
+            annotate(w, reaction_origin(reaction))?;
+            writeln!(w, "constraint {} >= {machines};", model.var_name(reaction))?;
+        }
+    }
+    if let Some(fixed) = options.fixed {
+        writeln!(w)?;
+        writeln!(w, "% fixed reactions")?;
+        for reaction in 0..program.reactions.len() {
+            let var_name = model.var_name(reaction);
+            annotate(w, reaction_origin(reaction))?;
+            let value = fixed.get(var_name).unwrap_or(0.0);
+            match options.integer && value.fract() != 0.0 {
+                // a saved solution with fractions of machines can't have whole numbers of them
+                true => writeln!(w, "constraint int2float({var_name}) = {value:?};")?,
+                false => writeln!(w, "constraint {var_name} = {};", machines_literal(value, options.integer))?,
+            }
+        }
+    }
+
+    let mut changes = Vec::new();
+    if let Some(baseline) = options.baseline {
+        writeln!(w)?;
+        writeln!(w, "% changes from the baseline")?;
+        for reaction in 0..program.reactions.len() {
+            let var_name = model.var_name(reaction);
+            let machines = baseline.get(var_name).unwrap_or(0.0);
+            let change = format!("change_{var_name}");
+
+            // at least the absolute difference, and exactly that when minimized
+            annotate(w, reaction_origin(reaction))?;
+            writeln!(w, "var float: {change};")?;
+            writeln!(w, "constraint {change} >= {var_name} - {machines};")?;
+            writeln!(w, "constraint {change} >= {machines} - {var_name};")?;
+            changes.push(change);
+        }
+    }
+
+    // the rate needed to create `scalar` of something in `window`
+    let required_rate = |scalar: usize, window: usize| match goal {
+        // when minimizing the time, it is a variable. To keep the model linear,
+        // we maximize the throughput (1 / time) instead.
+        Goal::Time => format!("{scalar} * throughput"),
+        _ => format!("{scalar} / {window}"),
+    };
+    if let Goal::Time = goal {
+        writeln!(w)?;
+        writeln!(w, "% available machines")?;
+        writeln!(w, "var float: throughput;")?;
+        writeln!(w, "constraint throughput >= 0;")?;
+        for (idx, reaction) in program.reactions.iter().enumerate() {
+            if let Some(machines) = reaction.machines {
+                annotate(w, reaction_origin(idx))?;
+                let relax = relax(w, options, &mut relaxations, relax_machines_var(model.var_name(idx)))?;
+                writeln!(w, "constraint {} <= {machines}{relax};", model.var_name(idx))?;
+            }
+        }
+    }
+
+    if !model.ranged().is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% inputs accepting a range of attributes")?;
+    }
+    for (idx, ranged) in model.ranged().iter().enumerate() {
+        let cost = program.reactions[ranged.reaction].cost.time;
+
+        annotate(w, reaction_origin(ranged.reaction))?;
+        for &variant in &ranged.variants {
+            writeln!(w, "var float: {};", model.flow_var(idx, variant))?;
+            writeln!(w, "constraint {} >= 0;", model.flow_var(idx, variant))?;
+        }
+
+        let flows = std::iter::once("0".to_string())
+            .chain(ranged.variants.iter().map(|&variant| model.flow_var(idx, variant)))
+            .join("+");
+        writeln!(w, "constraint {flows} = {} * {} / {cost};", ranged.amount, model.var_name(ranged.reaction))?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "% target constraints")?;
+    for (symbol, scalar) in &target.active_constraints().into_iter().sorted_by_key(|(symbol, _)| symbol.0).collect_vec() {
+        let (production, consumption) = rates(model, &scaling, symbol);
+
+        let comparison = target.comparison(symbol);
+        let rate = required_rate(*scalar, target.window(symbol));
+        let create = match comparison {
+            Comparison::AtLeast => format!("create {scalar} {}", symbol.0),
+            _ => format!("create {comparison} {scalar} {}", symbol.0),
+        };
+        let create = match target.windows.get(symbol) {
+            Some(window) => format!("{create} in {window}"),
+            None => create,
+        };
+        annotate(w, (format!("target {}: {create}", target.name), Some(target.span)))?;
+        let relax = relax(w, options, &mut relaxations, relax_target_var(symbol))?;
+        // relaxing allows creating less, or more when what's created is at most something
+        match comparison {
+            Comparison::AtMost => writeln!(w, "constraint ({production}) - ({consumption}) <= {rate}{relax};")?,
+            _ => writeln!(w, "constraint ({production}) - ({consumption}){relax} {} {rate};", comparison.operator())?,
+        }
+        let surplus = match comparison {
+            Comparison::AtMost => format!("{rate} - (({production}) - ({consumption}))"),
+            _ => format!("({production}) - ({consumption}) - {rate}"),
+        };
+        slacks.push((surplus_target_var(symbol), surplus));
+    }
+
+    let mut penalties = Vec::new();
+    if !target.preferences.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% soft constraints")?;
+    }
+    for (idx, preference) in target.preferences.iter().enumerate() {
+        for (symbol, scalar) in preference.terms.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+            let (production, consumption) = rates(model, &scaling, symbol);
+            let slack = format!("slack_{idx}_{}", symbol.mzn_name());
+
+            annotate(w, (format!("target {}: prefer {scalar} {}", target.name, symbol.0), Some(target.span)))?;
+            writeln!(w, "var float: {slack};")?;
+            writeln!(w, "constraint {slack} >= 0;")?;
+            writeln!(w, "constraint ({production}) - ({consumption}) + {slack} >= {};", required_rate(*scalar, target.in_time))?;
+            // the slack is a rate, so the penalty is per unit per time unit missing
+            penalties.push(format!("{} * {slack}", preference.penalty));
+        }
+    }
+
+    // excluding supports needs to know about every reaction whether it's used
+    let enumerating = !options.excluded_supports.is_empty();
+    let indicated = program.reactions
+        .iter()
+        .positions(|r| enumerating || r.fixed_cost.is_some() || r.min_batch.is_some())
+        .collect_vec();
+    if !indicated.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% reactions in use")?;
+    }
+    for &reaction in &indicated {
+        let var = model.var_name(reaction);
+        let used = indicator(var);
+
+        annotate(w, reaction_origin(reaction))?;
+        writeln!(w, "var 0..1: {used};")?;
+        writeln!(w, "constraint {var} <= {} * {used};", options.big_m)?;
+
+        if let Some(min_batch) = program.reactions[reaction].min_batch {
+            writeln!(w, "constraint {var} >= {min_batch} * {used};")?;
+        }
+        if enumerating {
+            writeln!(w, "constraint {var} >= {SUPPORT_EPSILON} * {used};")?;
+        }
+        if let Some(fixed_cost) = program.reactions[reaction].fixed_cost {
+            penalties.push(format!("{fixed_cost} * {used}"));
+        }
+    }
+
+    let curved = program.reactions.iter().positions(|r| r.cost_curve.is_some()).collect_vec();
+    if !curved.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% cost curves")?;
+    }
+    for reaction in curved {
+        let var = model.var_name(reaction);
+        let curve = program.reactions[reaction].cost_curve.as_ref().unwrap();
+        let segments = curve.len() - 1;
+
+        annotate(w, reaction_origin(reaction))?;
+        // the machine count is a convex combination of two adjacent breakpoints,
+        // the segment between them chosen by a 0/1 variable
+        writeln!(w, "array[0..{}] of var 0.0..1.0: weight_{var};", curve.len() - 1)?;
+        writeln!(w, "array[0..{}] of var 0..1: segment_{var};", segments - 1)?;
+        writeln!(w, "constraint sum(weight_{var}) = 1;")?;
+        writeln!(w, "constraint sum(segment_{var}) = 1;")?;
+        for i in 0..curve.len() {
+            let adjacent = [i.checked_sub(1), (i < segments).then_some(i)]
+                .into_iter()
+                .flatten()
+                .map(|j| format!("segment_{var}[{j}]"))
+                .join(" + ");
+            writeln!(w, "constraint weight_{var}[{i}] <= {adjacent};")?;
+        }
+
+        let machines = curve.iter().enumerate().map(|(i, (x, _))| format!("{x} * weight_{var}[{i}]")).join(" + ");
+        let cost = curve.iter().enumerate().map(|(i, (_, c))| format!("{c} * weight_{var}[{i}]")).join(" + ");
+        writeln!(w, "constraint {var} = {machines};")?;
+        penalties.push(format!("({cost})"));
+    }
+
+    if target.transport_weight.is_some() || target.transport_budget.is_some() {
+        let transport = std::iter::once("0".to_string())
+            .chain(program.transport
+                .iter()
+                .sorted_by_key(|(symbol, _)| symbol.0)
+                .map(|(symbol, cost)| format!("{cost} * ({})", rates(model, &scaling, symbol).0)))
+            .join(" + ");
+
+        writeln!(w)?;
+        writeln!(w, "% transport costs")?;
+        annotate(w, (format!("target {}: transport", target.name), Some(target.span)))?;
+        writeln!(w, "var float: transport = {transport};")?;
+
+        if let Some(budget) = target.transport_budget {
+            let relax = relax(w, options, &mut relaxations, RELAX_TRANSPORT_VAR.to_string())?;
+            writeln!(w, "constraint transport <= {budget}{relax};")?;
+        }
+        if let Some(weight) = target.transport_weight {
+            penalties.push(format!("{weight} * transport"));
+        }
+    }
+    let penalty = penalties.iter().map(|i| format!(" + {i}")).join("");
+
+    // which techs to unlock is only decided when minimizing their cost, otherwise all are available
+    if let Goal::Techs = goal {
+        writeln!(w)?;
+        writeln!(w, "% techs")?;
+        for tech in &program.techs {
+            annotate(w, (format!("tech {}", tech.name), Some(tech.span)))?;
+            writeln!(w, "var 0..1: {};", tech_var(tech.name))?;
+            for required in &tech.requires {
+                writeln!(w, "constraint {} <= {};", tech_var(tech.name), tech_var(required))?;
+            }
+        }
+        for (idx, reaction) in program.reactions.iter().enumerate() {
+            for required in &reaction.requires {
+                annotate(w, reaction_origin(idx))?;
+                writeln!(w, "constraint {} <= {} * {};", model.var_name(idx), options.big_m, tech_var(required))?;
+            }
+        }
+    }
+
+    // the named costs the target bounds or minimizes
+    let costs = target.budgets
+        .keys()
+        .chain(match goal {
+            Goal::Cost(name) => Some(name),
+            _ => None,
+        })
+        .unique()
+        .sorted()
+        .collect_vec();
+    if !costs.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% costs")?;
+    }
+    for &name in &costs {
+        let terms = program.reactions.iter().enumerate().filter_map(|(idx, r)| {
+            Some(format!("{} * {}", r.cost.other.get(name)?, model.var_name(idx)))
+        });
+        let total = std::iter::once("0".to_string())
+            .chain(chunked(w, &format!("{}_part", cost_var(name)), terms.collect())?)
+            .join(" + ");
+
+        annotate(w, (format!("target {}: cost {name}", target.name), Some(target.span)))?;
+        writeln!(w, "var float: {} :: add_to_output = {total};", cost_var(name))?;
+        if let Some(budget) = target.budgets.get(name) {
+            let relax = relax(w, options, &mut relaxations, relax_budget_var(name))?;
+            writeln!(w, "constraint {} <= {budget}{relax};", cost_var(name))?;
+        }
+    }
+
+    if !target.quanta.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% quantum constraints")?;
+    }
+    for (idx, quantum) in target.quanta.iter().enumerate() {
+        let rate = match &quantum.subject {
+            QuantumSubject::Symbol(symbol) => rates(model, &scaling, symbol).0,
+            QuantumSubject::Reaction(label) => {
+                let reaction = model.labeled(label).expect("checked by check_target");
+                format!("{} / {}", model.var_name(reaction), program.reactions[reaction].cost.time)
+            }
+        };
+        let multiple = format!("quantum_{idx}");
+
+        annotate(w, (format!("target {}: quantum {}", target.name, quantum.quantum), Some(quantum.span)))?;
+        writeln!(w, "var int: {multiple};")?;
+        writeln!(w, "constraint {multiple} >= 0;")?;
+        writeln!(w, "constraint {rate} = {} * {multiple};", quantum.quantum)?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "% balance constraints")?;
+
+    // raw materials are inputs of every target
+    let using: HashSet<&Symbol> = target.inputs.iter().chain(&program.raw).collect();
+    // all zones, and `None` for reactions outside of any zone
+    let zones = program.zones.iter().map(|z| Some(z.name)).chain([None]).collect_vec();
+    for id in model.symbols() {
+        let symbol = model.symbol(id);
+        match goal {
+            Goal::Resources(rt) if rt.contains_key(&symbol) => continue,
+            _ if using.contains(&symbol) => continue,
+            _ => {}
+        }
+
+        annotate(w, (format!("balance of {}", symbol.0), None))?;
+        if program.zones.is_empty() {
+            let (production, consumption) = id_rates(model, &scaling, id);
+
+            let relax = relax(w, options, &mut relaxations, relax_balance_var(&symbol, None))?;
+            let row = scaling.row(id);
+            writeln!(w, "constraint {} >= {};", scale_row(row, format!("({production}{relax})")), scale_row(row, consumption.clone()))?;
+            slacks.push((surplus_balance_var(&symbol, None), format!("({production}) - ({consumption})")));
+            continue;
+        }
+
+        // per zone, a symbol can also be transferred in from zones producing it,
+        // or out to zones consuming it.
+        let zones_of = |reactions: &[usize]| -> Vec<usize> {
+            zones.iter()
+                .positions(|&zone| reactions.iter().any(|&r| model.reaction(r).zone == zone))
+                .collect()
+        };
+        let producing = zones_of(&model.producers(id).iter().map(|u| u.reaction).collect_vec());
+        let consumers = model.consumers(id)
+            .iter()
+            .map(|u| u.reaction)
+            .chain(model.flows(id).iter().map(|&ranged| model.ranged()[ranged].reaction))
+            .collect_vec();
+        let consuming = zones_of(&consumers);
+        let transfer = |from: usize, to: usize| format!("transfer_{}_{from}_{to}", symbol.mzn_name());
+
+        for &from in &producing {
+            for &to in consuming.iter().filter(|&&to| to != from) {
+                writeln!(w, "var float: {};", transfer(from, to))?;
+                writeln!(w, "constraint {} >= 0;", transfer(from, to))?;
+            }
+        }
+
+        for (idx, &zone) in zones.iter().enumerate() {
+            if !producing.contains(&idx) && !consuming.contains(&idx) {
+                continue;
+            }
+
+            let (production, consumption) = filtered_rates(model, &scaling, id, |r| r.zone == zone);
+            let inflow = producing.iter()
+                .filter(|&&from| from != idx && consuming.contains(&idx))
+                .map(|&from| format!("+{}", transfer(from, idx)))
+                .join("");
+            let outflow = consuming.iter()
+                .filter(|&&to| to != idx && producing.contains(&idx))
+                .map(|&to| format!("+{}", transfer(idx, to)))
+                .join("");
+
+            let relax = relax(w, options, &mut relaxations, relax_balance_var(&symbol, Some(idx)))?;
+            let row = scaling.row(id);
+            writeln!(
+                w,
+                "constraint {} >= {};",
+                scale_row(row, format!("({production}{inflow}{relax})")),
+                scale_row(row, format!("{consumption}{outflow}"))
+            )?;
+            slacks.push((surplus_balance_var(&symbol, Some(idx)), format!("({production}{inflow}) - ({consumption}{outflow})")));
+        }
+    }
+
+    let capacities = program.zones.iter().enumerate().filter_map(|(i, z)| Some((i, z, z.capacity?))).collect_vec();
+    if !capacities.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% zone capacities")?;
+    }
+    for (idx, zone, capacity) in capacities {
+        let machines = std::iter::once("0")
+            .chain(program.reactions.iter().positions(|r| r.zone == Some(zone.name)).map(|i| model.var_name(i)))
+            .join("+");
+
+        annotate(w, (format!("zone {}", zone.name), Some(zone.span)))?;
+        let relax = relax(w, options, &mut relaxations, relax_capacity_var(idx))?;
+        writeln!(w, "constraint {machines} <= {capacity}{relax};")?;
+    }
+
+    w.mark(None);
+    let solve = match options.warm_start {
+        Some(solution) => {
+            let (vars, values): (Vec<_>, Vec<_>) = (0..program.reactions.len())
+                .filter_map(|i| Some((model.var_name(i), solution.get(model.var_name(i))?, bounds[i])))
+                .map(|(var, value, bound)| match options.integer {
+                    // rounded up, it still makes at least as much, but it has to stay in the domain
+                    true => (var, machines_literal(bound.map_or(value.ceil(), |bound| value.ceil().min(bound.floor())), true)),
+                    false => (var, machines_literal(value, false)),
+                })
+                .unzip();
+
+            format!("solve :: warm_start([{}], [{}])", vars.join(", "), values.join(", "))
+        }
+        None => "solve".to_string(),
+    };
+
+    let (goal_sense, goal_objective) = match goal {
+        Goal::Resources(rt) => {
+            let mut production = vec!["0".to_string()];
+            let mut consumption = vec!["0".to_string()];
+
+            for (symbol, weight) in rt.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+                let Some(id) = model.id(symbol) else {
+                    continue;
+                };
+
+                for u in model.consumers(id) {
+                    consumption.push(format!("{} * {} * {weight}", u.amount, model.var_name(u.reaction)))
+                }
+
+                for u in model.producers(id) {
+                    production.push(format!("{} * {} * {weight}", u.amount, model.var_name(u.reaction)))
+                }
+            }
+
+            let production = chunked(w, "produced_part", production)?.join("+");
+            let consumption = chunked(w, "consumed_part", consumption)?.join("+");
+
+            ("minimize", format!("({consumption}) - ({production}){penalty}"))
+        }
+        Goal::Reactions => {
+            let machines = (0..program.reactions.len()).map(|i| model.var_name(i).to_string()).collect();
+            ("minimize", format!("{}{penalty}", chunked(w, "machines_part", machines)?.join("+")))
+        }
+        Goal::Time => ("maximize", format!("throughput - (0{penalty})")),
+        Goal::Cost(name) => ("minimize", format!("{}{penalty}", cost_var(name))),
+        Goal::Techs => {
+            let techs = program.techs.iter().map(|t| format!(" + {} * {}", t.cost, tech_var(t.name))).join("");
+            ("minimize", format!("0{techs}{penalty}"))
+        }
+    };
+
+    let (sense, objective) = match (options.sample_weights, options.baseline) {
+        _ if options.elastic => {
+            // not the goal, but violating the constraints as little as possible
+            ("minimize", std::iter::once("0".to_string()).chain(chunked(w, "relaxed_part", relaxations.clone())?).join(" + "))
+        }
+        (Some(weights), _) => {
+            // not the goal, but a random objective to find some feasible solution
+            let weighted = weights.iter().enumerate().map(|(i, weight)| format!("{weight} * {}", model.var_name(i))).collect();
+            let weighted = chunked(w, "weighted_part", weighted)?.into_iter().map(|term| format!("+{term}")).join("");
+            let throughput = if let Goal::Time = goal { " - throughput" } else { "" };
+            ("minimize", format!("0{weighted}{throughput}{penalty}"))
+        }
+        (None, Some(_)) => {
+            // not the goal, but changing the baseline as little as possible
+            ("minimize", std::iter::once("0".to_string()).chain(chunked(w, "changes_part", changes.clone())?).join(" + "))
+        }
+        (None, None) => (goal_sense, goal_objective.clone()),
+    };
+
+    if !target.linear_constraints.is_empty() {
+        writeln!(w)?;
+        writeln!(w, "% linear constraints")?;
+    }
+    for constraint in &target.linear_constraints {
+        // the objectives of other targets are substituted by the time the model is generated
+        let side = |terms: &[(f64, Quantity)]| std::iter::once("0".to_string())
+            .chain(terms.iter().map(|(scale, r)| match r {
+                Quantity::One => scale.to_string(),
+                Quantity::Own => format!("{scale} * ({goal_objective})"),
+                Quantity::Of(name, _) => unreachable!("the objective of {name} is substituted before generating the model"),
+                Quantity::Net(symbol, _) => {
+                    let (production, consumption) = rates(model, &scaling, symbol);
+                    format!("{scale} * {} * (({production}) - ({consumption}))", target.in_time)
+                }
+            }))
+            .join(" + ");
+        let comparison = if constraint.at_most { "<=" } else { ">=" };
+
+        annotate(w, (format!("target {}: constraint", target.name), Some(constraint.span)))?;
+        writeln!(w, "constraint {} {comparison} {};", side(&constraint.lhs), side(&constraint.rhs))?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "% objective")?;
+    writeln!(w, "var float: objective :: add_to_output = {objective};")?;
+    if let Some((optimum, epsilon)) = options.near_optimum {
+        let slack = epsilon * optimum.abs().max(1.0);
+        if sense == "minimize" {
+            writeln!(w, "constraint objective <= {};", optimum + slack)?;
+        } else {
+            writeln!(w, "constraint objective >= {};", optimum - slack)?;
+        }
+    }
+    for (idx, support) in options.excluded_supports.iter().enumerate() {
+        // at least one reaction has to be (un)used differently
+        let cut = (0..program.reactions.len())
+            .map(|reaction| match support.contains(&reaction) {
+                true => format!("(1 - {})", indicator(model.var_name(reaction))),
+                false => indicator(model.var_name(reaction)),
+            })
+            .collect();
+        let cut = chunked(w, &format!("support_{idx}_part"), cut)?.join(" + ");
+        writeln!(w, "constraint {cut} >= 1;")?;
+    }
+    match options.feasible_only {
+        true => writeln!(w, "{solve} satisfy;")?,
+        false => writeln!(w, "{solve} {sense} objective;")?,
+    }
+
+    // there's no output item: minizinc outputs the values of the variables,
+    // and how they're shown is up to renderers like [`render_text`](crate::text::render_text).
+    // Besides the variables without a definition, those defined here are output too.
+    if options.bill_of_materials {
+        // raw materials are declared as such, or just not produced by anything.
+        // Some of a raw material may still be produced, only what's missing is counted.
+        let raw = model.symbols()
+            .filter(|&id| program.raw.contains(&model.symbol(id)) || model.producers(id).is_empty())
+            .sorted_by_key(|&id| model.symbol(id).0)
+            .collect_vec();
+
+        writeln!(w)?;
+        writeln!(w, "% raw materials")?;
+        for id in raw {
+            let (production, consumption) = id_rates(model, &scaling, id);
+            writeln!(w, "var float: {} :: add_to_output = ({consumption}) - ({production});", raw_needed_var(&model.symbol(id)))?;
+        }
+    }
+
+    if options.slack {
+        writeln!(w)?;
+        writeln!(w, "% slack")?;
+        for (var, slack) in slacks {
+            writeln!(w, "var float: {var} :: add_to_output = {slack};")?;
+        }
+    }
+
+    Ok(std::mem::take(&mut w.source_map))
+}
+
+/// Split a sum of more than [`SUM_CHUNK`] terms into partial sums, declared as variables `{name}_{index}`,
+/// returning the terms to sum instead. Minizinc takes long to flatten very long expressions,
+/// like sums over all reactions of a large program.
+fn chunked(w: &mut impl Write, name: &str, terms: Vec<String>) -> io::Result<Vec<String>> {
+    if terms.len() <= SUM_CHUNK {
+        return Ok(terms);
+    }
+
+    let mut parts = Vec::new();
+    for (idx, chunk) in terms.chunks(SUM_CHUNK).enumerate() {
+        let part = format!("{name}_{idx}");
+        writeln!(w, "var float: {part} = {};", chunk.join(" + "))?;
+        parts.push(part);
+    }
+    chunked(w, &format!("{name}_sum"), parts)
+}
+
+/// Record where the constraints written next come from.
+/// With `--annotate`, this is also written as a comment.
+fn annotate(w: &mut ModelWriter<impl Write>, sources: &Sources, options: &Options, origin: &str, span: Option<(usize, usize)>) -> io::Result<()> {
+    w.mark(span);
+    if !options.annotate {
+        return Ok(());
+    }
+
+    let origin = comment_text(origin);
+    match span {
+        Some((from, _)) => writeln!(w, "% {origin} ({})", sources.location(from)),
+        None => writeln!(w, "% {origin}"),
+    }
+}
+
+/// Text that's safe to put in a `%` comment in the model: minizinc ends comments
+/// at any line break, including a lone carriage return, so control characters become spaces
+fn comment_text(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+/// With [`Options::elastic`], declare a variable for how much a constraint is violated,
+/// returning what to add to the constraint to allow that
+fn relax(w: &mut impl Write, options: &Options, relaxations: &mut Vec<String>, var: String) -> io::Result<String> {
+    if !options.elastic {
+        return Ok(String::new());
+    }
+
+    writeln!(w, "var float: {var};")?;
+    writeln!(w, "constraint {var} >= 0;")?;
+    let res = format!(" + {var}");
+    relaxations.push(var);
+    Ok(res)
+}
+
+/// How much less of a symbol than required a target creates, per time unit
+pub fn relax_target_var(symbol: &Symbol) -> String {
+    format!("relax_target_{}", symbol.mzn_name())
+}
+
+/// How much of a symbol is missing per time unit, in a zone (by index) if there are zones.
+/// The last zone is the default zone.
+pub fn relax_balance_var(symbol: &Symbol, zone: Option<usize>) -> String {
+    match zone {
+        Some(zone) => format!("relax_balance_{}_{zone}", symbol.mzn_name()),
+        None => format!("relax_balance_{}", symbol.mzn_name()),
+    }
+}
+
+/// How many machines a zone (by index) needs above its capacity
+pub fn relax_capacity_var(zone: usize) -> String {
+    format!("relax_capacity_{zone}")
+}
+
+/// How many machines a reaction needs above those available to it
+pub fn relax_machines_var(var: &str) -> String {
+    format!("relax_machines_{var}")
+}
+
+/// The 0/1 variable telling whether a tech is unlocked
+pub fn tech_var(name: &str) -> String {
+    format!("tech_{}", mzn_ident(name))
+}
+
+/// The total of a named reaction cost
+pub fn cost_var(name: &str) -> String {
+    format!("cost_{}", mzn_ident(name))
+}
+
+/// A name in the chem file as part of a minizinc identifier. Underscores are doubled and dashes
+/// become `_d`, so names only differing in them, like `a-b` and `a_b`, are different variables
+fn mzn_ident(name: &str) -> String {
+    name.replace('_', "__").replace('-', "_d")
+}
+
+/// How much of a raw material is needed per time unit, with [`Options::bill_of_materials`]
+pub fn raw_needed_var(symbol: &Symbol) -> String {
+    format!("raw_needed_{}", symbol.mzn_name())
+}
+
+/// By how much a target creates more of a symbol than required per time unit,
+/// or less than allowed when it creates at most something, with [`Options::slack`]
+pub fn surplus_target_var(symbol: &Symbol) -> String {
+    format!("surplus_target_{}", symbol.mzn_name())
+}
+
+/// By how much more of a symbol is produced than consumed per time unit, with [`Options::slack`].
+/// Zones are numbered like in [`relax_balance_var`].
+pub fn surplus_balance_var(symbol: &Symbol, zone: Option<usize>) -> String {
+    match zone {
+        Some(zone) => format!("surplus_balance_{}_{zone}", symbol.mzn_name()),
+        None => format!("surplus_balance_{}", symbol.mzn_name()),
+    }
+}
+
+/// How much a named cost goes over its budget
+pub fn relax_budget_var(name: &str) -> String {
+    format!("relax_budget_{}", mzn_ident(name))
+}
+
+/// How much the transport costs go over budget
+pub const RELAX_TRANSPORT_VAR: &str = "relax_transport";
+
+/// Multiply (a side of) the balance of a symbol by its [`Scaling::row`]
+fn scale_row(row: f64, expr: String) -> String {
+    if row == 1.0 {
+        expr
+    } else {
+        format!("{row} * ({expr})")
+    }
+}
+
+/// The 0/1 variable telling whether a reaction is used at all
+pub fn indicator(var: &str) -> String {
+    format!("used_{var}")
+}
+
+/// The rates at which all reactions together produce and consume a symbol
+fn rates(model: &Model, scaling: &Scaling, symbol: &Symbol) -> (String, String) {
+    match model.id(symbol) {
+        Some(id) => id_rates(model, scaling, id),
+        None => ("0".to_string(), "0".to_string()),
+    }
+}
+
+fn id_rates(model: &Model, scaling: &Scaling, id: SymbolId) -> (String, String) {
+    filtered_rates(model, scaling, id, |_| true)
+}
+
+/// Like [`id_rates`], but only counting the reactions for which `include` holds
+fn filtered_rates(model: &Model, scaling: &Scaling, id: SymbolId, include: impl Fn(&Reaction) -> bool) -> (String, String) {
+    let rate = |uses: &[Use]| {
+        // roughly "+{amount} * {var} / {cost}" per use
+        let mut res = String::with_capacity(1 + uses.iter().map(|u| model.var_name(u.reaction).len() + 16).sum::<usize>());
+        res.push('0');
+        for u in uses.iter().filter(|u| include(model.reaction(u.reaction))) {
+            let cost = model.reaction(u.reaction).cost.time;
+            write!(res, "+{} * {} / {cost}", u.amount * scaling.column(u.reaction), scaling.var(model, u.reaction)).unwrap();
+        }
+        res
+    };
+
+    let mut consumption = rate(model.consumers(id));
+    for &ranged in model.flows(id) {
+        if include(model.reaction(model.ranged()[ranged].reaction)) {
+            write!(consumption, "+{}", model.flow_var(ranged, id)).unwrap();
+        }
+    }
+
+    (rate(model.producers(id)), consumption)
+}
+
+#[cfg(test)]
+mod tests;
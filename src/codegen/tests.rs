@@ -0,0 +1,81 @@
+//! Golden tests of the generated models: the model of each target of the chem files in `tests/golden`
+//! has to be the same as the one next to it, in `<file>.<target>.mzn`.
+//! Run them with `UPDATE_GOLDEN=1` to write the models after changing what's generated.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use itertools::Itertools;
+use crate::codegen::{generate_minizinc, Invalid, Options};
+use crate::model::Model;
+use crate::parse_checked;
+use crate::sources::{FileKind, Sources};
+
+/// Parse a chem file as the only source, panicking on errors
+fn sources(name: &str, text: &str) -> Sources {
+    let sources = Sources::new([(name.to_string(), text, FileKind::Chem)]);
+    if let Err(diagnostics) = parse_checked(sources.text()) {
+        panic!("{diagnostics:?} in {name}");
+    }
+    sources
+}
+
+#[test]
+fn models_match_golden_files() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let files = fs::read_dir(&dir)
+        .expect("read the golden directory")
+        .map(|entry| entry.expect("read the golden directory").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "chem"))
+        .sorted()
+        .collect_vec();
+    assert!(!files.is_empty(), "no chem files in {}", dir.display());
+
+    let mut different = Vec::new();
+    for path in files {
+        let name = format!("tests/golden/{}", path.file_name().unwrap().to_string_lossy());
+        let sources = sources(&name, &fs::read_to_string(&path).expect("read the chem file"));
+        let program = parse_checked(sources.text()).expect("checked above");
+        let model = Model::new(&program, false);
+
+        for target in program.targets.values().sorted_by_key(|t| t.span) {
+            let options = Options { annotate: true, ..Options::default() };
+            let (generated, _) = generate_minizinc(&sources, &model, target, &options)
+                .unwrap_or_else(|e| panic!("target {} of {name}: {e:?}", target.name));
+
+            let golden = path.with_extension(format!("{}.mzn", target.name));
+            if update {
+                fs::write(&golden, &generated).expect("write the golden file");
+            } else if fs::read_to_string(&golden).ok().as_deref() != Some(generated.as_str()) {
+                different.push(golden.file_name().unwrap().to_string_lossy().to_string());
+            }
+        }
+    }
+
+    assert!(
+        different.is_empty(),
+        "the generated models differ from {}, run the tests with UPDATE_GOLDEN=1 if that's intended",
+        different.join(", ")
+    );
+}
+
+#[test]
+fn invalid_targets_are_errors() {
+    let sources = sources("invalid.chem", "\
+        \"smelt\": ore -(1)-> plate;\n\
+        target no-goal { create plate; }\n\
+        target no-cost { create plate; minimize cost money; }\n\
+        target no-label { create plate; minimize reactions; quantum 2 \"mine\"; }\n\
+    ");
+    let program = parse_checked(sources.text()).expect("checked by sources");
+    let model = Model::new(&program, false);
+    let error = |name: &str| match generate_minizinc(&sources, &model, &program.targets[name], &Options::default()) {
+        Ok(_) => panic!("generated a model for {name}"),
+        Err(Invalid { message, .. }) => message,
+    };
+
+    assert_eq!(error("no-goal"), "expected 'goal' specification in target no-goal");
+    assert_eq!(error("no-cost"), "no reaction has a cost 'money'");
+    assert_eq!(error("no-label"), "no reaction labeled 'mine'");
+}
@@ -0,0 +1,118 @@
+use itertools::Itertools;
+use crate::ast::{Goal, Program, Target};
+use crate::tree::amount_str;
+
+/// Coefficients further apart than this make solvers like CBC lose precision,
+/// and silently return wrong solutions
+pub const MAX_COEFFICIENT_RATIO: f64 = 1e6;
+
+/// A number that ends up in the generated model, and where it comes from
+#[derive(Debug, Clone)]
+pub struct Coefficient {
+    pub value: f64,
+    pub origin: String,
+    pub span: Option<(usize, usize)>,
+}
+
+impl Coefficient {
+    /// The value, in scientific notation if it's very small or large
+    pub fn value_str(&self) -> String {
+        if (1e-3..1e6).contains(&self.value) {
+            amount_str(self.value)
+        } else {
+            format!("{:e}", self.value)
+        }
+    }
+}
+
+/// The smallest and largest coefficient of the model generated for a target,
+/// if they are further apart than [`MAX_COEFFICIENT_RATIO`]
+pub fn badly_conditioned(program: &Program, target: &Target) -> Option<(Coefficient, Coefficient)> {
+    let coefficients = coefficients(program, target)
+        .into_iter()
+        .filter(|c| c.value.is_finite() && c.value != 0.0)
+        .map(|c| Coefficient { value: c.value.abs(), ..c })
+        .collect_vec();
+
+    let smallest = coefficients.iter().min_by(|a, b| a.value.total_cmp(&b.value))?;
+    let largest = coefficients.iter().max_by(|a, b| a.value.total_cmp(&b.value))?;
+
+    (largest.value / smallest.value > MAX_COEFFICIENT_RATIO).then(|| (smallest.clone(), largest.clone()))
+}
+
+/// Roughly all numbers the model for a target is made of.
+/// The big M bounding machines isn't counted, it's large on purpose.
+fn coefficients(program: &Program, target: &Target) -> Vec<Coefficient> {
+    let mut res = Vec::new();
+    let mut add = |value: f64, origin: String, span: Option<(usize, usize)>| {
+        res.push(Coefficient { value, origin, span });
+    };
+
+    for reaction in &program.reactions {
+        let name = format!("reaction {}", reaction.name());
+        let span = Some(reaction.span);
+        let time = reaction.cost.time as f64;
+
+        // symbols are balanced on their rate per machine
+        for (symbol, &amount) in reaction.inputs.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+            add(amount as f64 / time, format!("the rate {name} consumes {} at", symbol.0), span);
+        }
+        for (symbol, amount) in reaction.effective_outputs(false).into_iter().sorted_by_key(|(symbol, _)| symbol.0) {
+            add(amount / time, format!("the rate {name} produces {} at", symbol.0), span);
+        }
+
+        if let Some(machines) = reaction.machines {
+            add(machines, format!("the machines available for {name}"), span);
+        }
+        if let Some(min_batch) = reaction.min_batch {
+            add(min_batch, format!("the minimum batch of {name}"), span);
+        }
+        if let Some(fixed_cost) = reaction.fixed_cost {
+            add(fixed_cost, format!("the fixed cost of {name}"), span);
+        }
+        for &(machines, cost) in reaction.cost_curve.iter().flatten() {
+            add(machines, format!("a breakpoint of the cost curve of {name}"), span);
+            add(cost, format!("a cost in the cost curve of {name}"), span);
+        }
+        for (cost, &value) in reaction.cost.other.iter().sorted_by_key(|(cost, _)| **cost) {
+            add(value, format!("the {cost} cost of {name}"), span);
+        }
+    }
+
+    for zone in &program.zones {
+        if let Some(capacity) = zone.capacity {
+            add(capacity, format!("the capacity of zone {}", zone.name), Some(zone.span));
+        }
+    }
+    if target.transport_weight.is_some() || target.transport_budget.is_some() {
+        for (symbol, &cost) in program.transport.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+            add(cost, format!("the cost of transporting {}", symbol.0), None);
+        }
+    }
+
+    let span = Some(target.span);
+    for (symbol, scalar) in target.active_constraints().into_iter().sorted_by_key(|(symbol, _)| symbol.0) {
+        let time = match target.goal {
+            Some(Goal::Time) => 1.0,
+            _ => target.window(&symbol) as f64,
+        };
+        add(scalar as f64 / time, format!("the rate target {} creates {} at", target.name, symbol.0), span);
+    }
+    for preference in &target.preferences {
+        add(preference.penalty as f64, format!("a penalty in target {}", target.name), span);
+    }
+    for quantum in &target.quanta {
+        add(quantum.quantum as f64, format!("a quantum in target {}", target.name), Some(quantum.span));
+    }
+    if let Some(weight) = target.transport_weight {
+        add(weight, format!("the transport weight of target {}", target.name), span);
+    }
+    if let Some(budget) = target.transport_budget {
+        add(budget, format!("the transport budget of target {}", target.name), span);
+    }
+    for (cost, &budget) in target.budgets.iter().sorted_by_key(|(cost, _)| **cost) {
+        add(budget, format!("the {cost} budget of target {}", target.name), span);
+    }
+
+    res
+}
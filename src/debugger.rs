@@ -0,0 +1,177 @@
+use std::io::{stdin, stdout, BufRead, Write};
+use itertools::Itertools;
+use crate::ast::{Comparison, Constants, LinearConstraint, Program, Symbol};
+use crate::codegen::Options;
+use crate::minizinc::{Outcome, Status};
+use crate::model::Model;
+use crate::sources::Sources;
+use crate::text::render_text;
+use crate::options::SolveOptions;
+use crate::{chosen_backend, exit_error, prepare, solve_frozen};
+
+/// A constraint of the target being debugged, which can be switched off and on
+enum Part<'s> {
+    Create(Symbol<'s>, usize),
+    Group(usize),
+    Budget(&'s str, f64),
+    TransportBudget(f64),
+    Linear(LinearConstraint<'s>),
+    Capacity(usize, f64),
+}
+
+/// Find out why a target is unsatisfiable, by switching its constraints off and on
+/// and solving again, at a prompt
+pub fn debug(sources: &Sources, constants: &Constants, target_name: &str, args: &SolveOptions) {
+    let (mut program, target_name) = prepare(sources, constants, Some(target_name), args);
+    let frozen = args.freeze.as_deref().map(|name| solve_frozen(sources, &mut program, name, args));
+
+    // the constraints are taken out of the program, and put back in when they're on
+    let target = program.targets.get_mut(target_name).expect("prepared");
+    let mut parts = target.constraints.drain().sorted_by_key(|(symbol, _)| symbol.0).map(|(s, amount)| Part::Create(s, amount)).collect_vec();
+    parts.extend((0..target.groups.len()).map(Part::Group));
+    parts.extend(target.budgets.drain().sorted_by_key(|(name, _)| *name).map(|(name, budget)| Part::Budget(name, budget)));
+    parts.extend(target.transport_budget.take().map(Part::TransportBudget));
+    parts.extend(target.linear_constraints.drain(..).map(Part::Linear));
+    parts.extend(program.zones.iter_mut().enumerate().filter_map(|(idx, zone)| Some(Part::Capacity(idx, zone.capacity.take()?))));
+    let mut enabled = parts
+        .iter()
+        .map(|part| match part {
+            Part::Group(idx) => program.targets[target_name].groups[*idx].enabled,
+            _ => true,
+        })
+        .collect_vec();
+
+    // backends that keep the model in memory only change what was switched since the last solve
+    let backend = chosen_backend(sources, &args.backend);
+    let mut session = backend.session();
+    let mut solve = |program: &mut _, enabled: &[bool]| {
+        apply(program, target_name, &parts, enabled);
+        let target = &program.targets[target_name];
+        let model = Model::new(program, args.strict_branches);
+        let options = Options {
+            integer: args.integer,
+            big_m: args.big_m,
+            strict_branches: args.strict_branches,
+            frozen: frozen.as_ref(),
+            ..Options::default()
+        };
+        let outcome = match &mut session {
+            Some(session) => session.solve(sources, &model, target, &options, args),
+            None => backend.solve(sources, &model, target, &options, args),
+        }.unwrap_or_else(|e| exit_error(sources, &args.backend, e));
+        show(program, target_name, &outcome, args);
+        outcome
+    };
+
+    if solve(&mut program, &enabled).status != Status::Unsatisfiable {
+        return;
+    }
+    println!("switch constraints off and on, and solve again, to find out which of them conflict. Type `help` for how.");
+    list(sources, &program, target_name, &parts, &enabled);
+
+    loop {
+        print!("debug> ");
+        stdout().flush().expect("io error");
+        let Some(Ok(line)) = stdin().lock().lines().next() else {
+            return;
+        };
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("list" | "l") => list(sources, &program, target_name, &parts, &enabled),
+            Some(command @ ("on" | "off")) => {
+                for word in words {
+                    let which = match word {
+                        "all" => 0..parts.len(),
+                        _ => match word.parse::<usize>() {
+                            Ok(n) if (1..=parts.len()).contains(&n) => n - 1..n,
+                            _ => {
+                                println!("no constraint {word}, they're numbered 1 to {}", parts.len());
+                                continue;
+                            }
+                        },
+                    };
+                    enabled[which].iter_mut().for_each(|e| *e = command == "on");
+                }
+                list(sources, &program, target_name, &parts, &enabled);
+            }
+            Some("solve" | "s") => {
+                solve(&mut program, &enabled);
+            }
+            Some("help" | "h") => {
+                println!("list            show the constraints of the target, and whether they're on");
+                println!("on N... | all   switch constraints on, by their number in the list");
+                println!("off N... | all  switch constraints off");
+                println!("solve           solve the target with the constraints that are on");
+                println!("quit            stop debugging");
+            }
+            Some("quit" | "q" | "exit") => return,
+            Some(command) => println!("unknown command '{command}', type `help` for the commands"),
+            None => {}
+        }
+    }
+}
+
+/// Put the constraints that are on back into the program, and take those that are off out
+fn apply<'s>(program: &mut Program<'s>, target_name: &str, parts: &[Part<'s>], enabled: &[bool]) {
+    for zone in &mut program.zones {
+        zone.capacity = None;
+    }
+    let target = program.targets.get_mut(target_name).expect("prepared");
+    target.constraints.clear();
+    target.budgets.clear();
+    target.transport_budget = None;
+    target.linear_constraints.clear();
+
+    for (part, &on) in parts.iter().zip(enabled) {
+        match part {
+            Part::Group(idx) => target.groups[*idx].enabled = on,
+            _ if !on => {}
+            Part::Create(symbol, amount) => {
+                target.constraints.insert(*symbol, *amount);
+            }
+            Part::Budget(name, budget) => {
+                target.budgets.insert(name, *budget);
+            }
+            Part::TransportBudget(budget) => target.transport_budget = Some(*budget),
+            Part::Linear(constraint) => target.linear_constraints.push(constraint.clone()),
+            Part::Capacity(idx, capacity) => program.zones[*idx].capacity = Some(*capacity),
+        }
+    }
+}
+
+fn list(sources: &Sources, program: &Program, target_name: &str, parts: &[Part], enabled: &[bool]) {
+    let target = &program.targets[target_name];
+    let width = parts.len().to_string().len();
+
+    for (idx, (part, on)) in parts.iter().zip(enabled).enumerate() {
+        let description = match part {
+            Part::Create(symbol, amount) => match target.comparison(symbol) {
+                Comparison::AtLeast => format!("create {amount} {} in {}", symbol.0, target.window(symbol)),
+                comparison => format!("create {comparison} {amount} {} in {}", symbol.0, target.window(symbol)),
+            },
+            Part::Group(group) => format!("group {}", target.groups[*group].name),
+            Part::Budget(name, budget) => format!("budget {name} {budget}"),
+            Part::TransportBudget(budget) => format!("transport budget {budget}"),
+            Part::Linear(constraint) => sources.text()[constraint.span.0..constraint.span.1].to_string(),
+            Part::Capacity(zone, capacity) => format!("capacity {capacity} of zone {}", program.zones[*zone].name),
+        };
+        println!("{:>width$} [{}] {description}", idx + 1, if *on { "on " } else { "off" });
+    }
+}
+
+/// Show the outcome of solving with some constraints switched off
+fn show(program: &Program, target_name: &str, outcome: &Outcome, args: &SolveOptions) {
+    let target = &program.targets[target_name];
+
+    match (outcome.status, &outcome.solution) {
+        (Status::Unsatisfiable, _) => println!("target {target_name} is unsatisfiable"),
+        (Status::Unbounded, _) => println!("target {target_name} is unbounded"),
+        (Status::UnsatisfiableOrUnbounded, _) => println!("target {target_name} is unsatisfiable or unbounded"),
+        (_, Some(solution)) => {
+            println!("target {target_name} is feasible:");
+            print!("{}", render_text(program, target, solution, args.strict_branches, args.group_by, args.number_format()));
+        }
+        (_, None) => println!("no solution found for target {target_name}, but it's not proven unsatisfiable either"),
+    }
+}
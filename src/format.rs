@@ -0,0 +1,69 @@
+/// How numbers in the solution are shown
+#[derive(Debug, Copy, Clone)]
+pub struct NumberFormat {
+    /// The most decimals to show, trailing zeros are left out
+    pub precision: usize,
+    pub decimal_separator: char,
+    /// Put between every three digits before the decimal separator, if any
+    pub thousands_separator: Option<char>,
+    /// Show numbers like `1.5e3`, instead of `1500`
+    pub scientific: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            precision: 5,
+            decimal_separator: '.',
+            thousands_separator: None,
+            scientific: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn amount(&self, amount: f64) -> String {
+        if self.scientific && amount != 0.0 && amount.is_finite() {
+            let res = format!("{amount:.precision$e}", precision = self.precision);
+            let (mantissa, exponent) = res.split_once('e').unwrap_or((&res, "0"));
+            return format!("{}e{exponent}", self.separate(&trim_zeros(mantissa)));
+        }
+
+        self.separate(&trim_zeros(&format!("{amount:.precision$}", precision = self.precision)))
+    }
+
+    /// Replace the decimal point, and group the thousands
+    fn separate(&self, number: &str) -> String {
+        let (sign, number) = match number.strip_prefix('-') {
+            Some(number) => ("-", number),
+            None => ("", number),
+        };
+        let (whole, fraction) = number.split_once('.').map_or((number, None), |(w, f)| (w, Some(f)));
+
+        let mut res = sign.to_string();
+        for (idx, digit) in whole.chars().enumerate() {
+            let left = whole.len() - idx;
+            if let (Some(separator), true) = (self.thousands_separator, idx > 0 && left % 3 == 0) {
+                res.push(separator);
+            }
+            res.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            res.push(self.decimal_separator);
+            res.push_str(fraction);
+        }
+
+        res
+    }
+}
+
+/// Leave out trailing zeros after the decimal point, and the sign of negative zero
+fn trim_zeros(number: &str) -> String {
+    let res = if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    };
+
+    if res == "-0" { "0".to_string() } else { res.to_string() }
+}
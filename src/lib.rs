@@ -0,0 +1,122 @@
+//! Chem files, and solving their targets. The command line is the binary, but the rest is a library too,
+//! so the parser can be fuzzed (see [`parse_checked`]), other tools can read chem files,
+//! and solver integrations can live in crates of their own (see [`backend`]).
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use itertools::Itertools;
+use lalrpop_util::ParseError;
+use minos::{Report, Source};
+use crate::ast::{Constants, Program};
+
+pub mod ast;
+pub mod backend;
+mod bounds;
+pub mod builder;
+#[cfg(feature = "clp")]
+pub mod clp;
+pub mod codegen;
+pub mod format;
+#[allow(clippy::all)]
+pub mod grammar;
+pub mod lp;
+pub mod minizinc;
+pub mod model;
+pub mod options;
+pub mod ortools;
+pub mod owned;
+mod print;
+mod scaling;
+pub mod solution;
+pub mod sources;
+pub mod stamp;
+pub mod workdir;
+
+/// What the parser returns on errors, for some kind of token
+pub type ChemParseError<T> = ParseError<usize, T, (usize, String, usize)>;
+
+/// An error in a chem file, about a span of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// What's wrong at the span, like what was expected there
+    pub label: String,
+    pub span: (usize, usize),
+}
+
+impl<T: Display> From<ChemParseError<T>> for Diagnostic {
+    fn from(e: ChemParseError<T>) -> Self {
+        match e {
+            ParseError::InvalidToken { location } => Self {
+                message: "invalid token".to_string(),
+                label: "here".to_string(),
+                span: (location, location.saturating_add(1)),
+            },
+            ParseError::UnrecognizedEof { location, expected } => Self {
+                message: "unexpected end of file".to_string(),
+                label: expected_str("expected ", &expected),
+                span: (location, location.saturating_add(1)),
+            },
+            ParseError::UnrecognizedToken { token: (from, tok, to), expected } => Self {
+                message: format!("invalid token '{tok}'"),
+                label: expected_str("expected ", &expected),
+                span: (from, to),
+            },
+            ParseError::ExtraToken { token: (from, tok, to) } => Self {
+                message: format!("unexpected token '{tok}'"),
+                label: "no rule expects this token".to_string(),
+                span: (from, to),
+            },
+            ParseError::User { error: (from, err, to) } => Self {
+                message: "parse error".to_string(),
+                label: err,
+                span: (from, to),
+            },
+        }
+    }
+}
+
+/// Parse a chem file into a program, returning what's wrong with it instead of reporting it.
+/// No constants are defined, and they're never taken from the environment, so an input always
+/// parses the same: `${NAME}` placeholders are errors, and `if defined(NAME)` blocks are left out.
+///
+/// Never panics, whatever the input, so it can be fuzzed, like with cargo-fuzz (see `fuzz/`).
+pub fn parse_checked(input: &str) -> Result<Program<'_>, Vec<Diagnostic>> {
+    let constants = Constants::only(HashMap::new());
+    let items = grammar::ItemsParser::new()
+        .parse(&constants, input)
+        .map_err(|e| vec![Diagnostic::from(e)])?;
+
+    Program::from_items(items).map_err(|(from, err, to)| vec![Diagnostic {
+        message: "parse error".to_string(),
+        label: err,
+        span: (from, to),
+    }])
+}
+
+/// What went wrong, as a report and the text its labels point into, for the binary to print
+pub struct Failure {
+    pub report: Box<Report>,
+    pub source: Source,
+}
+
+impl Failure {
+    pub fn new(report: Report, source: Source) -> Self {
+        Self { report: Box::new(report), source }
+    }
+}
+
+/// A list of what was expected, like "expected a, b or c"
+pub fn expected_str<'a>(word: &str, expected: impl IntoIterator<Item=impl AsRef<str> + 'a>) -> String {
+    let expected = expected.into_iter().collect_vec();
+    let expected = expected.iter().map(|i| i.as_ref()).collect_vec();
+
+    if expected.is_empty() {
+        "".to_string()
+    } else if expected.len() == 1 {
+        format!("{word}{}", expected[0])
+    } else {
+        let (last, rest) = expected.split_last().unwrap();
+        format!("{word}{} or {last}", rest.join(","))
+    }
+}
@@ -0,0 +1,542 @@
+//! Targets as linear programs in memory, for backends solving them without minizinc.
+//! Variables are named like in the minizinc model, so solutions look the same whichever backend found them.
+//! Not all of the language can be solved this way, see [`LinearProgram::new`].
+
+use std::collections::HashSet;
+use std::io;
+use std::io::Write;
+use itertools::Itertools;
+use microlp::{ComparisonOp, OptimizationDirection, Problem};
+use crate::ast::{Comparison, Goal, Quantity, QuantumSubject, Symbol, Target};
+use crate::backend::Error;
+use crate::bounds::upper_bounds;
+use crate::codegen::{check_target, cost_var, indicator, raw_needed_var, relax_balance_var, relax_budget_var, relax_target_var, surplus_balance_var, surplus_target_var, Options, RELAX_TRANSPORT_VAR, SUPPORT_EPSILON};
+use crate::model::{Model, SymbolId};
+use crate::solution::Solution;
+
+/// A sum of variables (by index) times coefficients, plus a constant
+#[derive(Debug, Clone, Default)]
+pub struct Linear {
+    pub terms: Vec<(usize, f64)>,
+    pub constant: f64,
+}
+
+impl Linear {
+    fn var(var: usize) -> Self {
+        Self { terms: vec![(var, 1.0)], constant: 0.0 }
+    }
+
+    fn constant(constant: f64) -> Self {
+        Self { terms: Vec::new(), constant }
+    }
+
+    /// Add `scale` times another sum to this one
+    fn add(&mut self, scale: f64, other: &Linear) {
+        self.terms.extend(other.terms.iter().map(|&(var, coefficient)| (var, scale * coefficient)));
+        self.constant += scale * other.constant;
+    }
+
+    fn plus(mut self, scale: f64, other: &Linear) -> Self {
+        self.add(scale, other);
+        self
+    }
+
+    pub fn value(&self, values: &[f64]) -> f64 {
+        self.constant + self.terms.iter().map(|&(var, coefficient)| coefficient * values[var]).sum::<f64>()
+    }
+}
+
+#[derive(Debug)]
+pub struct Variable {
+    pub name: String,
+    pub lower: f64,
+    pub upper: f64,
+    pub integer: bool,
+}
+
+/// A constraint `lower <= terms <= upper`, where either bound can be infinite
+#[derive(Debug)]
+pub struct Row {
+    pub terms: Vec<(usize, f64)>,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Part of the language a backend can't solve, and where it's used
+#[derive(Debug)]
+pub struct Unsupported {
+    pub what: &'static str,
+    pub span: (usize, usize),
+}
+
+/// A target as a linear program, minimizing its objective
+#[derive(Debug, Default)]
+pub struct LinearProgram {
+    pub variables: Vec<Variable>,
+    pub rows: Vec<Row>,
+    pub objective: Linear,
+    /// What minizinc would output besides the variables, like the costs and the objective
+    outputs: Vec<(String, Linear)>,
+}
+
+impl LinearProgram {
+    /// The same program minizinc would solve for the target, see [`generate_minizinc`](crate::codegen::generate_minizinc),
+    /// with the same errors in the target.
+    /// Zones, cost curves, and minimizing the time or the techs aren't supported.
+    pub fn new(model: &Model, target: &Target, options: &Options) -> Result<Self, Error> {
+        let program = model.program();
+        let unsupported = |what, span| Err(Error::Unsupported(Unsupported { what, span }));
+        let goal = check_target(model, target).map_err(Error::Invalid)?;
+        match goal {
+            Goal::Time => return unsupported("minimizing the time", target.span),
+            Goal::Techs => return unsupported("minimizing the techs", target.span),
+            _ => {}
+        }
+        if let Some(zone) = program.zones.first() {
+            return unsupported("zones", zone.span);
+        }
+        if let Some(reaction) = program.reactions.iter().find(|r| r.cost_curve.is_some()) {
+            return unsupported("cost curves", reaction.span);
+        }
+
+        let mut lp = Self::default();
+        let mut relaxations = Linear::default();
+        let mut penalties = Linear::default();
+
+        for (reaction, bound) in upper_bounds(model, target, options).into_iter().enumerate() {
+            let upper = match (bound, options.integer) {
+                (Some(bound), true) => bound.floor(),
+                (Some(bound), false) => bound,
+                (None, _) => f64::INFINITY,
+            };
+            lp.var(model.var_name(reaction).to_string(), 0.0, upper, options.integer);
+        }
+        let machines = |reaction: usize| Linear::var(reaction);
+
+        // optional reactions whose feature the target doesn't enable can't be used either
+        let disabled = program.reactions
+            .iter()
+            .positions(|r| r.feature.is_some_and(|feature| !target.features.contains(&feature)));
+        for reaction in options.excluded_reactions.iter().copied().chain(disabled).unique() {
+            lp.equal(machines(reaction), 0.0);
+        }
+        if let Some(frozen) = options.frozen {
+            for reaction in model.reactions() {
+                if let Some(existing) = frozen.get(model.var_name(reaction)).filter(|&m| m > 0.0) {
+                    lp.at_least(machines(reaction), existing);
+                }
+            }
+        }
+        if let Some(fixed) = options.fixed {
+            for reaction in model.reactions() {
+                lp.equal(machines(reaction), fixed.get(model.var_name(reaction)).unwrap_or(0.0));
+            }
+        }
+
+        let mut changes = Linear::default();
+        if let Some(baseline) = options.baseline {
+            for reaction in model.reactions() {
+                let var_name = model.var_name(reaction);
+                let existing = baseline.get(var_name).unwrap_or(0.0);
+                // at least the absolute difference, and exactly that when minimized
+                let change = Linear::var(lp.var(format!("change_{var_name}"), f64::NEG_INFINITY, f64::INFINITY, false));
+                lp.at_least(change.clone().plus(-1.0, &machines(reaction)), -existing);
+                lp.at_least(change.clone().plus(1.0, &machines(reaction)), existing);
+                changes.add(1.0, &change);
+            }
+        }
+
+        let mut flows = Vec::new();
+        for (idx, ranged) in model.ranged().iter().enumerate() {
+            let time = model.reaction(ranged.reaction).cost.time as f64;
+            let vars = ranged.variants
+                .iter()
+                .map(|&variant| lp.var(model.flow_var(idx, variant), 0.0, f64::INFINITY, false))
+                .collect_vec();
+
+            let total = vars.iter().fold(Linear::default(), |total, &var| total.plus(1.0, &Linear::var(var)));
+            lp.equal(total.plus(-(ranged.amount as f64) / time, &machines(ranged.reaction)), 0.0);
+            flows.push(vars);
+        }
+
+        // how much all reactions together create of a symbol, minus what they use, per time unit
+        let net = |id: Option<SymbolId>| {
+            let mut res = Linear::default();
+            let Some(id) = id else {
+                return res;
+            };
+            for u in model.producers(id) {
+                res.add(u.rate, &machines(u.reaction));
+            }
+            for u in model.consumers(id) {
+                res.add(-u.rate, &machines(u.reaction));
+            }
+            for &ranged in model.flows(id) {
+                let variant = model.ranged()[ranged].variants.iter().position(|&v| v == id).expect("a variant of the range");
+                res.add(-1.0, &Linear::var(flows[ranged][variant]));
+            }
+            res
+        };
+        let production = |id: SymbolId| model.producers(id).iter().fold(Linear::default(), |res, u| res.plus(u.rate, &machines(u.reaction)));
+
+        for (symbol, scalar) in target.active_constraints().into_iter().sorted_by_key(|(symbol, _)| symbol.0) {
+            let net = net(model.id(&symbol));
+            let rate = scalar as f64 / target.window(&symbol) as f64;
+            let comparison = target.comparison(&symbol);
+
+            // relaxing allows creating less, or more when what's created is at most something
+            let relaxed = lp.relax(options, &mut relaxations, relax_target_var(&symbol));
+            let sign = if comparison == Comparison::AtMost { -1.0 } else { 1.0 };
+            let row = net.clone().plus(sign, &relaxed);
+            match comparison {
+                Comparison::AtLeast => lp.at_least(row, rate),
+                Comparison::AtMost => lp.at_most(row, rate),
+                Comparison::Exactly => lp.equal(row, rate),
+            }
+
+            let surplus = net.plus(-1.0, &Linear::constant(rate));
+            lp.output(options.slack, surplus_target_var(&symbol), if sign < 0.0 { Linear::default().plus(-1.0, &surplus) } else { surplus });
+        }
+
+        for (idx, preference) in target.preferences.iter().enumerate() {
+            for (symbol, scalar) in preference.terms.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+                let slack = Linear::var(lp.var(format!("slack_{idx}_{}", symbol.mzn_name()), 0.0, f64::INFINITY, false));
+                lp.at_least(net(model.id(symbol)).plus(1.0, &slack), *scalar as f64 / target.in_time as f64);
+                penalties.add(preference.penalty as f64, &slack);
+            }
+        }
+
+        // excluding supports needs to know about every reaction whether it's used
+        let enumerating = !options.excluded_supports.is_empty();
+        let mut used = vec![None; program.reactions.len()];
+        for (reaction, r) in program.reactions.iter().enumerate() {
+            if !enumerating && r.fixed_cost.is_none() && r.min_batch.is_none() {
+                continue;
+            }
+
+            let indicator = Linear::var(lp.var(indicator(model.var_name(reaction)), 0.0, 1.0, true));
+            lp.at_most(machines(reaction).plus(-options.big_m, &indicator), 0.0);
+            if let Some(min_batch) = r.min_batch {
+                lp.at_least(machines(reaction).plus(-min_batch, &indicator), 0.0);
+            }
+            if enumerating {
+                lp.at_least(machines(reaction).plus(-SUPPORT_EPSILON, &indicator), 0.0);
+            }
+            if let Some(fixed_cost) = r.fixed_cost {
+                penalties.add(fixed_cost, &indicator);
+            }
+            used[reaction] = Some(indicator);
+        }
+
+        if target.transport_weight.is_some() || target.transport_budget.is_some() {
+            let transport = program.transport
+                .iter()
+                .sorted_by_key(|(symbol, _)| symbol.0)
+                .filter_map(|(symbol, cost)| Some((model.id(symbol)?, cost)))
+                .fold(Linear::default(), |res, (id, cost)| res.plus(*cost, &production(id)));
+
+            if let Some(budget) = target.transport_budget {
+                let relaxed = lp.relax(options, &mut relaxations, RELAX_TRANSPORT_VAR.to_string());
+                lp.at_most(transport.clone().plus(-1.0, &relaxed), budget);
+            }
+            if let Some(weight) = target.transport_weight {
+                penalties.add(weight, &transport);
+            }
+        }
+
+        // the named costs the target bounds or minimizes
+        let costs = target.budgets
+            .keys()
+            .chain(match goal {
+                Goal::Cost(name) => Some(name),
+                _ => None,
+            })
+            .unique()
+            .sorted()
+            .collect_vec();
+        let cost = |name: &str| program.reactions
+            .iter()
+            .enumerate()
+            .filter_map(|(reaction, r)| Some((reaction, *r.cost.other.get(name)?)))
+            .fold(Linear::default(), |res, (reaction, cost)| res.plus(cost, &machines(reaction)));
+        for &name in &costs {
+            if let Some(budget) = target.budgets.get(name) {
+                let relaxed = lp.relax(options, &mut relaxations, relax_budget_var(name));
+                lp.at_most(cost(name).plus(-1.0, &relaxed), *budget);
+            }
+            lp.output(true, cost_var(name), cost(name));
+        }
+
+        for (idx, quantum) in target.quanta.iter().enumerate() {
+            let rate = match &quantum.subject {
+                QuantumSubject::Symbol(symbol) => model.id(symbol).map(production).unwrap_or_default(),
+                QuantumSubject::Reaction(label) => {
+                    let reaction = model.labeled(label).expect("checked by check_target");
+                    Linear::default().plus(1.0 / model.reaction(reaction).cost.time as f64, &machines(reaction))
+                }
+            };
+            let multiple = Linear::var(lp.var(format!("quantum_{idx}"), 0.0, f64::INFINITY, true));
+            lp.equal(rate.plus(-(quantum.quantum as f64), &multiple), 0.0);
+        }
+
+        // raw materials are inputs of every target
+        let using: HashSet<&Symbol> = target.inputs.iter().chain(&program.raw).collect();
+        for id in model.symbols() {
+            let symbol = model.symbol(id);
+            match goal {
+                Goal::Resources(rt) if rt.contains_key(&symbol) => continue,
+                _ if using.contains(&symbol) => continue,
+                _ => {}
+            }
+
+            let relaxed = lp.relax(options, &mut relaxations, relax_balance_var(&symbol, None));
+            lp.at_least(net(Some(id)).plus(1.0, &relaxed), 0.0);
+            lp.output(options.slack, surplus_balance_var(&symbol, None), net(Some(id)));
+        }
+
+        let goal_objective = match goal {
+            // like in the minizinc model, what's used of the resources per machine, not per time unit
+            Goal::Resources(rt) => rt.iter().filter_map(|(symbol, &weight)| Some((model.id(symbol)?, weight))).fold(Linear::default(), |mut res, (id, weight)| {
+                for u in model.consumers(id) {
+                    res.add(u.amount * weight as f64, &machines(u.reaction));
+                }
+                for u in model.producers(id) {
+                    res.add(-u.amount * weight as f64, &machines(u.reaction));
+                }
+                res
+            }),
+            Goal::Reactions => model.reactions().fold(Linear::default(), |res, reaction| res.plus(1.0, &machines(reaction))),
+            Goal::Cost(name) => cost(name),
+            Goal::Time | Goal::Techs => unreachable!("not supported"),
+        }.plus(1.0, &penalties);
+
+        lp.objective = match (options.sample_weights, options.baseline) {
+            _ if options.elastic => relaxations,
+            (Some(weights), _) => weights.iter().enumerate().fold(penalties, |res, (reaction, &weight)| res.plus(weight, &machines(reaction))),
+            (None, Some(_)) => changes,
+            (None, None) => goal_objective.clone(),
+        };
+
+        for constraint in &target.linear_constraints {
+            let side = |terms: &[(f64, Quantity)]| terms.iter().fold(Linear::default(), |res, (scale, r)| match r {
+                Quantity::One => res.plus(*scale, &Linear::constant(1.0)),
+                Quantity::Own => res.plus(*scale, &goal_objective),
+                Quantity::Of(name, _) => unreachable!("the objective of {name} is substituted before solving"),
+                Quantity::Net(symbol, _) => res.plus(scale * target.in_time as f64, &net(model.id(symbol))),
+            });
+            let row = side(&constraint.lhs).plus(-1.0, &side(&constraint.rhs));
+            match constraint.at_most {
+                true => lp.at_most(row, 0.0),
+                false => lp.at_least(row, 0.0),
+            }
+        }
+
+        if let Some((optimum, epsilon)) = options.near_optimum {
+            lp.at_most(lp.objective.clone(), optimum + epsilon * optimum.abs().max(1.0));
+        }
+        for support in options.excluded_supports {
+            // at least one reaction has to be (un)used differently
+            let cut = used.iter().enumerate().fold(Linear::default(), |res, (reaction, indicator)| {
+                let indicator = indicator.as_ref().expect("every reaction has an indicator when enumerating");
+                match support.contains(&reaction) {
+                    true => res.plus(-1.0, indicator).plus(1.0, &Linear::constant(1.0)),
+                    false => res.plus(1.0, indicator),
+                }
+            });
+            lp.at_least(cut, 1.0);
+        }
+
+        // raw materials are declared as such, or just not produced by anything.
+        // Some of a raw material may still be produced, only what's missing is counted.
+        let raw = model.symbols().filter(|&id| program.raw.contains(&model.symbol(id)) || model.producers(id).is_empty());
+        for id in raw.sorted_by_key(|&id| model.symbol(id).0) {
+            lp.output(options.bill_of_materials, raw_needed_var(&model.symbol(id)), Linear::default().plus(-1.0, &net(Some(id))));
+        }
+        lp.output(true, "objective".to_string(), lp.objective.clone());
+        if options.feasible_only {
+            lp.objective = Linear::default();
+        }
+
+        Ok(lp)
+    }
+
+    fn var(&mut self, name: String, lower: f64, upper: f64, integer: bool) -> usize {
+        self.variables.push(Variable { name, lower, upper, integer });
+        self.variables.len() - 1
+    }
+
+    fn row(&mut self, sum: Linear, lower: f64, upper: f64) {
+        self.rows.push(Row { terms: sum.terms, lower: lower - sum.constant, upper: upper - sum.constant });
+    }
+
+    fn at_least(&mut self, sum: Linear, bound: f64) {
+        self.row(sum, bound, f64::INFINITY);
+    }
+
+    fn at_most(&mut self, sum: Linear, bound: f64) {
+        self.row(sum, f64::NEG_INFINITY, bound);
+    }
+
+    fn equal(&mut self, sum: Linear, value: f64) {
+        self.row(sum, value, value);
+    }
+
+    /// With [`Options::elastic`], a variable for how much a constraint is violated, added to the relaxations
+    fn relax(&mut self, options: &Options, relaxations: &mut Linear, name: String) -> Linear {
+        if !options.elastic {
+            return Linear::default();
+        }
+
+        let relaxed = Linear::var(self.var(name, 0.0, f64::INFINITY, false));
+        relaxations.add(1.0, &relaxed);
+        relaxed
+    }
+
+    fn output(&mut self, enabled: bool, name: String, sum: Linear) {
+        if enabled {
+            self.outputs.push((name, sum));
+        }
+    }
+
+    /// The solution with these values of the variables, with the outputs computed from them
+    pub fn solution(&self, values: &[f64]) -> Solution {
+        let variables = self.variables.iter().zip(values).map(|(var, &value)| (var.name.clone(), value));
+        let outputs = self.outputs.iter().map(|(name, sum)| (name.clone(), sum.value(values)));
+        Solution { values: variables.chain(outputs).collect() }
+    }
+
+    /// Solve the program in-process with microlp, with the integer variables relaxed to real ones,
+    /// for the value of each variable. `None` when it has no optimum
+    pub fn solve_relaxed(&self) -> Option<Vec<f64>> {
+        let mut problem = Problem::new(OptimizationDirection::Minimize);
+        let vars = self.variables
+            .iter()
+            .zip(self.objective_coefficients())
+            .map(|(var, coefficient)| problem.add_var(coefficient, (var.lower, var.upper)))
+            .collect_vec();
+
+        for row in &self.rows {
+            // microlp takes each variable of a row once
+            let terms = row.terms
+                .iter()
+                .copied()
+                .sorted_by_key(|&(var, _)| var)
+                .coalesce(|(a, x), (b, y)| if a == b { Ok((a, x + y)) } else { Err(((a, x), (b, y))) })
+                .map(|(var, coefficient)| (vars[var], coefficient))
+                .collect_vec();
+            if row.lower == row.upper {
+                problem.add_constraint(&terms, ComparisonOp::Eq, row.lower);
+                continue;
+            }
+            if row.lower.is_finite() {
+                problem.add_constraint(&terms, ComparisonOp::Ge, row.lower);
+            }
+            if row.upper.is_finite() {
+                problem.add_constraint(&terms, ComparisonOp::Le, row.upper);
+            }
+        }
+
+        let solution = problem.solve().ok()?;
+        Some(vars.iter().map(|&var| *solution.var_value(var)).collect())
+    }
+
+    /// The rows each variable is in, and with which coefficient, like in a sparse matrix by column
+    pub fn columns(&self) -> Vec<Vec<(usize, f64)>> {
+        let mut res = vec![Vec::new(); self.variables.len()];
+        for (row, r) in self.rows.iter().enumerate() {
+            for &(var, coefficient) in &r.terms {
+                res[var].push((row, coefficient));
+            }
+        }
+        res
+    }
+
+    /// The coefficient of each variable in the objective, summing terms of the same variable
+    pub fn objective_coefficients(&self) -> Vec<f64> {
+        let mut res = vec![0.0; self.variables.len()];
+        for &(var, coefficient) in &self.objective.terms {
+            res[var] += coefficient;
+        }
+        res
+    }
+
+    /// Write the program in (free) MPS format. Variables are called `C{index}` and rows `R{index}`,
+    /// as the names of the minizinc variables can be too long for some readers.
+    pub fn write_mps(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "NAME reactions")?;
+        writeln!(w, "ROWS")?;
+        writeln!(w, " N COST")?;
+        for (idx, row) in self.rows.iter().enumerate() {
+            let kind = match (row.lower.is_finite(), row.upper.is_finite()) {
+                _ if row.lower == row.upper => "E",
+                (true, _) => "G",
+                (false, true) => "L",
+                // a row without bounds doesn't constrain anything
+                (false, false) => "N",
+            };
+            writeln!(w, " {kind} R{idx}")?;
+        }
+
+        writeln!(w, "COLUMNS")?;
+        let objective = self.objective_coefficients();
+        let mut integer = false;
+        for (var, column) in self.columns().into_iter().enumerate() {
+            if self.variables[var].integer != integer {
+                integer = self.variables[var].integer;
+                writeln!(w, "    MARKER 'MARKER' '{}'", if integer { "INTORG" } else { "INTEND" })?;
+            }
+            // the row entries of a column can't repeat, so the same variable twice in a row is summed
+            let entries = column.into_iter().into_grouping_map().sum();
+            writeln!(w, "    C{var} COST {:?}", objective[var])?;
+            for (row, coefficient) in entries.into_iter().sorted_by_key(|(row, _)| *row) {
+                writeln!(w, "    C{var} R{row} {coefficient:?}")?;
+            }
+        }
+        if integer {
+            writeln!(w, "    MARKER 'MARKER' 'INTEND'")?;
+        }
+
+        writeln!(w, "RHS")?;
+        let mut ranges = Vec::new();
+        for (idx, row) in self.rows.iter().enumerate() {
+            match (row.lower.is_finite(), row.upper.is_finite()) {
+                (true, true) if row.lower != row.upper => {
+                    writeln!(w, "    RHS R{idx} {:?}", row.lower)?;
+                    ranges.push((idx, row.upper - row.lower));
+                }
+                (true, _) => writeln!(w, "    RHS R{idx} {:?}", row.lower)?,
+                (false, true) => writeln!(w, "    RHS R{idx} {:?}", row.upper)?,
+                (false, false) => {}
+            }
+        }
+        if !ranges.is_empty() {
+            writeln!(w, "RANGES")?;
+            for (idx, range) in ranges {
+                writeln!(w, "    RNG R{idx} {range:?}")?;
+            }
+        }
+
+        writeln!(w, "BOUNDS")?;
+        for (idx, var) in self.variables.iter().enumerate() {
+            if var.lower == var.upper {
+                writeln!(w, " FX BND C{idx} {:?}", var.lower)?;
+                continue;
+            }
+            if var.lower == f64::NEG_INFINITY {
+                writeln!(w, " MI BND C{idx}")?;
+            } else if var.lower != 0.0 {
+                writeln!(w, " LO BND C{idx} {:?}", var.lower)?;
+            }
+            if var.upper.is_finite() {
+                writeln!(w, " UP BND C{idx} {:?}", var.upper)?;
+            } else if var.integer {
+                // some readers take integer variables without an upper bound to be 0/1
+                writeln!(w, " PL BND C{idx}")?;
+            }
+        }
+        writeln!(w, "ENDATA")
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,28 @@
+//! Linear programs solved in-process, like the float relaxation of `--integer`.
+
+use crate::codegen::Options;
+use crate::lp::LinearProgram;
+use crate::model::Model;
+use crate::parse_checked;
+
+#[test]
+fn relaxations_have_fractions_of_machines() {
+    let program = parse_checked(r#"
+        raw ore;
+        "smelt": 2 ore -(3)-> plate;
+        "gears": 2 plate -(1)-> gear;
+
+        target gears {
+            create 3 gear in 10;
+            minimize reactions;
+        }
+    "#).expect("parses");
+    let model = Model::new(&program, false);
+    let options = Options { integer: true, ..Options::default() };
+    let lp = LinearProgram::new(&model, &program.targets["gears"], &options).expect("is a linear program");
+
+    let solution = lp.solution(&lp.solve_relaxed().expect("has an optimum"));
+    let machines = |reaction: usize| solution.get(model.var_name(reaction)).expect("solved");
+    assert!((machines(0) - 1.8).abs() < 1e-6, "smelt: {}", machines(0));
+    assert!((machines(1) - 0.3).abs() < 1e-6, "gears: {}", machines(1));
+}
@@ -1,358 +1,2162 @@
+use std::cmp::{Ordering, Reverse};
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io;
+use std::fmt::Display;
 use std::io::Write;
-use std::fs::read_to_string;
-use std::path::PathBuf;
-use std::process::{Command, exit, Stdio};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
 use std::thread::available_parallelism;
+use std::time::{SystemTime, UNIX_EPOCH};
 use clap::Parser;
 use itertools::Itertools;
-use lalrpop_util::ParseError;
 use minos::{Label, Report, ReportKind, Source};
-use ast::{ReactionTerms, Symbol};
-use crate::ast::{Goal, Program, Target};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use reaction_solver::{ast, backend, codegen, expected_str, format, grammar, lp, minizinc, model, options, ortools, solution, sources, stamp, workdir, ChemParseError, Diagnostic, Failure};
+use crate::ast::{sum_error, sum_terms, Comparison, ConstraintKinds, Constants, Goal, Item, Priority, Quantity, Program, QuantumSubject, Reaction, ReactionTerms, Spanned, Symbol, Target};
+use crate::backend::{backend, register, Backend, Error, MiniZinc};
+use crate::ortools::OrTools;
+use crate::manifest::Manifest;
+use crate::sources::{FileKind, Sources};
+use crate::chart::render_chart;
+use crate::schedule::schedule;
+use crate::debugger::debug;
+use crate::format::NumberFormat;
+use crate::markdown::render_markdown;
+use crate::minizinc::{Outcome, Status};
+use crate::conditioning::{badly_conditioned, Coefficient, MAX_COEFFICIENT_RATIO};
+use crate::codegen::{generate_minizinc, invalid_report, Invalid, Options, relax_balance_var, relax_budget_var, relax_capacity_var, relax_machines_var, relax_target_var, RELAX_TRANSPORT_VAR, SUPPORT_EPSILON};
+use crate::lp::LinearProgram;
+use crate::model::Model;
+use crate::options::{Emit, OutputFormat, SolveOptions};
+use crate::solution::Solution;
+use crate::stamp::{hash, Stamp};
+use crate::text::render_text;
+use crate::workdir::{keep_work_dir, remove_work_dir};
+use crate::tree::{amount_str, render_tree};
 
-mod grammar;
-mod ast;
+mod cache;
+mod tree;
+mod manifest;
+mod chart;
+mod conditioning;
+mod markdown;
+mod text;
+mod debugger;
+mod schedule;
 
-const MINIZINC_OUTPUT_NAME: &str = "program.mzn";
+/// The name of the target made from the command line, when no target is given
+const COMMAND_LINE_TARGET: &str = "<command line>";
 
-pub fn merge_terms<'s>(a: ReactionTerms<'s>, b: ReactionTerms<'s>) -> ReactionTerms<'s> {
-    let mut res = HashMap::new();
+#[derive(clap::Parser)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// the chem file to work on
+    #[arg(value_name = "FILE", env="FILE", required = true)]
+    file: Option<PathBuf>,
+
+    /// The target to optimize, like `science`, or `science:cheap` for its goal named `cheap`.
+    /// Without one, a target is made from the command line (see --constraint)
+    #[arg(value_name = "TARGET", env="TARGET", required_unless_present_any = ["constraint", "all_targets"])]
+    target: Option<String>,
+
+    /// Solve every target of the chem file, parsing and indexing it only once, and solving targets
+    /// whose objectives others use only once. Exits with 1 if any of them has no solution
+    #[arg(long, conflicts_with_all = [
+        "target", "enable_group", "disable_group", "freeze", "sample", "all_optimal", "critical", "dry_run", "emit",
+        "load_solution", "diff_solution", "minimize_change", "save_solution", "chart", "bom", "slack", "feasible_only",
+        "maximize_constraint", "schedule_csv",
+    ])]
+    all_targets: bool,
+
+    #[command(flatten)]
+    options: SolveOptions,
+}
+
+#[derive(clap::Subcommand)]
+enum Commands {
+    /// Solve a profile of a project, described by a manifest listing its chem files
+    Build {
+        /// The project manifest
+        #[arg(long, value_name = "MANIFEST", default_value = "reactions.toml")]
+        manifest: PathBuf,
+
+        /// The profile to solve
+        #[arg(long, value_name = "PROFILE", default_value = "default")]
+        profile: String,
+
+        #[command(flatten)]
+        options: SolveOptions,
+    },
+    /// Solve all targets of a suite defined in a chem file, and compare their solutions
+    RunSuite {
+        /// the chem file to work on
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// The suite to run
+        #[arg(value_name = "SUITE")]
+        suite: String,
+
+        #[command(flatten)]
+        options: SolveOptions,
+    },
+    /// Solve every target of the chem files in a directory, like the examples of the DSL,
+    /// and fail if any of them has no solution. With --dry-run, only check that they generate a model
+    RunExamples {
+        /// The directory with the chem files
+        #[arg(value_name = "DIR", default_value = "examples")]
+        dir: PathBuf,
+
+        #[command(flatten)]
+        options: SolveOptions,
+    },
+    /// Find out why a target is unsatisfiable: switch its constraints off and on at a prompt,
+    /// and solve it again, until it's clear which of them conflict
+    Debug {
+        /// the chem file to work on
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// The target to debug
+        #[arg(value_name = "TARGET")]
+        target: String,
+
+        #[command(flatten)]
+        options: SolveOptions,
+    },
+    /// Show where a variable of a target's model comes from, like one named in a solver's log:
+    /// the reaction, or the item of the target, it was generated for
+    Explain {
+        /// the chem file to work on
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// The target whose model has the variable
+        #[arg(value_name = "TARGET")]
+        target: String,
+
+        /// The variable, like `machine_2iron_ore_into_1iron_plate`
+        #[arg(value_name = "VAR")]
+        var: String,
+
+        #[command(flatten)]
+        options: SolveOptions,
+    },
+}
+
+/// The exit code when a target has no solution, because it's unsatisfiable or unbounded.
+/// Errors, in the chem file or running the solver, exit with 1.
+const EXIT_INFEASIBLE: i32 = 2;
+
+/// Like [`exit_report`], for reporting that a target has no solution
+fn exit_infeasible(r: &Report, source: Source) -> ! {
+    r.eprint(source).expect("io error");
+    remove_work_dir();
+    exit(EXIT_INFEASIBLE);
+}
+
+/// The kind of reports about likely mistakes, which are errors with `--strict`
+fn warning_kind(args: &SolveOptions) -> ReportKind {
+    if args.strict {
+        ReportKind::Error
+    } else {
+        ReportKind::Warning
+    }
+}
+
+/// Print a report of [`warning_kind`], exiting with `--strict`
+fn warn(r: &Report, source: Source, args: &SolveOptions) {
+    if args.strict {
+        exit_report(r, source);
+    }
+    r.eprint(source).expect("io error");
+}
+
+fn main() {
+    let cli = Cli::parse();
+    register(&MiniZinc);
+    register(&OrTools);
+    #[cfg(feature = "clp")]
+    register(&reaction_solver::clp::Clp);
+
+    match cli.command {
+        Some(Commands::Build { manifest: path, profile, mut options }) => {
+            let manifest = Manifest::read(&path);
+            let profile = manifest.profile(&path, &profile);
+
+            options.enable_group.extend(profile.enable_groups.iter().cloned());
+            options.disable_group.extend(profile.disable_groups.iter().cloned());
+            options.integer |= profile.integer;
+            options.strict_branches |= profile.strict_branches;
+
+            let constants = Constants::new(
+                manifest.constants.iter()
+                    .chain(&profile.constants)
+                    .map(|(name, value)| (name.clone(), *value))
+                    .chain(options.define.iter().cloned())
+                    .collect(),
+            );
+
+            solve(&Sources::read(&manifest.source_paths(&path), &options.targets_file, &options.patch).unwrap_or_else(|f| exit_failure(f)), &constants, Some(&profile.target), &options);
+        }
+        Some(Commands::RunSuite { file, suite, options }) => {
+            let constants = Constants::new(options.define.iter().cloned().collect());
+            run_suite(&Sources::read(&[file], &options.targets_file, &options.patch).unwrap_or_else(|f| exit_failure(f)), &constants, &suite, &options);
+        }
+        Some(Commands::RunExamples { dir, options }) => run_examples(&dir, &options),
+        Some(Commands::Debug { file, target, options }) => {
+            let constants = Constants::new(options.define.iter().cloned().collect());
+            debug(&Sources::read(&[file], &options.targets_file, &options.patch).unwrap_or_else(|f| exit_failure(f)), &constants, &target, &options);
+        }
+        Some(Commands::Explain { file, target, var, options }) => {
+            let constants = Constants::new(options.define.iter().cloned().collect());
+            explain(&Sources::read(&[file], &options.targets_file, &options.patch).unwrap_or_else(|f| exit_failure(f)), &constants, &target, &var, &options);
+        }
+        None => {
+            let Some(file) = cli.file else {
+                unreachable!("required by clap");
+            };
+
+            let constants = Constants::new(cli.options.define.iter().cloned().collect());
+            let sources = Sources::read(&[file], &cli.options.targets_file, &cli.options.patch).unwrap_or_else(|f| exit_failure(f));
+            match cli.all_targets {
+                true => solve_all_targets(&sources, &constants, &cli.options),
+                false => solve(&sources, &constants, cli.target.as_deref(), &cli.options),
+            }
+        }
+    }
+
+    remove_work_dir();
+}
+
+fn solve<'a>(sources: &'a Sources, constants: &Constants, target_name: Option<&'a str>, args: &'a SolveOptions) {
+    let (mut program, target_name) = prepare(sources, constants, target_name, args);
+    let frozen = args.freeze.as_deref().map(|name| solve_frozen(sources, &mut program, name, args));
+    let frozen = frozen.as_ref();
+    if let (Some(symbol), false) = (&args.maximize_constraint, args.dry_run) {
+        maximize_constraint(sources, &mut program, target_name, Symbol(symbol), args, frozen);
+    }
+    let target = &program.targets[target_name];
+    if !program.raw.is_empty() || args.strict {
+        check_raw(sources, &program, target, args);
+    }
+    if args.strict {
+        check_unused_reactions(sources, &program, args);
+    }
+    if !program.compositions.is_empty() {
+        warn_unbalanced(sources, &program, args);
+    }
+
+    if let (Some((smallest, largest)), false) = (badly_conditioned(&program, target), args.auto_scale) {
+        warn_conditioning(sources, smallest, largest, args);
+    }
+    let stamp = stamp(sources, constants, target, args);
+
+    if let (Some(samples), false) = (args.sample, args.dry_run) {
+        sample(sources, &program, target, args, frozen, samples, &stamp);
+        return;
+    }
+    if let (Some(cap), false) = (args.all_optimal, args.dry_run) {
+        all_optimal(sources, &program, target, args, frozen, cap);
+        return;
+    }
+    if args.critical && !args.dry_run {
+        critical_reactions(sources, &program, target, args, frozen);
+        return;
+    }
+
+    let loaded = args.load_solution.as_deref().map(|path| load_solution(path, sources, &program, target, args, &stamp));
+    let previous = args.diff_solution.as_deref().map(|path| load_solution(path, sources, &program, target, args, &stamp));
+    let baseline = args.minimize_change.as_deref().map(|path| load_solution(path, sources, &program, target, args, &stamp));
+
+    let options = Options {
+        integer: args.integer,
+        big_m: args.big_m,
+        strict_branches: args.strict_branches,
+        bill_of_materials: args.bom,
+        annotate: args.annotate,
+        auto_scale: args.auto_scale,
+        slack: args.slack,
+        frozen,
+        fixed: loaded.as_ref(),
+        baseline: baseline.as_ref(),
+        stamp: Some(&stamp),
+        feasible_only: args.feasible_only,
+        ..Options::default()
+    };
+
+    if let Some(Emit::Mzn) = args.emit {
+        let (text, _) = generate_minizinc(sources, &Model::new(&program, args.strict_branches), target, &options)
+            .unwrap_or_else(|e| exit_invalid(sources, e));
+        if let Err(e) = std::io::stdout().lock().write_all(text.as_bytes()) {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("while writing the model: {e}"))
+                    .finish(),
+                Source::from(String::new())
+            );
+        }
+        return;
+    }
+
+    let previous_or_baseline = previous.as_ref().or(baseline.as_ref());
+    if args.dry_run {
+        // the model is for running the command yourself
+        let model = Model::new(&program, args.strict_branches);
+        let dry_run = chosen_backend(sources, &args.backend)
+            .dry_run(sources, &model, target, &Options { warm_start: previous_or_baseline, ..options }, args)
+            .unwrap_or_else(|e| exit_error(sources, &args.backend, e));
+        keep_work_dir();
+
+        println!("model: {}", dry_run.model.display());
+        for cmd in dry_run.commands {
+            println!(
+                "command: {}",
+                std::iter::once(cmd.get_program())
+                    .chain(cmd.get_args())
+                    .map(|i| shell_escape(&i.to_string_lossy()))
+                    .format(" ")
+            );
+        }
+        return;
+    }
+
+    // the solver starts from the previous solution, or else with --integer, from the best one with fractions of machines
+    let relaxation = match (previous_or_baseline, args.prune_threshold) {
+        (None, _) | (_, Some(_)) if args.integer => relaxed_solution(sources, &program, target, &options, args),
+        _ => None,
+    };
+    let warm_start = previous_or_baseline.or(relaxation.as_ref());
+
+    let run = |options: &Options| solve_model(sources, &program, target, options, args);
+    let pruned = pruned_reactions(&program, relaxation.as_ref(), args);
+    let mut outcome = run(&Options { warm_start, excluded_reactions: &pruned, ..options });
+    if !pruned.is_empty() {
+        match outcome.solution {
+            Some(_) => outcome.statistics.push(("prunedReactions".to_string(), pruned.len().into())),
+            // some of them are needed after all
+            None => outcome = run(&Options { warm_start, ..options }),
+        }
+    }
+    let mut options = options;
+
+    // an unbounded or garbled result is often numerical trouble of the solver, which bounding the machines,
+    // or otherwise only allowing whole numbers of them, can get around
+    if let Some(trouble) = numerical_trouble(&outcome, &program, args, None) {
+        let fallbacks = [
+            (Options { bounded: true, ..options }, format!("with the machines of every reaction bounded by --big-m ({})", args.big_m)),
+            (Options { bounded: true, integer: true, ..options }, "with bounded, whole numbers of machines".to_string()),
+        ];
+        // with --integer, bounding the machines is all that's left to try
+        let fallbacks = &fallbacks[..if args.integer { 1 } else { 2 }];
+
+        for (fallback, how) in fallbacks {
+            let retried = run(fallback);
+            if numerical_trouble(&retried, &program, args, Some(fallback.big_m)).is_some() {
+                continue;
+            }
+
+            Report::build(ReportKind::Warning)
+                .with_message(format!("the solver {trouble}, so target {} was solved again {how}", target.name))
+                .with_label(sources.label(target.span).with_message("this target"))
+                .with_note(match fallback.integer {
+                    true => "the solution may need more machines than the best one with fractions of machines",
+                    false => "no reaction needs as many machines as the bound, so the solution isn't limited by it",
+                })
+                .finish()
+                .eprint(sources.source(target.span))
+                .expect("io error");
+            (outcome, options) = (retried, Options { ..*fallback });
+            break;
+        }
+    }
+
+    if outcome.status == Status::Unsatisfiable && options.fixed.is_none() {
+        if let Some(relaxed) = drop_groups(sources, &mut program, target_name, args, &options) {
+            outcome = relaxed;
+        }
+    }
+    let target = &program.targets[target_name];
+
+    match outcome.status {
+        Status::Unsatisfiable => suggest_relaxations(sources, &program, target, args, &options),
+        Status::Unbounded | Status::UnsatisfiableOrUnbounded => exit_infeasible(
+            &Report::build(ReportKind::Error)
+                .with_message(match outcome.status {
+                    Status::Unbounded => format!("target {} is unbounded", target.name),
+                    _ => format!("target {} is unsatisfiable or unbounded, the solver can't tell which", target.name),
+                })
+                .with_label(sources.label(target.span).with_message("this target"))
+                .with_help("an unbounded goal can get better without limit, like when reactions create more of a minimized resource than they use")
+                .finish(),
+            sources.source(target.span)
+        ),
+        _ => {}
+    }
+    print_output(&outcome, sources, &program, target, args, previous_or_baseline, &stamp);
+}
+
+/// Solve each target of the program for `--all-targets`, showing the solutions in the order of the targets.
+/// Unlike solving them one by one, the program is parsed and indexed once for all of them.
+fn solve_all_targets(sources: &Sources, constants: &Constants, args: &SolveOptions) {
+    let mut program = parse(sources, constants, args);
+    let names = program.targets.values().sorted_by_key(|t| t.span).map(|t| t.name).collect_vec();
+    for target in program.targets.values_mut() {
+        compose_target(target, constants, args);
+        default_goal(sources, target, constants, args);
+        warn_shadowed_inputs(sources, target, args);
+        warn_goal_weights(sources, &program.reactions, target, args);
+    }
+    let mut solved = HashMap::new();
+    for &name in &names {
+        resolve_objectives(sources, &mut program, name, args, &mut Vec::new(), &mut solved);
+    }
+
+    // targets whose objectives others use are solved already
+    let model = Model::new(&program, args.strict_branches);
+    let mut failed = Vec::new();
+    for (idx, &name) in names.iter().enumerate() {
+        let target = &program.targets[name];
+        let outcome = solved
+            .remove(name)
+            .unwrap_or_else(|| solve_with(sources, &model, target, &base_options(args), args));
+
+        if idx > 0 {
+            println!();
+        }
+        println!("target {name}:");
+        match outcome.solution {
+            Some(_) => print_output(&outcome, sources, &program, target, args, None, &stamp(sources, constants, target, args)),
+            None => {
+                println!("{}", unsolved(outcome.status));
+                failed.push(name);
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        println!();
+        println!("failed: {}", failed.join(", "));
+        remove_work_dir();
+        exit(1);
+    }
+}
+
+/// Parse the program, and get the target ready to solve: made from the command line without a name,
+/// with the groups and changes the options ask for, and the objectives of other targets its constraints use.
+/// Returns the program with the target, and the target's name.
+fn prepare<'a>(sources: &'a Sources, constants: &Constants, target_name: Option<&'a str>, args: &'a SolveOptions) -> (Program<'a>, &'a str) {
+    let mut program = parse(sources, constants, args);
+
+    let target_name = target_name.unwrap_or_else(|| {
+        program.targets.insert(COMMAND_LINE_TARGET, Target {
+            inputs: Vec::new(),
+            constraints: HashMap::new(),
+            in_time: 1,
+            name: COMMAND_LINE_TARGET,
+            goal: None,
+            groups: Vec::new(),
+            preferences: Vec::new(),
+            quanta: Vec::new(),
+            transport_weight: None,
+            transport_budget: None,
+            budgets: HashMap::new(),
+            features: Vec::new(),
+            linear_constraints: Vec::new(),
+            input_spans: HashMap::new(),
+            constraint_spans: HashMap::new(),
+            comparisons: HashMap::new(),
+            windows: HashMap::new(),
+            goal_span: None,
+            goal_spans: HashMap::new(),
+            variants: Vec::new(),
+            span: (0, 0),
+            name_span: (0, 0),
+        });
+        COMMAND_LINE_TARGET
+    });
+    // `target:variant` picks a named goal of the target
+    let (target_name, variant) = match target_name.split_once(':') {
+        Some((target_name, variant)) => (target_name, Some(variant)),
+        None => (target_name, None),
+    };
+
+    let Some(target) = program.targets.get_mut(target_name) else {
+        let targets = program.targets.values().sorted_by_key(|t| t.name_span).collect_vec();
+        let help = if targets.is_empty() {
+            "no targets are defined".to_string()
+        } else {
+            format!("did you mean {}", expected_str("", targets.iter().map(|t| t.name)))
+        };
+
+        // a report shows one file, so only the targets in the same file as the first are labeled
+        let file = targets.first().map(|t| sources.file_name(t.name_span.0));
+        let report = targets.iter().filter(|t| Some(sources.file_name(t.name_span.0)) == file).fold(
+            Report::build(ReportKind::Error).with_message(format!("target '{target_name}' not found")),
+            |report, t| report.with_label(
+                sources.label(t.name_span).with_message(format!("'{}' is defined here", t.name))
+            ),
+        );
+
+        exit_report(
+            &report
+                .with_help(help)
+                .finish(),
+            sources.source(targets.first().map_or((0, 0), |t| t.name_span))
+        );
+    };
+
+    if let Some(variant) = variant {
+        let Some(picked) = target.variants.iter().find(|v| v.name == variant) else {
+            let help = match target.variants.is_empty() {
+                true => format!("add one, like `minimize {variant}: reactions;`"),
+                false => format!("did you mean {}", expected_str("", target.variants.iter().map(|v| v.name))),
+            };
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("no goal '{variant}' in target {}", target.name))
+                    .with_label(sources.label(target.span).with_message("in this target"))
+                    .with_help(help)
+                    .finish(),
+                sources.source(target.span)
+            );
+        };
+
+        target.goal = Some(picked.goal.clone());
+        target.goal_span = Some(picked.span);
+        target.goal_spans = picked.goal_spans.clone();
+    }
+
+    let toggles = args.enable_group.iter().map(|g| (g, true))
+        .chain(args.disable_group.iter().map(|g| (g, false)));
+    for (name, enabled) in toggles {
+        let Some(group) = target.groups.iter_mut().find(|g| g.name == name.as_str()) else {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("no constraint group '{name}' in target {}", target.name))
+                    .with_label(sources.label(target.span).with_message("in this target"))
+                    .with_help(format!("did you mean {}", expected_str("", target.groups.iter().map(|g| &g.name))))
+                    .finish(),
+                sources.source(target.span)
+            );
+        };
+
+        group.enabled = enabled;
+    }
+
+    compose_target(target, constants, args);
+    default_goal(sources, target, constants, args);
+    warn_shadowed_inputs(sources, target, args);
+    warn_goal_weights(sources, &program.reactions, target, args);
+    resolve_objectives(sources, &mut program, target_name, args, &mut Vec::new(), &mut HashMap::new());
+
+    (program, target_name)
+}
+
+/// The best solution with fractions of machines, solved in-process as a linear program.
+/// Targets that can't be one, like with zones, are solved with the backend instead
+fn relaxed_solution(sources: &Sources, program: &Program, target: &Target, options: &Options, args: &SolveOptions) -> Option<Solution> {
+    let options = Options { integer: false, ..*options };
+    let model = Model::new(program, options.strict_branches);
+    match LinearProgram::new(&model, target, &options) {
+        Ok(lp) => lp.solve_relaxed().map(|values| lp.solution(&values)),
+        Err(_) => solve_with(sources, &model, target, &options, args).solution,
+    }
+}
+
+/// The reactions `--prune-threshold` leaves out, going by the float relaxation
+fn pruned_reactions(program: &Program, relaxation: Option<&Solution>, args: &SolveOptions) -> Vec<usize> {
+    let (Some(threshold), Some(relaxation)) = (args.prune_threshold, relaxation) else {
+        return Vec::new();
+    };
+
+    let model = Model::new(program, args.strict_branches);
+    (0..program.reactions.len())
+        .filter(|&r| relaxation.get(model.var_name(r)).unwrap_or(0.0) <= threshold)
+        .collect()
+}
+
+/// Show where a variable of a target's model comes from, going by the source map of the model
+fn explain(sources: &Sources, constants: &Constants, target_name: &str, var: &str, args: &SolveOptions) {
+    let (program, target_name) = prepare(sources, constants, Some(target_name), args);
+    let target = &program.targets[target_name];
+    let model = Model::new(&program, args.strict_branches);
+    let options = Options { bill_of_materials: args.bom, slack: args.slack, ..base_options(args) };
+
+    let (generated, source_map) = generate_minizinc(sources, &model, target, &options).unwrap_or_else(|e| exit_invalid(sources, e));
+    // elements of arrays, like `segment_x[2]`, come from where the array is declared
+    let name = var.split_once('[').map_or(var, |(name, _)| name);
+    let Some(line) = generated.lines().position(|line| declared(line) == Some(name)) else {
+        exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("the model of target {target_name} has no variable '{name}'"))
+                .with_label(sources.label(target.span).with_message("this target"))
+                .with_help("see the variables there are with --emit mzn, with the same options")
+                .finish(),
+            sources.source(target.span)
+        );
+    };
+
+    let Some(span) = source_map.lookup(line + 1) else {
+        println!("{var}: generated for the model of target {target_name} as a whole");
+        return;
+    };
+    let what = match program.reactions.iter().position(|r| r.span == span) {
+        Some(reaction) => format!("reaction {}", model.name(reaction)),
+        None if target.span.0 <= span.0 && span.1 <= target.span.1 => format!("target {target_name}"),
+        None => "the item".to_string(),
+    };
+    println!("{var}: {what} at {}", sources.location(span.0));
+    for line in sources.text()[span.0..span.1].lines() {
+        println!("    {line}");
+    }
+}
+
+/// The variable a line of a model declares, like `x` of `var float: x = 2 * y;`
+fn declared(line: &str) -> Option<&str> {
+    if !line.starts_with("var ") && !line.starts_with("array") {
+        return None;
+    }
+    let (_, rest) = line.split_once(": ")?;
+    rest.split(|c: char| !c.is_alphanumeric() && c != '_').next()
+}
+
+/// Why the outcome of solving looks like numerical trouble, rather than something to show:
+/// the model being unbounded, or a solution with negative or non-finite machine counts.
+/// With a `bound` on the machines, solutions reaching it mean the model is unbounded after all.
+fn numerical_trouble(outcome: &Outcome, program: &Program, args: &SolveOptions, bound: Option<f64>) -> Option<String> {
+    match outcome.status {
+        Status::Unbounded => return Some("reported the model to be unbounded".to_string()),
+        Status::UnsatisfiableOrUnbounded => return Some("reported the model to be unsatisfiable or unbounded".to_string()),
+        _ => {}
+    }
+
+    let solution = outcome.solution.as_ref()?;
+    let model = Model::new(program, args.strict_branches);
+    (0..program.reactions.len()).find_map(|reaction| {
+        let machines = solution.get(model.var_name(reaction))?;
+        let name = model.name(reaction);
+
+        if !machines.is_finite() || machines < -SUPPORT_EPSILON {
+            Some(format!("returned {machines} machines for {name}"))
+        } else if bound.is_some_and(|bound| machines >= bound * (1.0 - SUPPORT_EPSILON)) {
+            Some(format!("needed as many machines for {name} as the bound allows"))
+        } else {
+            None
+        }
+    })
+}
+
+/// Past this many, `--maximize-constraint` stops looking for more the target can create
+const MAX_SEARCHED_AMOUNT: usize = 1 << 40;
+
+/// Find the most of a symbol the target can create for `--maximize-constraint`: double the amount in its constraint
+/// on the symbol while the target is feasible, and then bisect between the last feasible and the first infeasible
+/// amount. Only feasibility is solved for, the goal doesn't matter. The constraint is set to the most it can create.
+fn maximize_constraint<'s>(sources: &Sources, program: &mut Program<'s>, target_name: &str, symbol: Symbol<'s>, args: &SolveOptions, frozen: Option<&Solution>) {
+    let target = &program.targets[target_name];
+    let (name, span) = (target.name, target.span);
+    let fail = |message: String, help: String| -> ! {
+        exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(message)
+                .with_label(sources.label(span).with_message("this target"))
+                .with_help(help)
+                .finish(),
+            sources.source(span)
+        );
+    };
+    let Some(&start) = target.constraints.get(&symbol) else {
+        fail(
+            format!("target {name} has no constraint on {} to maximize", symbol.0),
+            match target.constraints.is_empty() {
+                true => format!("add one, like `create {};`", symbol.0),
+                false => format!("did you mean {}", expected_str("", target.constraints.keys().map(|s| s.0).sorted())),
+            },
+        );
+    };
+    if target.comparison(&symbol) == Comparison::AtMost {
+        fail(
+            format!("the constraint on {} in target {name} is an upper bound, which any amount meets", symbol.0),
+            "maximize a constraint creating at least or exactly some amount".to_string(),
+        );
+    }
+
+    let options = Options { feasible_only: true, frozen, ..base_options(args) };
+    let mut feasible = |amount: usize| {
+        let target = program.targets.get_mut(target_name).expect("checked above");
+        target.constraints.insert(symbol, amount);
+        let target = &program.targets[target_name];
+        let outcome = solve_model(sources, program, target, &options, args);
+        match (outcome.status, outcome.solution) {
+            (_, Some(_)) => true,
+            (Status::Unsatisfiable, None) => false,
+            (status, None) => exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("while looking for the most {} target {} can create, with {amount}: {}", symbol.0, target.name, unsolved(status)))
+                    .with_label(sources.label(target.span).with_message("this target"))
+                    .finish(),
+                sources.source(target.span)
+            ),
+        }
+    };
+
+    // the most known to be feasible, and the least known not to be
+    let (mut low, mut high) = (None, start.max(1));
+    while feasible(high) {
+        low = Some(high);
+        if high >= MAX_SEARCHED_AMOUNT {
+            fail(
+                format!("target {name} can create any amount of {}, at least {high}", symbol.0),
+                "nothing limits it: bound what making it costs, like with `objective <= 100;` and the reactions goal".to_string(),
+            );
+        }
+        high = high.saturating_mul(2);
+    }
+    let mut low = match low {
+        Some(low) => low,
+        None if feasible(0) => 0,
+        None => fail(
+            format!("target {name} is unsatisfiable even without creating {}", symbol.0),
+            "solve it without --maximize-constraint to find out why".to_string(),
+        ),
+    };
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        match feasible(middle) {
+            true => low = middle,
+            false => high = middle,
+        }
+    }
+
+    let target = program.targets.get_mut(target_name).expect("checked above");
+    target.constraints.insert(symbol, low);
+    println!("the most {} target {name} can create: {low} in {}", symbol.0, target.window(&symbol));
+    println!();
+}
+
+/// For an unsatisfiable target, disable its groups of constraints that aren't hard one by one, those
+/// of low priority first and the last in the target first, until it isn't unsatisfiable anymore.
+/// Warns about the groups given up, or enables them again if it's unsatisfiable without all of them.
+fn drop_groups(sources: &Sources, program: &mut Program, target_name: &str, args: &SolveOptions, options: &Options) -> Option<Outcome> {
+    let order = program.targets[target_name].groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| group.enabled && group.priority != Priority::Hard)
+        .sorted_by_key(|&(idx, group)| (group.priority, Reverse(idx)))
+        .map(|(idx, _)| idx)
+        .collect_vec();
+
+    for (dropped, &idx) in order.iter().enumerate() {
+        program.targets.get_mut(target_name).expect("exists").groups[idx].enabled = false;
+        let target = &program.targets[target_name];
+        let outcome = solve_model(sources, program, target, options, args);
+        if outcome.status == Status::Unsatisfiable {
+            continue;
+        }
+
+        let groups = order[..=dropped].iter().map(|&idx| {
+            let group = &target.groups[idx];
+            format!("'{}' ({} priority)", group.name, if group.priority == Priority::Low { "low" } else { "high" })
+        });
+        Report::build(ReportKind::Warning)
+            .with_message(format!("target {} is unsatisfiable with all its constraints, so groups of them were given up", target.name))
+            .with_label(sources.label(target.span).with_message("this target"))
+            .with_note(format!("given up: {}", groups.format(", ")))
+            .finish()
+            .eprint(sources.source(target.span))
+            .expect("io error");
+        return Some(outcome);
+    }
+
+    for idx in order {
+        program.targets.get_mut(target_name).expect("exists").groups[idx].enabled = true;
+    }
+    None
+}
+
+/// Solve a version of the model where constraints may be violated, minimizing by how much,
+/// and report the target as unsatisfiable with what would need to change to make it feasible
+fn suggest_relaxations(sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, options: &Options) -> ! {
+    let elastic = Options {
+        elastic: true,
+        warm_start: None,
+        feasible_only: false,
+        ..*options
+    };
+    let report = Report::build(ReportKind::Error)
+        .with_message(match options.fixed {
+            Some(_) => format!("the saved solution doesn't meet target {}", target.name),
+            None => format!("target {} is unsatisfiable", target.name),
+        })
+        .with_label(sources.label(target.span).with_message("this target"));
+    let Some(solution) = solve_model(sources, program, target, &elastic, args).solution else {
+        exit_infeasible(&report.finish(), sources.source(target.span));
+    };
+    let model = Model::new(program, args.strict_branches);
+    let zone_name = |zone: usize| program.zones.get(zone).map_or("outside of any zone".to_string(), |z| format!("in zone {}", z.name));
+
+    // how much each relaxation variable the model can have is used, if at all
+    let relaxed = |var: &str| solution.get(var).filter(|&x| x > SUPPORT_EPSILON);
+    let mut needed = Vec::new();
+
+    for (symbol, scalar) in target.active_constraints().into_iter().sorted_by_key(|(s, _)| s.0) {
+        if let Some(x) = relaxed(&relax_target_var(&symbol)) {
+            // the rate the constraint requires, which with a time goal depends on the throughput solved for
+            let rate = match target.goal {
+                Some(Goal::Time) => solution.get("throughput").map_or(0.0, |throughput| scalar as f64 * throughput),
+                _ => scalar as f64 / target.window(&symbol) as f64,
+            };
+            let (verb, less) = match target.comparison(&symbol) {
+                Comparison::AtMost => ("allow", "more"),
+                _ => ("create", "less"),
+            };
+            needed.push(match rate > 0.0 {
+                true => format!("{verb} {:.1}% {less} {} ({} {less} per time unit)", x / rate * 100.0, symbol.0, amount_str(x)),
+                false => format!("{verb} {} {less} {} per time unit", amount_str(x), symbol.0),
+            });
+        }
+    }
+    for id in model.symbols() {
+        let symbol = model.symbol(id);
+        let places = if program.zones.is_empty() {
+            vec![(None, String::new())]
+        } else {
+            (0..=program.zones.len()).map(|zone| (Some(zone), format!(" {}", zone_name(zone)))).collect()
+        };
+
+        for (zone, place) in places {
+            if let Some(x) = relaxed(&relax_balance_var(&symbol, zone)) {
+                needed.push(format!("{} more {} per time unit{place}, e.g. by adding it as an input", amount_str(x), symbol.0));
+            }
+        }
+    }
+    for zone in 0..program.zones.len() {
+        if let Some(x) = relaxed(&relax_capacity_var(zone)) {
+            needed.push(format!("{} more machines {}", amount_str(x), zone_name(zone)));
+        }
+    }
+    for reaction in 0..program.reactions.len() {
+        if let Some(x) = relaxed(&relax_machines_var(model.var_name(reaction))) {
+            let name = model.name(reaction);
+            needed.push(format!("{} more machines for {name}", amount_str(x)));
+        }
+    }
+    for (name, _) in target.budgets.iter().sorted_by_key(|(name, _)| **name) {
+        if let Some(x) = relaxed(&relax_budget_var(name)) {
+            needed.push(format!("a {name} budget {} higher", amount_str(x)));
+        }
+    }
+    if let Some(x) = relaxed(RELAX_TRANSPORT_VAR) {
+        needed.push(format!("a transport budget {} higher", amount_str(x)));
+    }
+
+    let report = match needed.is_empty() {
+        true => report,
+        false => report.with_help(format!("it would be feasible with:\n{}", needed.iter().map(|n| format!("    {n}")).join("\n"))),
+    };
+    exit_infeasible(&report.finish(), sources.source(target.span));
+}
+
+/// Solve every target of a suite, and show their solutions side by side
+fn run_suite(sources: &Sources, constants: &Constants, suite_name: &str, args: &SolveOptions) {
+    let mut program = parse(sources, constants, args);
+
+    let Some(suite) = program.suites.remove(suite_name) else {
+        let help = if program.suites.is_empty() {
+            "no suites are defined".to_string()
+        } else {
+            format!("did you mean {}", expected_str("", program.suites.keys().sorted()))
+        };
+
+        exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("suite '{suite_name}' not found"))
+                .with_help(help)
+                .finish(),
+            Source::from(String::new())
+        );
+    };
+
+    for &(name, span) in &suite.targets {
+        let Some(target) = program.targets.get_mut(name) else {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("target '{name}' not found"))
+                    .with_label(sources.label(span).with_message(format!("in suite {}", suite.name)))
+                    .finish(),
+                sources.source(span)
+            );
+        };
+
+        // the suite's settings, and then those on the command line, override the target's
+        if let Some(in_time) = suite.in_time {
+            target.in_time = in_time;
+        }
+        if let Some(goal) = &suite.goal {
+            target.goal = Some(goal.clone());
+            target.goal_span = None;
+            target.goal_spans.clear();
+        }
+        compose_target(target, constants, args);
+        default_goal(sources, target, constants, args);
+        warn_shadowed_inputs(sources, target, args);
+        warn_goal_weights(sources, &program.reactions, target, args);
+    }
+    let mut solved = HashMap::new();
+    for &(name, _) in &suite.targets {
+        resolve_objectives(sources, &mut program, name, args, &mut Vec::new(), &mut solved);
+    }
+
+    // targets whose objectives others use are solved already
+    let model = Model::new(&program, args.strict_branches);
+    let solutions = suite.targets
+        .iter()
+        .map(|&(name, _)| match solved.remove(name) {
+            Some(outcome) => outcome.solution,
+            None => solve_with(sources, &model, &program.targets[name], &base_options(args), args).solution,
+        })
+        .collect_vec();
+
+    let format = args.number_format();
+    let used = |reaction: usize| solutions.iter().flatten().any(|s| s.get(model.var_name(reaction)).unwrap_or(0.0) > 0.0);
+    let mut rows = (0..program.reactions.len())
+        .filter(|&reaction| used(reaction))
+        .map(|reaction| {
+            let var_name = model.var_name(reaction);
+            let values = solutions.iter().map(|solution| match solution {
+                Some(solution) => solution.get(var_name).filter(|&m| m > 0.0).map_or("-".to_string(), |m| format.amount(m)),
+                None => "-".to_string(),
+            });
+
+            (model.name(reaction).to_string(), values.collect_vec())
+        })
+        .collect_vec();
+
+    let objectives = solutions.iter().map(|solution| match solution {
+        Some(solution) => solution.get("objective").map_or("-".to_string(), |o| format.amount(o)),
+        None => "infeasible".to_string(),
+    });
+    rows.push(("objective".to_string(), objectives.collect()));
+    if suite.targets.iter().any(|&(name, _)| matches!(program.targets[name].goal, Some(Goal::Time))) {
+        let times = solutions.iter().map(|solution| {
+            solution.as_ref().and_then(|s| s.get("throughput")).filter(|&t| t > 0.0).map_or("-".to_string(), |t| format.amount(1.0 / t))
+        });
+        rows.push(("time".to_string(), times.collect()));
+    }
+
+    let name_width = rows.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let widths = suite.targets
+        .iter()
+        .enumerate()
+        .map(|(idx, (name, _))| rows.iter().map(|(_, values)| values[idx].chars().count()).chain([name.chars().count()]).max().unwrap_or(0))
+        .collect_vec();
+
+    println!("suite {}:", suite.name);
+    println!("{:name_width$}  {}", "", suite.targets.iter().zip(&widths).map(|((name, _), width)| format!("{name:>width$}")).join("  "));
+    for (name, values) in rows {
+        println!("{name:<name_width$}  {}", values.iter().zip(&widths).map(|(value, width)| format!("{value:>width$}")).join("  "));
+    }
+}
+
+/// Solve each target of each chem file in a directory, reporting how every one went.
+/// Exits with 1 if any has no solution, so the examples double as tests of the whole solver.
+fn run_examples(dir: &Path, args: &SolveOptions) {
+    let files = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "chem"))
+            .sorted()
+            .collect_vec(),
+        Err(e) => {
+            let name = dir.to_string_lossy().to_string();
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("{e}"))
+                    .with_label(Label::new(0..name.chars().count()).with_message("while reading this directory"))
+                    .finish(),
+                Source::from(name)
+            );
+        }
+    };
+
+    let constants = Constants::new(args.define.iter().cloned().collect());
+    let options = Options { feasible_only: args.feasible_only, ..base_options(args) };
+    let mut solved = 0;
+    let mut failed = Vec::new();
+
+    for file in files {
+        let sources = Sources::read(std::slice::from_ref(&file), &args.targets_file, &args.patch).unwrap_or_else(|f| exit_failure(f));
+        let names = parse(&sources, &constants, args).targets.into_keys().sorted().collect_vec();
+
+        for name in names {
+            // each target is prepared from scratch, as the settings of one could leak into the next
+            let (program, name) = prepare(&sources, &constants, Some(name), args);
+            let target = &program.targets[name];
+            let (ok, result) = if args.dry_run {
+                chosen_backend(&sources, &args.backend)
+                    .dry_run(&sources, &Model::new(&program, args.strict_branches), target, &options, args)
+                    .unwrap_or_else(|e| exit_error(&sources, &args.backend, e));
+                (true, "generated a model".to_string())
+            } else {
+                let outcome = solve_model(&sources, &program, target, &options, args);
+                match (outcome.status, outcome.solution) {
+                    (Status::Optimal | Status::Satisfied, Some(solution)) => {
+                        let objective = solution.get("objective").map_or("-".to_string(), |o| args.number_format().amount(o));
+                        (true, format!("solved, objective {objective}"))
+                    }
+                    (status, _) => (false, unsolved(status).to_string()),
+                }
+            };
+
+            if ok {
+                solved += 1;
+            } else {
+                failed.push(format!("{} {name}", file.display()));
+            }
+            println!("{}: target {name}: {result}", file.display());
+        }
+    }
+
+    println!();
+    println!("{solved} of {} targets {}", solved + failed.len(), if args.dry_run { "generated a model" } else { "solved" });
+    if !failed.is_empty() {
+        println!("failed: {}", failed.join(", "));
+        remove_work_dir();
+        exit(1);
+    }
+}
+
+/// Why solving a target gave no solution
+fn unsolved(status: Status) -> &'static str {
+    match status {
+        Status::Unsatisfiable => "unsatisfiable",
+        Status::Unbounded => "unbounded",
+        Status::UnsatisfiableOrUnbounded => "unsatisfiable or unbounded",
+        _ => "no solution found",
+    }
+}
+
+/// Solve the targets whose objectives the constraints of a target use, dependencies first,
+/// and substitute their objectives into the constraints. `visiting` are the targets waiting
+/// for this one, and `solved` the outcomes of the targets solved so far, which all have an objective.
+/// They're solved with the options of [`base_options`], so callers solving them with those can reuse them.
+fn resolve_objectives<'s>(
+    sources: &Sources,
+    program: &mut Program<'s>,
+    target_name: &'s str,
+    args: &SolveOptions,
+    visiting: &mut Vec<&'s str>,
+    solved: &mut HashMap<&'s str, Outcome>,
+) {
+    let references = program.targets[target_name].linear_constraints
+        .iter()
+        .flat_map(|c| c.references())
+        .collect_vec();
+
+    visiting.push(target_name);
+    for (name, span) in references {
+        if solved.contains_key(name) {
+            continue;
+        }
+        if visiting.contains(&name) {
+            let cycle = visiting.iter().skip_while(|&&t| t != name).chain([&name]).join(" -> ");
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("cyclic objective constraints: {cycle}"))
+                    .with_label(sources.label(span).with_message(format!("{target_name} needs the objective of {name}")))
+                    .finish(),
+                sources.source(span)
+            );
+        }
+        if !program.targets.contains_key(name) {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("target '{name}' not found"))
+                    .with_label(sources.label(span).with_message(format!("its objective is used by {target_name}")))
+                    .finish(),
+                sources.source(span)
+            );
+        }
+
+        resolve_objectives(sources, program, name, args, visiting, solved);
+
+        let outcome = solve_model(sources, program, &program.targets[name], &base_options(args), args);
+        if objective(&outcome).is_none() {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("target {name} is infeasible, so it has no objective"))
+                    .with_label(sources.label(span).with_message(format!("used by {target_name}")))
+                    .finish(),
+                sources.source(span)
+            );
+        }
+        solved.insert(name, outcome);
+    }
+    visiting.pop();
+
+    let Some(target) = program.targets.get_mut(target_name) else {
+        unreachable!("checked by the caller");
+    };
+    for constraint in &mut target.linear_constraints {
+        for (scale, r) in constraint.lhs.iter_mut().chain(&mut constraint.rhs) {
+            if let Quantity::Of(name, _) = r {
+                *scale *= objective(&solved[name]).expect("checked when solved");
+                *r = Quantity::One;
+            }
+        }
+
+        // with only numbers left, the constraint already holds or it doesn't
+        let variable = |terms: &[(f64, Quantity)]| terms.iter().any(|(_, r)| !matches!(r, Quantity::One));
+        if variable(&constraint.lhs) || variable(&constraint.rhs) {
+            continue;
+        }
+        let (lhs, rhs) = (constraint.lhs.iter().map(|(s, _)| s).sum::<f64>(), constraint.rhs.iter().map(|(s, _)| s).sum::<f64>());
+        if (lhs <= rhs) != constraint.at_most && lhs != rhs {
+            let comparison = if constraint.at_most { ">" } else { "<" };
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("constraint of target {} doesn't hold", target.name))
+                    .with_label(sources.label(constraint.span).with_message(format!(
+                        "this is {} {comparison} {}",
+                        amount_str(lhs),
+                        amount_str(rhs),
+                    )))
+                    .finish(),
+                sources.source(constraint.span)
+            );
+        }
+    }
+}
+
+/// Solve the target to freeze with `--freeze`, for the solution to build on it
+fn solve_frozen<'s>(sources: &Sources, program: &mut Program<'s>, name: &'s str, args: &SolveOptions) -> Solution {
+    if !program.targets.contains_key(name) {
+        exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("target '{name}' to freeze not found"))
+                .with_help(format!("did you mean {}", expected_str("", program.targets.keys().sorted())))
+                .finish(),
+            Source::from(String::new())
+        );
+    }
+    resolve_objectives(sources, program, name, args, &mut Vec::new(), &mut HashMap::new());
+
+    let target = &program.targets[name];
+    let Some(solution) = solve_model(sources, program, target, &base_options(args), args).solution else {
+        exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("target {name} to freeze is infeasible"))
+                .with_label(sources.label(target.span).with_message("this target"))
+                .finish(),
+            sources.source(target.span)
+        );
+    };
+
+    solution
+}
+
+/// Require every symbol that reactions use, but no reaction produces,
+/// to be declared raw or be an input of the target
+fn check_raw(sources: &Sources, program: &Program, target: &Target, args: &SolveOptions) {
+    let model = Model::new(program, args.strict_branches);
+    let undeclared = model.symbols().find(|&id| {
+        let symbol = model.symbol(id);
+        model.producers(id).is_empty() && !program.raw.contains(&symbol) && !target.inputs.contains(&symbol)
+    });
+    let Some(id) = undeclared else {
+        return;
+    };
+    let symbol = model.symbol(id);
+
+    // a report shows one file, so only the reactions in the same file as the first are labeled
+    let spans = model.consumers(id).iter().map(|u| program.reactions[u.reaction].span).collect_vec();
+    let file = spans.first().map(|span| sources.file_name(span.0));
+    let report = spans.iter().filter(|span| Some(sources.file_name(span.0)) == file).fold(
+        Report::build(ReportKind::Error).with_message(format!("{} is used, but no reaction produces it and it isn't declared raw", symbol.0)),
+        |report, &span| report.with_label(sources.label(span).with_message(format!("uses {}", symbol.0))),
+    );
 
-    for m in [a, b] {
-        for (k, v) in m {
-            *res.entry(k).or_insert(0) += v;
+    exit_report(
+        &report
+            .with_help(format!("declare it with `raw {};`, or add a reaction producing it", symbol.0))
+            .finish(),
+        sources.source(spans.first().copied().unwrap_or(target.span))
+    );
+}
+
+/// With `--strict`, require every reaction to create something that another reaction
+/// or some target uses, since otherwise it can never be useful
+fn check_unused_reactions(sources: &Sources, program: &Program, args: &SolveOptions) {
+    let mut used: HashSet<Symbol> = program.reactions.iter().flat_map(|r| r.inputs.keys().copied()).collect();
+    for target in program.targets.values() {
+        used.extend(target.constraints.keys());
+        used.extend(target.groups.iter().flat_map(|g| g.constraints.keys()));
+        used.extend(target.preferences.iter().flat_map(|p| p.terms.keys()));
+        if let Some(Goal::Resources(rt)) = &target.goal {
+            used.extend(rt.keys());
         }
+        used.extend(target.quanta.iter().filter_map(|q| match q.subject {
+            QuantumSubject::Symbol(symbol) => Some(symbol),
+            QuantumSubject::Reaction(_) => None,
+        }));
     }
+    let is_used = |symbol: &Symbol| used.contains(symbol) || used.iter().any(|range| symbol.in_range(range));
+
+    // reactions without outputs get rid of something, which is useful in itself
+    let unused = program.reactions.iter().find(|r| {
+        let outputs = r.effective_outputs(args.strict_branches);
+        !outputs.is_empty() && !outputs.keys().any(is_used)
+    });
+    let Some(reaction) = unused else {
+        return;
+    };
 
-    res
+    let name = reaction.name();
+    exit_report(
+        &Report::build(ReportKind::Error)
+            .with_message(format!("reaction {name} is never useful, nothing uses what it creates"))
+            .with_label(sources.label(reaction.span).with_message("this reaction"))
+            .with_help("remove it, or use what it creates in a reaction or target")
+            .finish(),
+        sources.source(reaction.span)
+    );
 }
 
-#[derive(clap::Parser)]
-#[command(version, about, long_about = None)]
-struct Cli {
-    /// the chem file to work on
-    #[arg(value_name = "FILE", env="FILE")]
-    file: PathBuf,
+/// Warn about reactions that don't conserve elements, like from a typo in an amount. Only reactions
+/// with inputs and outputs, that all have a composition, are checked, with every branch on its own.
+fn warn_unbalanced(sources: &Sources, program: &Program, args: &SolveOptions) {
+    let elements = |terms: &ReactionTerms| {
+        let mut total: HashMap<&str, usize> = HashMap::new();
+        for (symbol, amount) in terms {
+            for (&element, count) in program.compositions.get(symbol)? {
+                *total.entry(element).or_default() += amount * count;
+            }
+        }
+        Some(total)
+    };
+
+    for reaction in &program.reactions {
+        let outputs = match reaction.branches.is_empty() {
+            true => vec![&reaction.outputs],
+            false => reaction.branches.iter().map(|b| &b.outputs).collect(),
+        };
+        let Some(consumed) = elements(&reaction.inputs).filter(|_| !reaction.inputs.is_empty()) else {
+            continue;
+        };
 
-    /// The target to optimize
-    #[arg(value_name = "TARGET", env="TARGET")]
-    target: String,
+        let mut unbalanced = Vec::new();
+        for produced in outputs.into_iter().filter(|o| !o.is_empty()).filter_map(elements) {
+            for element in consumed.keys().chain(produced.keys()).unique().sorted() {
+                let (used, made) = (consumed.get(element).copied().unwrap_or(0), produced.get(element).copied().unwrap_or(0));
+                if used != made {
+                    unbalanced.push(format!("{element} ({used} in, {made} out)"));
+                }
+            }
+        }
+        if unbalanced.is_empty() {
+            continue;
+        }
 
-    /// Arguments to give to the solver (through minizinc)
-    #[arg(long, short, value_name = "SOLVER_ARGS", env="SOLVER_ARGS")]
-    solver_arguments: Option<String>
+        warn(
+            &Report::build(warning_kind(args))
+                .with_message(format!("reaction {} doesn't conserve {}", reaction.name(), unbalanced.into_iter().unique().join(", ")))
+                .with_label(sources.label(reaction.span).with_message("this reaction"))
+                .with_help("check the amounts of its inputs and outputs, and the elements of what it uses")
+                .finish(),
+            sources.source(reaction.span),
+            args
+        );
+    }
 }
 
-fn exit_report(r: &Report, source: Source) -> ! {
-    r.eprint(source).expect("io error");
-    exit(1);
+/// Warn about inputs of a target that it also creates, or that its goal minimizes.
+/// Inputs aren't balanced, which silently changes what those mean.
+fn warn_shadowed_inputs(sources: &Sources, target: &Target, args: &SolveOptions) {
+    let constraints = target.active_constraints();
+
+    for input in target.inputs.iter().unique().sorted_by_key(|s| s.0) {
+        let mut overlaps = Vec::new();
+        if matches!(&target.goal, Some(Goal::Resources(rt)) if rt.contains_key(input)) {
+            overlaps.push((
+                format!("{} is an input of target {}, and minimized by its goal", input.0, target.name),
+                (target.goal_span, "minimized here"),
+                "what the goal minimizes isn't balanced anyway, so the input changes nothing. Remove one of them to make clear which is meant",
+            ));
+        }
+        if constraints.contains_key(input) {
+            overlaps.push((
+                format!("{} is an input of target {}, and created by it", input.0, target.name),
+                (target.constraint_spans.get(input).copied(), "created here"),
+                "only what reactions create counts towards the constraint, not the input. Remove one of them to make clear which is meant",
+            ));
+        }
+
+        for (message, other, help) in overlaps {
+            let spans = [(target.input_spans.get(input).copied(), "an input here"), other]
+                .into_iter()
+                .filter_map(|(span, label)| Some((span?, label)))
+                .collect_vec();
+            let report = spans.iter().fold(
+                Report::build(warning_kind(args)).with_message(message),
+                |report, &(span, label)| report.with_label(sources.label(span).with_message(label)),
+            );
+
+            warn(
+                &report
+                    .with_help(help)
+                    .finish(),
+                sources.source(spans.first().map_or(target.span, |(span, _)| *span)),
+                args
+            );
+        }
+    }
 }
 
-fn main() {
-    let args = Cli::parse();
-    let input = match read_to_string(&args.file) {
-        Ok(i) => i,
-        Err(e) => {
-            let name = args.file.to_string_lossy();
-            exit_report(
-                &Report::build(ReportKind::Error)
-                    .with_message(e.to_string())
-                    .with_label(Label::new(0..name.chars().count()).with_message("while reading this file"))
+/// Warn about resources of a resources goal that no reaction uses or creates, which add nothing
+/// to the objective, and about weights so far apart that the lightest hardly count
+fn warn_goal_weights(sources: &Sources, reactions: &[Reaction], target: &Target, args: &SolveOptions) {
+    let Some(Goal::Resources(resources)) = &target.goal else {
+        return;
+    };
+    let span = |symbol: &Symbol| target.goal_spans.get(symbol).copied().or(target.goal_span).unwrap_or(target.span);
+
+    for symbol in resources.keys().sorted_by_key(|s| s.0) {
+        let touched = reactions.iter().any(|r| {
+            r.inputs.contains_key(symbol) || r.outputs.contains_key(symbol) || r.branches.iter().any(|b| b.outputs.contains_key(symbol))
+        });
+        if !touched {
+            warn(
+                &Report::build(warning_kind(args))
+                    .with_message(format!("no reaction uses or creates {}, so minimizing it in target {} does nothing", symbol.0, target.name))
+                    .with_label(sources.label(span(symbol)).with_message("minimized here"))
+                    .with_help("check its spelling, or remove it from the goal")
                     .finish(),
-                Source::from(name.to_string())
+                sources.source(span(symbol)),
+                args
             );
         }
+    }
+
+    let (Some((lightest, &least)), Some((heaviest, &most))) = (
+        resources.iter().min_by_key(|&(s, &w)| (w, s.0)),
+        resources.iter().max_by_key(|&(s, &w)| (w, Reverse(s.0))),
+    ) else {
+        return;
+    };
+    if least == 0 || most as f64 / (least as f64) < MAX_COEFFICIENT_RATIO {
+        return;
+    }
+    // a report shows one file, so the lightest is only labeled when it's in the same file
+    let report = Report::build(warning_kind(args))
+        .with_message(format!("the weights of the goal of target {} are {most} times apart", target.name))
+        .with_label(sources.label(span(heaviest)).with_message(format!("{} weighs {most}", heaviest.0)));
+    let report = match sources.file_name(span(lightest).0) == sources.file_name(span(heaviest).0) {
+        true => report.with_label(sources.label(span(lightest)).with_message(format!("{} weighs {least}", lightest.0))),
+        false => report,
     };
+    warn(
+        &report
+            .with_help("the lightest hardly count, and the solver can't tell their amounts apart precisely. Scale the weights closer together")
+            .finish(),
+        sources.source(span(heaviest)),
+        args
+    );
+}
 
-    let program = parse(&input, args.file.to_string_lossy().as_ref());
+/// Warn that the model's coefficients are too far apart for the solver to be precise
+fn warn_conditioning(sources: &Sources, smallest: Coefficient, largest: Coefficient, args: &SolveOptions) {
+    let describe = |c: &Coefficient| format!("{} {}", c.origin, c.value_str());
 
-    let Some(target ) = program.targets.get(args.target.as_str()) else {
-        let cmdline_args = std::env::args().join(" ");
-        let offset = cmdline_args.find(&args.target).unwrap();
+    // a report shows one file, so only the coefficients in the same file as the first are labeled
+    let spans = [&smallest, &largest].into_iter().filter_map(|c| Some((c.span?, describe(c)))).collect_vec();
+    let file = spans.first().map(|(span, _)| sources.file_name(span.0));
+    let report = spans.iter().filter(|(span, _)| Some(sources.file_name(span.0)) == file).fold(
+        Report::build(warning_kind(args)).with_message(format!(
+            "the numbers in the model range from {} to {}, too far apart for the solver to be precise",
+            smallest.value_str(),
+            largest.value_str(),
+        )),
+        |report, (span, description)| report.with_label(sources.label(*span).with_message(description)),
+    );
 
-        exit_report(
+    let report = report
+        .with_note(format!("smallest is {}, largest is {}", describe(&smallest), describe(&largest)))
+        .with_help("use larger or smaller units for some symbols or costs to bring the numbers closer together, or try --auto-scale")
+        .finish();
+    let source = spans.first().map_or(Source::from(String::new()), |(span, _)| sources.source(*span));
+    warn(&report, source, args);
+}
+
+/// Solve with random objectives instead of the goal,
+/// printing every differently looking solution until there are `samples` of them
+fn sample(sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, frozen: Option<&Solution>, samples: usize, stamp: &Stamp) {
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+    });
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut seen = HashSet::new();
+    let model = Model::new(program, args.strict_branches);
+    let format = args.number_format();
+
+    // random objectives can keep finding the same solution, so give up at some point
+    for _ in 0..samples * 4 {
+        if seen.len() == samples {
+            break;
+        }
+
+        let weights = (0..program.reactions.len()).map(|_| rng.gen::<f64>()).collect_vec();
+        let outcome = solve_model(sources, program, target, &Options {
+            integer: args.integer,
+            big_m: args.big_m,
+            strict_branches: args.strict_branches,
+            bill_of_materials: args.bom,
+            annotate: args.annotate,
+            sample_weights: Some(&weights),
+            frozen,
+            ..Options::default()
+        }, args);
+        // the objective changes with the weights, what matters is how many machines are used
+        let machines = outcome.solution.as_ref().map(|solution| {
+            (0..program.reactions.len()).map(|r| format.amount(solution.get(model.var_name(r)).unwrap_or(0.0))).collect_vec()
+        });
+        if seen.insert(machines) {
+            println!("sample {}:", seen.len());
+            print_output(&outcome, sources, program, target, args, None, stamp);
+        }
+    }
+
+    if seen.len() < samples {
+        eprintln!("only found {} different solutions (seed {seed})", seen.len());
+    }
+}
+
+/// Find the optimum, and then up to `cap` other solutions with (nearly) the same objective
+/// but using a different set of reactions, printing how they differ from the optimum
+fn all_optimal(sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, frozen: Option<&Solution>, cap: usize) {
+    let options = Options {
+        integer: args.integer,
+        big_m: args.big_m,
+        strict_branches: args.strict_branches,
+        annotate: args.annotate,
+        frozen,
+        ..Options::default()
+    };
+    let solve = |options: &Options| solve_model(sources, program, target, options, args).solution;
+    let model = Model::new(program, args.strict_branches);
+    let machines = |solution: &Solution, reaction: usize| solution.get(model.var_name(reaction)).unwrap_or(0.0);
+    let support = |solution: &Solution| {
+        (0..program.reactions.len()).filter(|&r| machines(solution, r) > SUPPORT_EPSILON).collect_vec()
+    };
+
+    let Some(optimum) = solve(&options) else {
+        exit_infeasible(
             &Report::build(ReportKind::Error)
-                .with_message("target name not found".to_string())
-                .with_label(
-                    Label::new(offset..offset+args.target.chars().count()).with_message(format!("'{}' not found", args.target))
-                )
-                .with_help(format!("did you mean {}", expected_str("", program.targets.into_keys())))
+                .with_message(format!("target {} has no solution", target.name))
+                .with_label(sources.label(target.span).with_message("this target"))
+                .with_help("solve it without --all-optimal or --critical to see why")
                 .finish(),
-            Source::from(cmdline_args)
+            sources.source(target.span)
         );
     };
+    let objective = optimum.get("objective").unwrap_or(0.0);
 
-    let mut f = match File::create(MINIZINC_OUTPUT_NAME) {
-        Ok(f) => f,
-        Err(e) => {
-            exit_report(
-                &Report::build(ReportKind::Error)
-                    .with_message(format!("{e}"))
-                    .with_label(Label::new(0..MINIZINC_OUTPUT_NAME.chars().count()).with_message("while creating this file"))
-                    .finish(),
-                Source::from(MINIZINC_OUTPUT_NAME.to_string())
-            );
-        }
+    let format = args.number_format();
+    println!("optimum (objective {}):", format.amount(objective));
+    for reaction in support(&optimum) {
+        println!("    {} = {}", model.name(reaction), format.amount(machines(&optimum, reaction)));
+    }
+
+    let mut supports = vec![support(&optimum)];
+    while supports.len() <= cap {
+        let Some(alternative) = solve(&Options {
+            near_optimum: Some((objective, args.optimal_epsilon)),
+            excluded_supports: &supports,
+            ..options
+        }) else {
+            break;
+        };
+
+        println!();
+        println!(
+            "alternative {} (objective {}):",
+            supports.len(),
+            format.amount(alternative.get("objective").unwrap_or(0.0))
+        );
+        print_changes(&model, &optimum, &alternative, format);
+
+        supports.push(support(&alternative));
+    }
+
+    if supports.len() == 1 {
+        println!();
+        println!("no alternative optima");
+    }
+}
+
+/// Solve without each of the reactions the optimum uses, one at a time,
+/// and rank them by how much worse the objective gets without them
+fn critical_reactions(sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, frozen: Option<&Solution>) {
+    let options = Options {
+        integer: args.integer,
+        big_m: args.big_m,
+        strict_branches: args.strict_branches,
+        annotate: args.annotate,
+        frozen,
+        ..Options::default()
+    };
+    let solve = |options: &Options| solve_model(sources, program, target, options, args).solution;
+    let model = Model::new(program, args.strict_branches);
+
+    let Some(optimum) = solve(&options) else {
+        exit_infeasible(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("target {} has no solution", target.name))
+                .with_label(sources.label(target.span).with_message("this target"))
+                .with_help("solve it without --all-optimal or --critical to see why")
+                .finish(),
+            sources.source(target.span)
+        );
     };
+    let objective = optimum.get("objective").unwrap_or(0.0);
+    let maximizing = matches!(target.goal, Some(Goal::Time));
+
+    // how much worse the objective gets without each reaction, or `None` if it's infeasible
+    let mut degradations = (0..program.reactions.len())
+        .filter(|&r| optimum.get(model.var_name(r)).unwrap_or(0.0) > SUPPORT_EPSILON)
+        .map(|reaction| {
+            let without = solve(&Options { excluded_reactions: &[reaction], ..options })
+                .and_then(|solution| solution.get("objective"));
+            let degradation = without.map(|o| if maximizing { objective - o } else { o - objective });
+
+            (reaction, degradation)
+        })
+        .collect_vec();
+    degradations.sort_by(|(_, a), (_, b)| match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Less,
+        (Some(_), None) => Ordering::Greater,
+        (Some(a), Some(b)) => b.total_cmp(a),
+    });
+
+    let width = degradations.iter().map(|&(r, _)| model.name(r).chars().count()).max().unwrap_or(0);
+    let format = args.number_format();
+    println!("objective {}, without each used reaction:", format.amount(objective));
+    for (reaction, degradation) in degradations {
+        let signed = |d: f64| if d >= 0.0 { format!("+{}", format.amount(d)) } else { format.amount(d) };
+        let effect = match degradation {
+            None => "infeasible".to_string(),
+            Some(d) if objective != 0.0 => format!("{} ({:+.1}%)", signed(d), d / objective.abs() * 100.0),
+            Some(d) => signed(d),
+        };
+        println!("    {:<width$} {effect}", model.name(reaction));
+    }
+}
+
+/// Print which reactions are added, removed or changed from one solution to another,
+/// returning whether there are any differences
+fn print_changes(model: &Model, before: &Solution, after: &Solution, format: NumberFormat) -> bool {
+    let machines = |solution: &Solution, reaction: usize| solution.get(model.var_name(reaction)).unwrap_or(0.0);
+    let mut changed = false;
+
+    for reaction in model.reactions() {
+        let (before, after) = (machines(before, reaction), machines(after, reaction));
+        match (before > SUPPORT_EPSILON, after > SUPPORT_EPSILON) {
+            (false, true) => println!("    + {} = {}", model.name(reaction), format.amount(after)),
+            (true, false) => println!("    - {}", model.name(reaction)),
+            (true, true) if (before - after).abs() > SUPPORT_EPSILON => {
+                println!("    ~ {} = {} (was {})", model.name(reaction), format.amount(after), format.amount(before))
+            }
+            _ => continue,
+        }
+        changed = true;
+    }
+
+    changed
+}
 
-    if let Err(e) = generate_minizinc(&mut f, &input, &program, target) {
+fn print_output(outcome: &Outcome, sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, previous: Option<&Solution>, stamp: &Stamp) {
+    if args.statistics {
+        for (name, value) in &outcome.statistics {
+            eprintln!("{name}: {}", value.as_str().map_or(value.to_string(), str::to_string));
+        }
+    }
+
+    let Some(solution) = &outcome.solution else {
         exit_report(
             &Report::build(ReportKind::Error)
-                .with_message(e.to_string())
-                .with_label(Label::new(0..MINIZINC_OUTPUT_NAME.chars().count()).with_message("while writing to this file"))
+                .with_message("no solution found")
+                .with_label(sources.label(target.span).with_message("for this target"))
+                .with_note("the solver stopped without finding a solution, or proving there is none")
                 .finish(),
-            Source::from(MINIZINC_OUTPUT_NAME.to_string())
+            sources.source(target.span)
         );
+    };
+
+    let format = args.number_format();
+    let shown = match (args.tree, args.output_format) {
+        (true, _) => render_tree(program, target, solution, args.strict_branches, format),
+        (false, OutputFormat::Markdown) => render_markdown(program, target, solution, args.strict_branches, format),
+        (false, OutputFormat::Text) => render_text(program, target, solution, args.strict_branches, args.group_by, format),
+    };
+    print!("{shown}");
+    write_schedule(solution, program, args);
+    match (args.tree, args.output_format) {
+        (false, OutputFormat::Markdown) => println!("- Stamp: {stamp}"),
+        _ => println!("\nstamp: {stamp}"),
     }
+    use_solution(solution, sources, program, target, args, previous, stamp);
+}
+
+/// Do what the options ask for with the solution, besides showing it
+fn use_solution(solution: &Solution, sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, previous: Option<&Solution>, stamp: &Stamp) {
+    let model = Model::new(program, args.strict_branches);
 
-    drop(f);
+    warn_deprecated(solution, sources, program, args);
+    write_chart(solution, program, args);
 
-    let cpus = available_parallelism().expect("get available parallelism").to_string();
+    if let Some(path) = &args.save_solution {
+        // only what's needed to show the solution again
+        let vars = (0..program.reactions.len()).map(|r| model.var_name(r)).chain(["objective"]).collect::<HashSet<_>>();
+        let saved = Solution {
+            values: solution.values.iter().filter(|(var, _)| vars.contains(var.as_str())).map(|(var, &value)| (var.clone(), value)).collect(),
+        };
+        saved.write(path, target.name, stamp).unwrap_or_else(|f| exit_failure(f));
+    }
 
-    let mut cmd = Command::new("minizinc");
-    cmd
-        .args(["--soln-sep", ""])
-        .args(["--search-complete-msg", ""])
-        .args(["--unsatorunbnd-msg", "unsatisfiable or unbounded"])
-        .args(["--unsatisfiable-msg", "unsatisfiable"])
-        .args(["--solver", "cbc"])
-        .args(["-p", cpus.as_str()])
-        .arg(MINIZINC_OUTPUT_NAME)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+    if let Some(previous) = previous {
+        println!("compared to the saved solution:");
+        if !print_changes(&model, previous, solution, args.number_format()) {
+            println!("    no changes");
+        }
+    }
+}
 
+/// Read a solution saved with `--save-solution`, warning about what doesn't fit the program anymore.
+/// With `--verify-stamp`, it has to have been solved from the same inputs.
+fn load_solution(path: &Path, sources: &Sources, program: &Program, target: &Target, args: &SolveOptions, stamp: &Stamp) -> Solution {
+    let (name, saved_stamp, solution) = Solution::read(path).unwrap_or_else(|f| exit_failure(f));
+    let file = path.to_string_lossy().to_string();
 
-    let output = match cmd.output() {
-        Ok(child) => child,
-        Err(e) => {
+    if args.verify_stamp {
+        let changed = match &saved_stamp {
+            Some(saved_stamp) => saved_stamp.differences(stamp),
+            None => vec!["its stamp, which it doesn't have"],
+        };
+        if !changed.is_empty() {
             exit_report(
                 &Report::build(ReportKind::Error)
-                    .with_message(format!("while spawning 'minizinc' process: {e}"))
+                    .with_message(format!("the saved solution doesn't match the current inputs: {} changed", changed.join(", ")))
+                    .with_label(Label::new(0..file.chars().count()).with_message("in this saved solution"))
+                    .with_note(format!(
+                        "saved with {}\ncurrently {stamp}",
+                        saved_stamp.map_or("no stamp".to_string(), |saved_stamp| saved_stamp.to_string()),
+                    ))
+                    .with_help("solve the target again and save the solution, or leave out --verify-stamp")
                     .finish(),
-                Source::from("minizinc".to_string())
+                Source::from(file)
             );
         }
+    }
+
+    let warn = |message: String| {
+        Report::build(ReportKind::Warning)
+            .with_message(message)
+            .with_label(Label::new(0..file.chars().count()).with_message("in this saved solution"))
+            .finish()
+            .eprint(Source::from(file.clone()))
+            .expect("io error");
+    };
+
+    if name != target.name {
+        warn(format!("the saved solution is of target {name}, not {}", target.name));
+    }
+
+    // reactions that were changed or removed since the solution was saved
+    let model = Model::new(program, args.strict_branches);
+    let vars = (0..program.reactions.len()).map(|r| model.var_name(r)).collect::<HashSet<_>>();
+    let unknown = solution.values.keys().filter(|var| *var != "objective" && !vars.contains(var.as_str())).sorted().collect_vec();
+    if !unknown.is_empty() {
+        warn(format!("the saved solution uses reactions that are not in {} anymore: {}", sources.file_name(target.span.0), unknown.iter().join(", ")));
+    }
+
+    solution
+}
+
+/// Warn about every deprecated reaction the solution uses
+fn warn_deprecated(solution: &Solution, sources: &Sources, program: &Program, args: &SolveOptions) {
+    let model = Model::new(program, args.strict_branches);
+
+    for (idx, reaction) in program.reactions.iter().enumerate() {
+        let Some(deprecation) = &reaction.deprecated else {
+            continue;
+        };
+        if solution.get(model.var_name(idx)).unwrap_or(0.0) <= SUPPORT_EPSILON {
+            continue;
+        }
+
+        Report::build(ReportKind::Warning)
+            .with_message(format!("the solution uses deprecated reaction {}", model.name(idx)))
+            .with_label(sources.label(reaction.span).with_message("deprecated here"))
+            .with_note(deprecation)
+            .finish()
+            .eprint(sources.source(reaction.span))
+            .expect("io error");
+    }
+}
+
+/// With `--chart`, draw the solution
+fn write_chart(solution: &Solution, program: &Program, args: &SolveOptions) {
+    let Some(path) = &args.chart else {
+        return;
     };
 
-    if !output.status.success() {
-        let output = String::from_utf8_lossy(&output.stderr).to_string();
+    if let Err(e) = render_chart(path, program, solution, args.strict_branches) {
+        let name = path.to_string_lossy().to_string();
         exit_report(
             &Report::build(ReportKind::Error)
-                .with_message("while running 'minizinc' process".to_string())
-                .with_code(&output)
+                .with_message(e)
+                .with_label(Label::new(0..name.chars().count()).with_message("while writing this chart"))
                 .finish(),
-            Source::from(output)
+            Source::from(name)
         );
     }
-
-    println!("{}", String::from_utf8_lossy(&output.stdout));
 }
 
-fn generate_minizinc(w: &mut impl Write, input: &str, program: &Program, target: &Target) -> io::Result<()> {
-    let Some(ref goal) = target.goal else {
+/// With `--schedule` or `--schedule-csv`, show or write the schedule of the solution
+fn write_schedule(solution: &Solution, program: &Program, args: &SolveOptions) {
+    if !args.schedule && args.schedule_csv.is_none() {
+        return;
+    }
+
+    let schedule = schedule(program, solution, args.strict_branches).unwrap_or_else(|e| {
         exit_report(
             &Report::build(ReportKind::Error)
-                .with_message(format!("expected 'goal' specification in target {}", target.name))
-                .with_label(Label::new(target.span.0..target.span.1).with_message("in this target"))
+                .with_message(format!("can't schedule the solution: {e}"))
                 .finish(),
-            Source::from(input.to_string())
+            Source::from(String::new())
         );
-    };
-
-    for i in input.lines() {
-        writeln!(w, "% {i}")?;
+    });
+    if args.schedule {
+        print!("\n{}", schedule.render(program, args.strict_branches));
+    }
+    if let Some(path) = &args.schedule_csv {
+        if let Err(e) = std::fs::write(path, schedule.to_csv(program, args.strict_branches)) {
+            let name = path.to_string_lossy().to_string();
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(e.to_string())
+                    .with_label(Label::new(0..name.chars().count()).with_message("while writing this schedule"))
+                    .finish(),
+                Source::from(name)
+            );
+        }
     }
+}
 
-    writeln!(w)?;
-    writeln!(w, "% variables")?;
+/// The stamp of solving a target: a hash of the chem files, one of the constants and options
+/// that change the model, and the version of the backend's solver
+fn stamp(sources: &Sources, constants: &Constants, target: &Target, args: &SolveOptions) -> Stamp {
+    // every option is named, so one added later has to be stamped or said not to change the model
+    let SolveOptions {
+        define: _, constraint, input, goal, default_goal, in_time, portfolio, backend: backend_name, solver_arguments,
+        enable_group, disable_group, enable, freeze, integer, prune_threshold, feasible_only, maximize_constraint,
+        big_m, strict_branches, bom, minimize_change, auto_scale, slack, sample, seed,
+        // in the sources, and the constants
+        patch: _, targets_file: _,
+        // how the solution is shown, saved or compared
+        cache_dir: _, tree: _, output_format: _, precision: _, decimal_separator: _, thousands_separator: _,
+        scientific: _, group_by: _, statistics: _, chart: _, schedule: _, schedule_csv: _, save_solution: _,
+        load_solution: _, diff_solution: _, verify_stamp: _, strict: _, annotate: _, all_optimal: _,
+        optimal_epsilon: _, critical: _, dry_run: _, emit: _,
+    } = args;
+    // what changes about a solution with it is the content of the file
+    let baseline = minimize_change.as_deref().map(|path| std::fs::read_to_string(path).map(|text| hash(&text)).ok());
 
-    for reaction in &program.reactions {
-        let variable = reaction.var_name();
-        writeln!(w, "var float: {variable};")?;
-    }
+    let options = format!(
+        "target={} goal_span={:?} constants={:?} files={:?} constraint={constraint:?} input={input:?} goal={goal:?} \
+         default_goal={default_goal:?} in_time={in_time:?} backend={backend_name:?} portfolio={portfolio:?} \
+         solver_arguments={solver_arguments:?} enable_group={enable_group:?} disable_group={disable_group:?} \
+         enable={enable:?} freeze={freeze:?} integer={integer} prune_threshold={prune_threshold:?} \
+         feasible_only={feasible_only} maximize_constraint={maximize_constraint:?} big_m={big_m} \
+         strict_branches={strict_branches} bom={bom} minimize_change={baseline:?} auto_scale={auto_scale} \
+         slack={slack} sample={sample:?} seed={seed:?}",
+        target.name,
+        // which of the target's goals is picked
+        target.goal_span,
+        constants.defined.iter().sorted_by(|a, b| a.0.cmp(b.0)).collect_vec(),
+        // whether files are chem files, targets files or patches
+        sources.files().map(|(_, _, kind)| kind).collect_vec(),
+    );
 
-    writeln!(w)?;
-    writeln!(w, "% non-negative constraints")?;
-    for reaction in &program.reactions {
-        writeln!(w, "constraint {} >= 0;", reaction.var_name())?;
+    Stamp::new(sources.text(), &options, chosen_backend(sources, backend_name).version())
+}
+
+/// The options of solving a target for something else than showing its solution,
+/// like for its objective, or to compare it to other targets
+fn base_options(args: &SolveOptions) -> Options<'static> {
+    Options {
+        integer: args.integer,
+        big_m: args.big_m,
+        strict_branches: args.strict_branches,
+        auto_scale: args.auto_scale,
+        ..Options::default()
     }
+}
 
-    writeln!(w)?;
-    writeln!(w, "% target constraints")?;
-    for (symbol, scalar) in &target.constraints {
-        let mut production = vec!["0".to_string()];
-        let mut consumption = vec!["0".to_string()];
+/// The objective of the solution of an outcome, if it has one
+fn objective(outcome: &Outcome) -> Option<f64> {
+    outcome.solution.as_ref().and_then(|s| s.get("objective"))
+}
 
-        for reaction in &program.reactions {
-            let cost = reaction.cost.0;
+/// Solve a target with the backend `--backend` picks
+fn solve_model(sources: &Sources, program: &Program, target: &Target, options: &Options, args: &SolveOptions) -> Outcome {
+    solve_with(sources, &Model::new(program, options.strict_branches), target, options, args)
+}
 
-            if let Some(&i) = reaction.inputs.get(symbol) {
-                consumption.push(format!("{i} * {} / {cost}", reaction.var_name()))
-            }
+/// Like [`solve_model`], with a model of the program that's already made
+fn solve_with(sources: &Sources, model: &Model, target: &Target, options: &Options, args: &SolveOptions) -> Outcome {
+    chosen_backend(sources, &args.backend)
+        .solve(sources, model, target, options, args)
+        .unwrap_or_else(|e| exit_error(sources, &args.backend, e))
+}
 
-            if let Some(&i) = reaction.outputs.get(symbol) {
-                production.push(format!("{i} * {} / {cost}", reaction.var_name()))
-            }
-        }
+/// The backend with a name, exiting with the names there are when there's none
+fn chosen_backend(sources: &Sources, name: &str) -> &'static dyn Backend {
+    backend(name).unwrap_or_else(|e| exit_error(sources, name, e))
+}
+
+/// Print a report, and exit with 1 after removing the work directory
+fn exit_report(r: &Report, source: Source) -> ! {
+    r.eprint(source).expect("io error");
+    remove_work_dir();
+    exit(1);
+}
 
-        let production = production.join("+");
-        let consumption = consumption.join("+");
+/// Exit with the report of what went wrong
+fn exit_failure(failure: Failure) -> ! {
+    exit_report(&failure.report, failure.source)
+}
 
-        let in_time = target.in_time;
+/// Exit reporting an invalid target on the chem file
+fn exit_invalid(sources: &Sources, e: Invalid) -> ! {
+    exit_failure(invalid_report(sources, e))
+}
 
-        writeln!(w, "constraint ({production}) - ({consumption}) >= {scalar} / {in_time};")?;
+/// Exit reporting why a backend couldn't solve a target, on the chem file where it's about part of it
+fn exit_error(sources: &Sources, backend: &str, e: Error) -> ! {
+    match e {
+        Error::NoBackend { name, registered } => exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("no backend '{name}'"))
+                .with_help(format!("the backends are {}", registered.join(", ")))
+                .finish(),
+            Source::from(String::new())
+        ),
+        Error::Invalid(e) => exit_invalid(sources, e),
+        Error::Unsupported(e) => exit_report(
+            &Report::build(ReportKind::Error)
+                .with_message(format!("the {backend} backend doesn't support {}", e.what))
+                .with_label(sources.label(e.span).with_message("used here"))
+                .with_help("solve it with `--backend minizinc`")
+                .finish(),
+            sources.source(e.span)
+        ),
+        Error::Rejected { message, label, span, output } => {
+            let report = Report::build(ReportKind::Error)
+                .with_message(message)
+                .with_label(sources.label(span).with_message(label));
+            exit_report(
+                &match output {
+                    Some(output) => report.with_code(output),
+                    None => report,
+                }.finish(),
+                sources.source(span)
+            );
+        }
+        Error::Failed { message, output, help } => {
+            let mut report = Report::build(ReportKind::Error).with_message(message);
+            if let Some(output) = &output {
+                report = report.with_code(output);
+            }
+            if let Some(help) = help {
+                report = report.with_help(help);
+            }
+            exit_report(&report.finish(), Source::from(output.unwrap_or_default()));
+        }
     }
+}
 
-    writeln!(w)?;
-    writeln!(w, "% balance constraints")?;
+/// Quote an argument of a command printed by `--dry-run` when it needs to be, so the command
+/// can be pasted into a shell: a POSIX one, or cmd and powershell on windows
+fn shell_escape(arg: &str) -> String {
+    // backslashes separate paths on windows
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c) || (cfg!(windows) && c == '\\');
 
-    let mut symbols: HashSet<Symbol> = HashSet::new();
-    for i in &program.reactions {
-        symbols.extend(i.inputs.keys());
-        symbols.extend(i.outputs.keys());
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else if cfg!(windows) {
+        // cmd and powershell both take double quotes, but not single ones, and a quote in them is doubled
+        format!("\"{}\"", arg.replace('"', "\"\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
     }
+}
+
+/// Add the constraints, inputs and goal given on the command line to a target
+fn compose_target<'a>(target: &mut Target<'a>, constants: &Constants, args: &'a SolveOptions) {
+    for constraint in &args.constraint {
+        let parsed = parse_arg(constraint, grammar::ConstraintTermsParser::new().parse(constants, constraint));
 
-    let using: HashSet<&Symbol> = target.inputs.iter().collect();
-    for symbol in symbols {
-        match goal {
-            Goal::Reactions if using.contains(&symbol) => continue,
-            Goal::Resources(rt) if using.contains(&symbol) || rt.keys().contains(&symbol) => continue,
-            _ => {}
+        // they're added to the constraints of the target on the same symbols
+        let mut kinds = ConstraintKinds::of(target);
+        if let Err((symbol, difference)) = kinds.add(parsed.comparison, parsed.window, parsed.terms.iter().flat_map(|terms| terms.keys().copied())) {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("this constraint and those of target {} on {} {difference}", target.name, symbol.0))
+                    .with_label(Label::new(0..constraint.chars().count()).with_message("this constraint"))
+                    .with_help(format!(
+                        "the target's constraints on it are {}, and met in {}",
+                        target.comparison(&symbol),
+                        target.window(&symbol)
+                    ))
+                    .finish(),
+                Source::from(constraint.clone())
+            );
         }
+        (target.comparisons, target.windows) = kinds.finish();
 
-        let mut production = vec!["0".to_string()];
-        let mut consumption = vec!["0".to_string()];
+        let too_large = |message: String| -> ! {
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("can't add this constraint to target {}", target.name))
+                    .with_label(Label::new(0..constraint.chars().count()).with_message(message))
+                    .finish(),
+                Source::from(constraint.clone())
+            );
+        };
+        let constraints = std::iter::once(std::mem::take(&mut target.constraints)).chain(parsed.terms);
+        target.constraints = sum_terms(constraints).unwrap_or_else(|symbol| too_large(sum_error(symbol)));
+        if let Err(message) = target.check_sums() {
+            too_large(message);
+        }
+    }
 
-        for reaction in &program.reactions {
-            let cost = reaction.cost.0;
+    target.inputs.extend(args.input.iter().map(|i| Symbol(i.as_str())));
+    target.features.extend(args.enable.iter().map(String::as_str));
 
-            if let Some(&i) = reaction.inputs.get(&symbol) {
-                consumption.push(format!("{i} * {} / {cost}", reaction.var_name()))
-            }
+    if let Some(goal) = &args.goal {
+        target.goal = Some(parse_arg(goal, grammar::GoalParser::new().parse(constants, goal)));
+        target.goal_span = None;
+        target.goal_spans.clear();
+    }
+    if let Some(in_time) = args.in_time {
+        target.in_time = in_time;
+    }
+}
 
-            if let Some(&i) = reaction.outputs.get(&symbol) {
-                production.push(format!("{i} * {} / {cost}", reaction.var_name()))
-            }
-        }
+/// Give a target without a goal the one of `--default-goal`, warning that it has none
+fn default_goal<'a>(sources: &Sources, target: &mut Target<'a>, constants: &Constants, args: &'a SolveOptions) {
+    if target.goal.is_some() {
+        return;
+    }
+
+    let goal = parse_arg(&args.default_goal, grammar::GoalParser::new().parse(constants, &args.default_goal));
+    let report = Report::build(warning_kind(args))
+        .with_message(format!("target {} has no goal, so it minimizes {}", target.name, args.default_goal));
+    // a target made on the command line isn't in the chem file
+    let report = match target.name {
+        COMMAND_LINE_TARGET => report.with_help("add one with --goal, like --goal reactions"),
+        _ => report
+            .with_label(sources.label(target.span).with_message("this target"))
+            .with_help("add one, like `minimize reactions;`, or pick the goal of targets without one with --default-goal"),
+    };
+    warn(&report.finish(), sources.source(target.span), args);
 
-        let production = production.join("+");
-        let consumption = consumption.join("+");
+    target.goal = Some(goal);
+}
 
-        writeln!(w, "constraint ({production}) >= {consumption};")?;
+/// The result of parsing a command line argument, reporting errors on the argument
+fn parse_arg<T>(arg: &str, result: Result<T, ChemParseError<impl Display>>) -> T {
+    match result {
+        Ok(i) => i,
+        Err(e) => exit_report(&parse_error_report(e), Source::from(arg.to_string())),
     }
+}
 
-    writeln!(w)?;
-    match goal {
-        Goal::Resources(rt) => {
-            let mut production = vec!["0".to_string()];
-            let mut consumption = vec!["0".to_string()];
+fn parse_error_report(e: ChemParseError<impl Display>) -> Report {
+    let Diagnostic { message, label, span: (from, to) } = Diagnostic::from(e);
+    Report::build(ReportKind::Error)
+        .with_message(message)
+        .with_label(Label::new(from..to).with_message(label))
+        .finish()
+}
 
-            for (symbol, weight) in rt {
-                for reaction in &program.reactions {
-                    if let Some(&i) = reaction.inputs.get(&symbol) {
-                        consumption.push(format!("{i} * {} * {weight}", reaction.var_name()))
-                    }
+/// Replace the reactions with the same labels as the reactions of a patch file, keeping their zones.
+/// The patch's other items, including reactions without a label, are added.
+fn apply_patch<'s>(sources: &Sources, items: &mut Vec<Spanned<Item<'s>>>, patch: impl Iterator<Item = Spanned<Item<'s>>>, args: &SolveOptions) {
+    for (l, item, r) in patch {
+        let Item::Reaction(reaction) = item else {
+            items.push((l, item, r));
+            continue;
+        };
+        let Some(label) = reaction.label.clone() else {
+            items.push((l, Item::Reaction(reaction), r));
+            continue;
+        };
 
-                    if let Some(&i) = reaction.outputs.get(&symbol) {
-                        production.push(format!("{i} * {} * {weight}", reaction.var_name()))
-                    }
-                }
+        let replaced = items
+            .iter_mut()
+            .flat_map(|(_, item, _)| match item {
+                Item::Reaction(reaction) => vec![reaction],
+                Item::Zone((_, reactions)) => reactions.iter_mut().collect(),
+                _ => Vec::new(),
+            })
+            .find(|base| base.label.as_ref() == Some(&label));
+        match replaced {
+            Some(base) => *base = Reaction { zone: base.zone, ..reaction },
+            None => {
+                warn(
+                    &Report::build(warning_kind(args))
+                        .with_message(format!("reaction '{label}' of the patch doesn't replace any reaction"))
+                        .with_label(sources.label(reaction.span).with_message("added instead"))
+                        .with_help("check its label, or leave it out if the reaction is meant to be new")
+                        .finish(),
+                    sources.source(reaction.span),
+                    args
+                );
+                items.push((l, Item::Reaction(reaction), r));
             }
+        }
+    }
+}
 
-            let production = production.join("+");
-            let consumption = consumption.join("+");
+/// Add the items of a targets file, which can only be targets and suites. Its targets
+/// replace those of the chem files with the same name, which is warned about.
+fn add_targets<'s>(sources: &Sources, items: &mut Vec<Spanned<Item<'s>>>, targets: impl Iterator<Item = Spanned<Item<'s>>>, args: &SolveOptions) {
+    for (l, item, r) in targets {
+        let Item::Target(target) = &item else {
+            if !matches!(item, Item::Suite(_)) {
+                exit_report(
+                    &Report::build(ReportKind::Error)
+                        .with_message("a targets file can only have targets and suites")
+                        .with_label(sources.label((l, r)).with_message("this isn't a target or suite"))
+                        .with_help("move it to a chem file")
+                        .finish(),
+                    sources.source((l, r))
+                );
+            }
+            items.push((l, item, r));
+            continue;
+        };
 
-            writeln!(w, "solve minimize ({consumption}) - ({production});")?;
-        }
-        Goal::Reactions => {
-            writeln!(w, "solve minimize {};", program.reactions.iter().map(|i| i.var_name()).format("+"))?;
+        let replaced = items.iter().position(|(_, item, _)| matches!(item, Item::Target(t) if t.name == target.name));
+        if let Some(idx) = replaced {
+            let (_, Item::Target(replaced), _) = items.remove(idx) else {
+                unreachable!("found a target");
+            };
+            warn(
+                &Report::build(warning_kind(args))
+                    .with_message(format!(
+                        "target {} of {} replaces the one in {}",
+                        target.name,
+                        sources.file_name(l),
+                        sources.file_name(replaced.span.0),
+                    ))
+                    .with_label(sources.label(target.name_span).with_message("replaces the other"))
+                    .with_help("rename it to solve both")
+                    .finish(),
+                sources.source(target.span),
+                args
+            );
         }
+        items.push((l, item, r));
     }
+}
 
-    let mut output_exprs = Vec::new();
-    let max_width = program
-        .reactions
-        .iter()
-        .map(|reaction| {
-            let reaction_name = reaction.var_name();
-            let pretty_name = reaction.label.as_deref().unwrap_or(&reaction_name);
-            pretty_name.chars().count()
-        })
-        .max()
-        .unwrap_or(0);
+/// The items of a file, or why it can't be parsed
+type ParsedFile<'s> = Result<Vec<Spanned<Item<'s>>>, ChemParseError<String>>;
 
-    for reaction in &program.reactions {
-        let reaction_name = reaction.var_name();
-        let pretty_name = reaction.label.as_deref().unwrap_or(&reaction_name);
-        output_exprs.push(format!("if fix({reaction_name}) > 0 then \"{pretty_name:<width$} =\" ++ show_float(8, 5, {reaction_name}) ++ \"\\n\" else \"\" endif", width=max_width))
+/// Parse each file of the sources on its own, spread over a thread per cpu when there are several,
+/// as projects can have hundreds of them. The spans of the items are within their file
+fn parse_files<'s>(sources: &'s Sources, constants: &Constants) -> Vec<ParsedFile<'s>> {
+    let files = sources.files().map(|(_, range, _)| range).collect_vec();
+    let parse = |constants: &Constants, range: &Range<usize>| {
+        grammar::ItemsParser::new()
+            .parse(constants, &sources.text()[range.clone()])
+            .map_err(|e| e.map_token(|t| t.to_string()))
+    };
+    if let [range] = files.as_slice() {
+        return vec![parse(constants, range)];
     }
 
-    writeln!(w, "output [{}];", output_exprs.join(",\n"))?;
-
-    Ok(())
+    // the constants keep track of the blocks being parsed, so each thread has its own
+    let defined = &constants.defined;
+    let cpus = available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|s| {
+        let threads = files
+            .chunks(files.len().div_ceil(cpus).max(1))
+            .map(|chunk| s.spawn(move || {
+                let constants = Constants::new(defined.clone());
+                chunk.iter().map(|range| parse(&constants, range)).collect_vec()
+            }))
+            .collect_vec();
+        threads.into_iter().flat_map(|t| t.join().expect("parser panicked")).collect()
+    })
 }
 
-fn expected_str<'a>(word: &str, expected: impl IntoIterator<Item=impl AsRef<str> + 'a>) -> String {
-    let expected = expected.into_iter().collect_vec();
-    let expected = expected.iter().map(|i| i.as_ref()).collect_vec();
-
-    if expected.len() == 0 {
-        "".to_string()
-    } else if expected.len() == 1 {
-        format!("{word}{}", expected[0])
-    } else {
-        let (last, rest) = expected.split_last().unwrap();
-        format!("{word}{} or {last}", rest.join(","))
+/// Parse all files, apply the patches, and combine them into one program
+fn parse<'s>(sources: &'s Sources, constants: &Constants, args: &SolveOptions) -> Program<'s> {
+    if let Some(program) = args.cache_dir.as_deref().and_then(|dir| cache::load(dir, sources, constants, args.strict)) {
+        return program;
     }
-}
+    let mut items = Vec::new();
 
-fn parse<'s>(input: &'s str, filename: &str) -> Program<'s> {
-    let program = match grammar::ProgramParser::new().parse(&input) {
-        Ok(i) => { i }
-        Err(e) => {
-            let report = match e {
-                ParseError::InvalidToken { location } => {
-                    Report::build(ReportKind::Error)
-                        .with_message("invalid token")
-                        .with_label(Label::new(location..location+1).with_message("here"))
-                        .finish()
-                }
-                ParseError::UnrecognizedEof { location, expected } => {
-                    Report::build(ReportKind::Error)
-                        .with_message("unexpected end of file")
-                        .with_label(Label::new(location..location + 1).with_message(expected_str("expected ", &expected)))
-                        .finish()
-                }
-                ParseError::UnrecognizedToken { token: (from, tok, to), expected } => {
-                    Report::build(ReportKind::Error)
-                        .with_message(format!("invalid token '{tok}'"))
-                        .with_label(Label::new(from..to).with_message(expected_str("expected ", &expected)))
-                        .finish()
-                }
-                ParseError::ExtraToken { token: (from, tok, to) } => {
-                    Report::build(ReportKind::Error)
-                        .with_message(format!("unexpected token '{tok}'"))
-                        .with_label(Label::new(from..to).with_message("no rule expects this token"))
-                        .finish()
-                }
-                ParseError::User { error: (from, err, to) } => {
-                    Report::build(ReportKind::Error)
-                        .with_message("parse error".to_string())
-                        .with_label(Label::new(from..to).with_message(err))
-                        .finish()
+    for ((filename, range, kind), parsed) in sources.files().zip(parse_files(sources, constants)) {
+        let input = &sources.text()[range.clone()];
+
+        match parsed {
+            Ok(i) => {
+                let i = i.into_iter().map(|(l, mut item, r)| {
+                    item.shift_spans(range.start);
+                    (l + range.start, item, r + range.start)
+                });
+                match kind {
+                    FileKind::Chem => items.extend(i),
+                    FileKind::Targets => add_targets(sources, &mut items, i, args),
+                    FileKind::Patch => apply_patch(sources, &mut items, i, args),
                 }
-            };
+            }
+            Err(e) => {
+                exit_report(
+                    &parse_error_report(e),
+                    Source::from(input.to_string())
+                        .with_filename(filename)
+                );
+            }
+        }
+    }
 
+    let program = match Program::from_items(items) {
+        Ok(program) => program,
+        Err((from, err, to)) => {
             exit_report(
-                &report,
-                Source::from(input.to_string())
-                    .with_filename(filename)
+                &Report::build(ReportKind::Error)
+                    .with_message("parse error".to_string())
+                    .with_label(sources.label((from, to)).with_message(err))
+                    .finish(),
+                sources.source((from, to))
             );
         }
     };
+    if let Some(dir) = &args.cache_dir {
+        cache::store(dir, sources, constants, args.strict, &program);
+    }
+
     program
 }
-
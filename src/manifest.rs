@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use minos::{Label, Report, ReportKind, Source};
+use serde::Deserialize;
+use crate::{exit_report, expected_str};
+
+/// A project spanning several chem files, like
+///
+/// ```toml
+/// sources = ["smelting.chem", "science.chem"]
+///
+/// [constants]
+/// throughput = 60
+///
+/// [profiles.peak]
+/// target = "science"
+/// enable-groups = ["peak"]
+/// constants = { throughput = 120 }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Manifest {
+    /// Chem files, relative to the manifest
+    pub sources: Vec<PathBuf>,
+    /// Values for `${NAME}` placeholders in the chem files
+    #[serde(default)]
+    pub constants: HashMap<String, f64>,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// A named way to solve the project
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    pub target: String,
+    /// Overrides of the manifest's constants
+    #[serde(default)]
+    pub constants: HashMap<String, f64>,
+    #[serde(default)]
+    pub enable_groups: Vec<String>,
+    #[serde(default)]
+    pub disable_groups: Vec<String>,
+    #[serde(default)]
+    pub integer: bool,
+    #[serde(default)]
+    pub strict_branches: bool,
+}
+
+impl Manifest {
+    pub fn read(path: &Path) -> Self {
+        let name = path.to_string_lossy().to_string();
+        let input = match read_to_string(path) {
+            Ok(i) => i,
+            Err(e) => {
+                exit_report(
+                    &Report::build(ReportKind::Error)
+                        .with_message(e.to_string())
+                        .with_label(Label::new(0..name.chars().count()).with_message("while reading this file"))
+                        .finish(),
+                    Source::from(name)
+                );
+            }
+        };
+
+        match toml::from_str(&input) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let span = e.span().unwrap_or(0..0);
+                exit_report(
+                    &Report::build(ReportKind::Error)
+                        .with_message("invalid manifest")
+                        .with_label(Label::new(span).with_message(e.message()))
+                        .finish(),
+                    Source::from(input)
+                        .with_filename(name)
+                );
+            }
+        }
+    }
+
+    /// The chem files of the project, relative to the working directory
+    pub fn source_paths(&self, manifest: &Path) -> Vec<PathBuf> {
+        let dir = manifest.parent().unwrap_or(Path::new(""));
+        self.sources.iter().map(|source| dir.join(source)).collect()
+    }
+
+    pub fn profile(&self, manifest: &Path, name: &str) -> &Profile {
+        let Some(profile) = self.profiles.get(name) else {
+            let manifest = manifest.to_string_lossy().to_string();
+            let help = if self.profiles.is_empty() {
+                "the manifest doesn't define any profiles".to_string()
+            } else {
+                let mut names = self.profiles.keys().collect::<Vec<_>>();
+                names.sort();
+                format!("did you mean {}", expected_str("", names))
+            };
+
+            exit_report(
+                &Report::build(ReportKind::Error)
+                    .with_message(format!("profile '{name}' not found"))
+                    .with_label(Label::new(0..manifest.chars().count()).with_message("in this manifest"))
+                    .with_help(help)
+                    .finish(),
+                Source::from(manifest)
+            );
+        };
+
+        profile
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use crate::ast::{Goal, Program, Symbol, Target};
+use crate::format::NumberFormat;
+use crate::model::Model;
+use crate::solution::Solution;
+
+/// Render a solution as a GitHub-flavored markdown table of the reactions it uses,
+/// followed by a summary, for pasting into issues and wiki pages
+pub fn render_markdown(program: &Program, target: &Target, solution: &Solution, strict_branches: bool, format: NumberFormat) -> String {
+    let model = Model::new(program, strict_branches);
+    let used = (0..program.reactions.len())
+        .filter_map(|idx| Some((idx, solution.get(model.var_name(idx)).filter(|&m| m > 0.0)?)))
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+
+    writeln!(out, "## Solution of `{}`", target.name).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "| Reaction | Machines |").unwrap();
+    writeln!(out, "| --- | ---: |").unwrap();
+    for &(idx, machines) in &used {
+        let name = model.name(idx);
+        writeln!(out, "| {} | {} |", cell(name), format.amount(machines)).unwrap();
+    }
+
+    writeln!(out).unwrap();
+    writeln!(out, "### Summary").unwrap();
+    writeln!(out).unwrap();
+    if let Some(goal) = &target.goal {
+        writeln!(out, "- Goal: minimize {}", goal_str(program, goal)).unwrap();
+    }
+    if let Some(objective) = solution.get("objective") {
+        writeln!(out, "- Objective: {}", format.amount(objective)).unwrap();
+    }
+    match (&target.goal, solution.get("throughput")) {
+        (Some(Goal::Time), Some(throughput)) if throughput > 0.0 => {
+            writeln!(out, "- Time: {}", format.amount(1.0 / throughput)).unwrap();
+        }
+        (Some(Goal::Time), _) => writeln!(out, "- Time: infinite").unwrap(),
+        _ => writeln!(out, "- Time: {}", target.in_time).unwrap(),
+    }
+    writeln!(out, "- Reactions used: {}", used.len()).unwrap();
+    writeln!(out, "- Total machines: {}", format.amount(used.iter().map(|(_, machines)| machines).sum())).unwrap();
+
+    let mut costs = BTreeMap::new();
+    for &(idx, machines) in &used {
+        for (name, cost) in &program.reactions[idx].cost.other {
+            *costs.entry(*name).or_insert(0.0) += cost * machines;
+        }
+    }
+    for (name, total) in costs {
+        writeln!(out, "- Total {}: {}", cell(name), format.amount(total)).unwrap();
+    }
+    if program.reactions.iter().any(|r| r.enthalpy.is_some()) {
+        let absorbed = used.iter().map(|&(idx, machines)| program.reactions[idx].enthalpy.unwrap_or(0.0) * machines).sum::<f64>();
+        writeln!(out, "- Heat released: {} kJ", format.amount(-absorbed)).unwrap();
+    }
+
+    out
+}
+
+/// Text safe to put in a table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+/// A symbol by its display name, after its icon if it has one
+fn symbol_str(program: &Program, symbol: Symbol) -> String {
+    let name = cell(program.display_name(symbol));
+    match program.symbols.get(&symbol).and_then(|info| info.icon.as_deref()) {
+        Some(icon) => format!("![{name}]({icon}) {name}"),
+        None => name,
+    }
+}
+
+fn goal_str(program: &Program, goal: &Goal) -> String {
+    match goal {
+        Goal::Resources(rt) => {
+            let mut terms = rt.iter().map(|(&symbol, amount)| format!("{amount} {}", symbol_str(program, symbol))).collect::<Vec<_>>();
+            terms.sort();
+            format!("resources {}", terms.join(" + "))
+        }
+        Goal::Reactions => "reactions".to_string(),
+        Goal::Time => "time".to_string(),
+        Goal::Cost(name) => format!("cost {name}"),
+        Goal::Techs => "techs".to_string(),
+    }
+}
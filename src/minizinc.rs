@@ -0,0 +1,441 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::thread::available_parallelism;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use crate::ast::Target;
+use crate::backend::Error;
+use crate::codegen::{generate_minizinc, Options, SourceMap};
+use crate::model::Model;
+use crate::solution::Solution;
+use crate::sources::Sources;
+use crate::workdir::work_dir;
+
+/// The environment variable to point at the minizinc binary with, when it can't be found
+pub const MINIZINC_VAR: &str = "MINIZINC";
+
+/// Find the minizinc binary: the one [`MINIZINC_VAR`] points at, the first on the `PATH`
+/// (like `where` or `which` would), or where the installers put it: on windows where the registry
+/// says it's installed, or else where they do by default.
+/// Falls back to just the name, so spawning it fails with the usual error.
+pub fn find_minizinc() -> PathBuf {
+    if let Some(path) = env::var_os(MINIZINC_VAR) {
+        return PathBuf::from(path);
+    }
+
+    let name = if cfg!(windows) { "minizinc.exe" } else { "minizinc" };
+    let on_path = env::var_os("PATH").into_iter().flat_map(|paths| env::split_paths(&paths).collect::<Vec<_>>());
+    on_path
+        .chain(install_dirs())
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Where the minizinc (IDE bundle) installers put the binary, which isn't always on the `PATH`
+fn install_dirs() -> Vec<PathBuf> {
+    if cfg!(windows) {
+        // where the installer says it installed it, or else where it does by default:
+        // for all users, or in the user's own programs
+        let program_files = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+            .into_iter()
+            .filter_map(env::var_os)
+            .map(|dir| PathBuf::from(dir).join("MiniZinc"));
+        let local = env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Programs").join("MiniZinc"));
+        registered_dirs().into_iter().chain(program_files).chain(local).collect()
+    } else if cfg!(target_os = "macos") {
+        vec![PathBuf::from("/Applications/MiniZincIDE.app/Contents/Resources")]
+    } else {
+        // the bundle is an archive or AppImage to unpack anywhere, so there's nowhere to look
+        Vec::new()
+    }
+}
+
+/// The install locations of minizinc in the uninstall entries of the registry, which the installer
+/// adds for all users or the user, by the name of the program and its version like "MiniZinc 2.8.3"
+#[cfg(windows)]
+fn registered_dirs() -> Vec<PathBuf> {
+    use winreg::enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE};
+    use winreg::RegKey;
+
+    let uninstall = [
+        r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+        // where 32-bit installers write them on 64-bit windows
+        r"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+    ];
+    [HKEY_LOCAL_MACHINE, HKEY_CURRENT_USER]
+        .into_iter()
+        .flat_map(|root| uninstall.map(|path| RegKey::predef(root).open_subkey(path)))
+        .filter_map(Result::ok)
+        .flat_map(|programs| programs.enum_keys().filter_map(|name| programs.open_subkey(name.ok()?).ok()).collect::<Vec<_>>())
+        .filter(|program| program.get_value::<String, _>("DisplayName").is_ok_and(|name| name.starts_with("MiniZinc")))
+        .filter_map(|program| program.get_value::<String, _>("InstallLocation").ok())
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn registered_dirs() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// How solving ended, as minizinc reports it
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Status {
+    /// The search finished, so the last solution is optimal
+    #[serde(rename = "OPTIMAL_SOLUTION", alias = "ALL_SOLUTIONS")]
+    Optimal,
+    /// A solution was found, but the search didn't finish, like after a time limit
+    Satisfied,
+    Unsatisfiable,
+    Unbounded,
+    #[serde(rename = "UNSAT_OR_UNBOUNDED")]
+    UnsatisfiableOrUnbounded,
+    Error,
+    /// Nothing was found, but the search didn't finish either
+    #[serde(other)]
+    Unknown,
+}
+
+/// An error minizinc reports, like a type error in the model
+#[derive(Debug)]
+pub struct MinizincError {
+    pub message: String,
+    /// The (1-based) line in the model it's about, if any
+    pub line: Option<usize>,
+}
+
+/// What minizinc reports on solving a model with `--output-mode json --json-stream`,
+/// which is one JSON message per line
+#[derive(Debug)]
+pub struct Outcome {
+    pub status: Status,
+    /// The last (and best) solution found, if any
+    pub solution: Option<Solution>,
+    pub errors: Vec<MinizincError>,
+    /// What minizinc and the solver report about solving, like how long it took, in the order reported
+    pub statistics: Vec<(String, Value)>,
+}
+
+/// A message of the JSON stream, by its `type`
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Message {
+    Solution {
+        output: Option<SolutionOutput>,
+    },
+    Status {
+        status: Status,
+    },
+    Error {
+        message: Option<String>,
+        what: Option<String>,
+        location: Option<Location>,
+    },
+    Statistics {
+        statistics: Map<String, Value>,
+    },
+    /// Comments, warnings and timestamps
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct SolutionOutput {
+    json: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct Location {
+    #[serde(rename = "firstLine")]
+    first_line: Option<usize>,
+}
+
+impl Outcome {
+    /// Parse the messages minizinc wrote. Lines that aren't JSON, like output of the solver
+    /// itself, are ignored.
+    pub fn parse(stream: &str) -> Self {
+        let mut res = Self {
+            status: Status::Unknown,
+            solution: None,
+            errors: Vec::new(),
+            statistics: Vec::new(),
+        };
+
+        for message in stream.lines().filter_map(|line| serde_json::from_str(line).ok()) {
+            match message {
+                Message::Solution { output } => {
+                    res.solution = output.and_then(|output| output.json).and_then(solution_of);
+                    res.status = Status::Satisfied;
+                }
+                Message::Status { status } => res.status = status,
+                Message::Error { message, what, location } => {
+                    let message = message.unwrap_or_else(|| "unknown error".to_string());
+                    res.errors.push(MinizincError {
+                        message: what.map_or(message.clone(), |what| format!("{what}: {message}")),
+                        line: location.and_then(|location| location.first_line),
+                    });
+                    res.status = Status::Error;
+                }
+                Message::Statistics { statistics } => res.statistics.extend(statistics),
+                Message::Other => {}
+            }
+        }
+
+        res
+    }
+}
+
+/// The values of the variables in the `json` output section of a solution.
+/// Depending on the minizinc version, the section is JSON itself, or a string of it.
+fn solution_of(section: Value) -> Option<Solution> {
+    let section = match section {
+        Value::String(text) => serde_json::from_str(&text).ok()?,
+        section => section,
+    };
+    let Value::Object(values) = section else {
+        return None;
+    };
+
+    let values = values
+        .into_iter()
+        .filter_map(|(var, value)| match value {
+            Value::Number(n) => Some((var, n.as_f64()?)),
+            Value::Bool(b) => Some((var, if b { 1.0 } else { 0.0 })),
+            _ => None,
+        })
+        .collect();
+
+    Some(Solution { values })
+}
+
+/// The name of the model in the work directory
+const MODEL_NAME: &str = "program.mzn";
+
+/// Where the model is written, in the work directory of this run
+pub fn model_path() -> Result<PathBuf, Error> {
+    let dir = work_dir().map_err(|e| Error::failed(format!("while creating a directory for the model: {e}")))?;
+    Ok(dir.join(MODEL_NAME))
+}
+
+/// Write the model of a target to [`model_path`], returning where its lines come from
+pub fn write_model(sources: &Sources, model: &Model, target: &Target, options: &Options) -> Result<SourceMap, Error> {
+    let path = model_path()?;
+    let (text, source_map) = generate_minizinc(sources, model, target, options).map_err(Error::Invalid)?;
+
+    let write = || -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(&path)?);
+        f.write_all(text.as_bytes())?;
+        f.flush()
+    };
+    write().map_err(|e| Error::failed(format!("while writing {}: {e}", path.display())))?;
+    Ok(source_map)
+}
+
+/// The solver models are solved with, unless `--portfolio` picks others
+const DEFAULT_SOLVER: &str = "cbc";
+
+/// The minizinc invocation solving the generated model,
+/// reporting the outcome as a stream of JSON messages (see [`Outcome`]).
+/// Minizinc and the solver keep their scratch files in the work directory too.
+pub fn minizinc_command() -> Result<Command, Error> {
+    solver_command(DEFAULT_SOLVER, 1)
+}
+
+/// Like [`minizinc_command`], for a solver sharing the cpus with `others - 1` others
+pub fn solver_command(solver: &str, others: usize) -> Result<Command, Error> {
+    let cpus = available_parallelism().expect("get available parallelism").get();
+    let threads = (cpus / others).max(1).to_string();
+    let model = model_path()?;
+    let dir = model.parent().expect("the model is in the work directory");
+
+    let mut cmd = Command::new(find_minizinc());
+    cmd
+        .args(["--output-mode", "json", "--json-stream", "--statistics"])
+        .args(["--solver", solver])
+        .args(["-p", threads.as_str()])
+        .arg(&model)
+        .current_dir(dir)
+        .env("TMPDIR", dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    Ok(cmd)
+}
+
+fn spawn_error(cmd: &Command, e: io::Error) -> Error {
+    Error::Failed {
+        message: format!("while spawning 'minizinc' process ({}): {e}", cmd.get_program().to_string_lossy()),
+        output: None,
+        help: Some(format!("install minizinc (https://www.minizinc.org), and put it on the PATH or set {MINIZINC_VAR} to it")),
+    }
+}
+
+/// Run minizinc, returning what it reported.
+/// Errors in the model are about the part of the chem file they were generated for, where possible.
+pub fn run_minizinc(cmd: &mut Command, source_map: &SourceMap) -> Result<Outcome, Error> {
+    let output = cmd.output().map_err(|e| spawn_error(cmd, e))?;
+    check_output(&output, source_map)
+}
+
+/// Run minizinc with each of the solvers at once. The outcome of the first solver to finish its search
+/// (proving its solution optimal, or that there is none) is taken, and the others are stopped.
+/// When none of them finish, like after a time limit, the best solution found is taken.
+/// Solvers failing, like when they're not installed, are skipped, unless all of them fail.
+/// Each solver runs in a process group of its own, so stopping it also stops the solver minizinc started.
+pub fn run_portfolio(solvers: &[String], maximize: bool, source_map: &SourceMap) -> Result<Outcome, Error> {
+    let stop = |children: &mut [Child]| {
+        for child in children {
+            #[cfg(unix)]
+            // SAFETY: kill has no memory safety requirements, the group is the one spawned for the child
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            #[cfg(not(unix))]
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut children = Vec::new();
+
+    for (idx, solver) in solvers.iter().enumerate() {
+        let mut cmd = solver_command(solver, solvers.len())?;
+        // solvers may write scratch files with the same names
+        let dir = model_path()?.with_file_name(format!("solver-{idx}"));
+        cmd.current_dir(&dir).env("TMPDIR", &dir);
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+        let spawned = std::fs::create_dir_all(&dir).and_then(|_| cmd.spawn());
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                stop(&mut children);
+                return Err(spawn_error(&cmd, e));
+            }
+        };
+        let (mut stdout, mut stderr) = (child.stdout.take().expect("piped"), child.stderr.take().expect("piped"));
+        let sender = sender.clone();
+        thread::spawn(move || {
+            // both have to be read at once, or the solver can block on writing the other
+            let stderr = thread::spawn(move || {
+                let mut buf = Vec::new();
+                let _ = stderr.read_to_end(&mut buf);
+                buf
+            });
+            let mut buf = Vec::new();
+            let _ = stdout.read_to_end(&mut buf);
+            let _ = sender.send((idx, buf, stderr.join().unwrap_or_default()));
+        });
+        children.push(child);
+    }
+    drop(sender);
+
+    let mut finished = Vec::new();
+    for (idx, stdout, stderr) in receiver {
+        let Ok(status) = children[idx].wait() else {
+            continue;
+        };
+        let output = Output { status, stdout, stderr };
+        let outcome = Outcome::parse(&String::from_utf8_lossy(&output.stdout));
+
+        let failed = !output.status.success() || !outcome.errors.is_empty();
+        let conclusive = !matches!(outcome.status, Status::Satisfied | Status::Unknown | Status::Error);
+        if !failed && conclusive {
+            stop(&mut children);
+            eprintln!("solved with {}, which finished first", solvers[idx]);
+            return Ok(outcome);
+        }
+        finished.push((idx, failed, output, outcome));
+    }
+
+    // solutions of a solver that was stopped early aren't necessarily optimal, so the best one is taken
+    let worst = if maximize { f64::NEG_INFINITY } else { f64::INFINITY };
+    let objective = |outcome: &Outcome| outcome.solution.as_ref().and_then(|s| s.get("objective")).unwrap_or(worst);
+    let best = finished
+        .iter()
+        .filter(|(_, failed, _, _)| !failed)
+        .min_by(|(_, _, _, a), (_, _, _, b)| match maximize {
+            true => objective(b).total_cmp(&objective(a)),
+            false => objective(a).total_cmp(&objective(b)),
+        });
+    match best {
+        Some((idx, _, output, _)) => {
+            eprintln!("solved with {}, which found the best solution", solvers[*idx]);
+            check_output(output, source_map)
+        }
+        None => {
+            let (_, _, output, _) = finished.first().expect("at least one solver");
+            check_output(output, source_map)
+        }
+    }
+}
+
+/// What minizinc reported, or the errors it reported if any
+fn check_output(output: &Output, source_map: &SourceMap) -> Result<Outcome, Error> {
+    let outcome = Outcome::parse(&String::from_utf8_lossy(&output.stdout));
+
+    if let Some(error) = outcome.errors.first() {
+        if let Some(span) = error.line.and_then(|line| source_map.lookup(line)) {
+            return Err(Error::Rejected {
+                message: "minizinc rejected the generated model".to_string(),
+                label: error.message.clone(),
+                span,
+                output: None,
+            });
+        }
+
+        return Err(Error::Failed {
+            message: "while running 'minizinc' process".to_string(),
+            output: Some(outcome.errors.iter().map(|e| &e.message).join("\n")),
+            help: None,
+        });
+    }
+
+    if !output.status.success() {
+        let output = String::from_utf8_lossy(&output.stderr).to_string();
+
+        if let Some((span, label)) = model_error(&output, source_map) {
+            return Err(Error::Rejected {
+                message: "minizinc rejected the generated model".to_string(),
+                label,
+                span,
+                output: Some(output),
+            });
+        }
+
+        return Err(Error::Failed {
+            message: "while running 'minizinc' process".to_string(),
+            output: Some(output),
+            help: None,
+        });
+    }
+
+    Ok(outcome)
+}
+
+/// Find the location in the model of an error minizinc reports, like
+/// `program.mzn:12.5-20:`, and the message that goes with it.
+/// Returns the span in the chem file that location was generated for.
+fn model_error(stderr: &str, source_map: &SourceMap) -> Option<((usize, usize), String)> {
+    let location = format!("{MODEL_NAME}:");
+    let (_, rest) = stderr.split_once(&location)?;
+    let line = rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()?;
+    let span = source_map.lookup(line)?;
+
+    let message = stderr
+        .lines()
+        .find(|l| l.to_lowercase().contains("error"))
+        .map(|l| l.trim().trim_start_matches("MiniZinc:").trim().to_string())
+        .unwrap_or_else(|| "error in the model generated for this".to_string());
+
+    Some((span, message))
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use itertools::Itertools;
+use crate::ast::{Program, Reaction, Symbol};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct SymbolId(pub u32);
+
+/// A reaction using a symbol, and how much of it per reaction
+#[derive(Debug, Copy, Clone)]
+pub struct Use {
+    pub reaction: usize,
+    pub amount: f64,
+    /// how much of it one machine uses per time unit
+    pub rate: f64,
+}
+
+/// A reaction input accepting a range of attributes, like `steam@165..500`,
+/// which can be satisfied by any mix of the variants in that range
+#[derive(Debug)]
+pub struct RangedUse<'s> {
+    pub reaction: usize,
+    pub amount: usize,
+    pub range: Symbol<'s>,
+    pub variants: Vec<SymbolId>,
+}
+
+/// What codegen and the analyses of solutions work on: a checked program, with all its symbols
+/// interned to [`SymbolId`]s, the reactions producing and consuming each of them at
+/// what rate, and its reactions by label.
+///
+/// Built once, so codegen doesn't have to scan every reaction for every symbol.
+pub struct Model<'p, 's> {
+    program: &'p Program<'s>,
+    ids: HashMap<Symbol<'s>, SymbolId>,
+    symbols: Vec<Symbol<'s>>,
+    producers: Vec<Vec<Use>>,
+    consumers: Vec<Vec<Use>>,
+    ranged: Vec<RangedUse<'s>>,
+    /// per symbol, the ranged uses it is a variant of
+    flows: Vec<Vec<usize>>,
+    var_names: Vec<String>,
+    names: Vec<String>,
+    /// the first reaction with each label
+    labels: HashMap<&'p str, usize>,
+}
+
+impl<'p, 's> Model<'p, 's> {
+    /// With `strict_branches`, reactions with random outcomes are
+    /// counted as producing the least they could (see [`Reaction::effective_outputs`](crate::ast::Reaction::effective_outputs))
+    pub fn new(program: &'p Program<'s>, strict_branches: bool) -> Self {
+        let mut res = Self {
+            program,
+            ids: HashMap::new(),
+            symbols: Vec::new(),
+            producers: Vec::new(),
+            consumers: Vec::new(),
+            ranged: Vec::new(),
+            flows: Vec::new(),
+            var_names: program.reactions.iter().map(|i| i.var_name()).collect(),
+            names: program.reactions.iter().map(|i| i.name().into_owned()).collect(),
+            labels: HashMap::new(),
+        };
+        for (reaction, r) in program.reactions.iter().enumerate() {
+            if let Some(label) = &r.label {
+                res.labels.entry(label.as_ref()).or_insert(reaction);
+            }
+        }
+
+        // interned in alphabetical order, so the generated model doesn't depend on the order of hashmaps
+        let symbols = program.reactions
+            .iter()
+            .flat_map(|r| r.inputs.keys().copied().filter(|s| !s.is_range()).chain(r.effective_outputs(strict_branches).into_keys()))
+            .sorted_by_key(|symbol| symbol.0)
+            .dedup();
+        for symbol in symbols {
+            res.intern(symbol);
+        }
+
+        for (reaction, r) in program.reactions.iter().enumerate() {
+            let time = r.cost.time as f64;
+            for (&symbol, &amount) in r.inputs.iter().sorted_by_key(|(symbol, _)| symbol.0) {
+                if symbol.is_range() {
+                    res.ranged.push(RangedUse { reaction, amount, range: symbol, variants: Vec::new() });
+                    continue;
+                }
+
+                let id = res.intern(symbol);
+                let amount = amount as f64;
+                res.consumers[id.0 as usize].push(Use { reaction, amount, rate: amount / time });
+            }
+            for (symbol, amount) in r.effective_outputs(strict_branches) {
+                let id = res.intern(symbol);
+                res.producers[id.0 as usize].push(Use { reaction, amount, rate: amount / time });
+            }
+        }
+
+        for (idx, ranged) in res.ranged.iter_mut().enumerate() {
+            for (id, symbol) in res.symbols.iter().enumerate() {
+                if symbol.in_range(&ranged.range) {
+                    ranged.variants.push(SymbolId(id as u32));
+                    res.flows[id].push(idx);
+                }
+            }
+        }
+
+        res
+    }
+
+    fn intern(&mut self, symbol: Symbol<'s>) -> SymbolId {
+        *self.ids.entry(symbol).or_insert_with(|| {
+            self.symbols.push(symbol);
+            self.producers.push(Vec::new());
+            self.consumers.push(Vec::new());
+            self.flows.push(Vec::new());
+            SymbolId(self.symbols.len() as u32 - 1)
+        })
+    }
+
+    pub fn program(&self) -> &'p Program<'s> {
+        self.program
+    }
+
+    /// All reactions, by index
+    pub fn reactions(&self) -> Range<usize> {
+        0..self.program.reactions.len()
+    }
+
+    pub fn reaction(&self, reaction: usize) -> &'p Reaction<'s> {
+        &self.program.reactions[reaction]
+    }
+
+    /// The first reaction with a label
+    pub fn labeled(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).copied()
+    }
+
+    /// How a reaction is called in output, see [`Reaction::name`](crate::ast::Reaction::name)
+    pub fn name(&self, reaction: usize) -> &str {
+        &self.names[reaction]
+    }
+
+    pub fn id(&self, symbol: &Symbol<'s>) -> Option<SymbolId> {
+        self.ids.get(symbol).copied()
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> Symbol<'s> {
+        self.symbols[id.0 as usize]
+    }
+
+    /// All symbols used by any reaction
+    pub fn symbols(&self) -> impl Iterator<Item=SymbolId> {
+        (0..self.symbols.len() as u32).map(SymbolId)
+    }
+
+    pub fn producers(&self, id: SymbolId) -> &[Use] {
+        &self.producers[id.0 as usize]
+    }
+
+    pub fn consumers(&self, id: SymbolId) -> &[Use] {
+        &self.consumers[id.0 as usize]
+    }
+
+    /// All reaction inputs accepting a range of symbols
+    pub fn ranged(&self) -> &[RangedUse<'s>] {
+        &self.ranged
+    }
+
+    /// The ranged inputs (by index into [`ranged`](Self::ranged)) that this symbol can be used for
+    pub fn flows(&self, id: SymbolId) -> &[usize] {
+        &self.flows[id.0 as usize]
+    }
+
+    /// The variable for how much of a variant flows into a ranged input
+    pub fn flow_var(&self, ranged: usize, variant: SymbolId) -> String {
+        format!("flow_{ranged}_{}", self.symbol(variant).mzn_name())
+    }
+
+    /// The (cached) minizinc variable name of a reaction
+    pub fn var_name(&self, reaction: usize) -> &str {
+        &self.var_names[reaction]
+    }
+}
@@ -0,0 +1,273 @@
+//! The options of solving a target, which every command solving targets takes on the command line.
+//! Backends get them too, for options of their own like `--portfolio`.
+
+use std::path::PathBuf;
+use clap::ValueEnum;
+use crate::format::NumberFormat;
+
+#[derive(clap::Args)]
+pub struct SolveOptions {
+    /// Give a value to `${KEY}` placeholders in the chem files, instead of taking it from the environment
+    #[arg(long, short = 'D', value_name = "KEY=VALUE", value_parser = parse_define)]
+    pub define: Vec<(String, f64)>,
+
+    /// A chem file whose reactions replace the reactions with the same labels in the chem files,
+    /// like a mod changing some recipes. Its other items are added. Can be given several times
+    #[arg(long, value_name = "FILE")]
+    pub patch: Vec<PathBuf>,
+
+    /// A chem file with only targets and suites, solved with the reactions of the chem files,
+    /// like scenarios of your own for a shared library of reactions. Its targets replace those
+    /// with the same names. Can be given several times
+    #[arg(long, value_name = "FILE")]
+    pub targets_file: Vec<PathBuf>,
+
+    /// Keep parsed programs in this directory, so later runs on the same chem files, patches
+    /// and constants don't parse them again. Warnings about patches and targets files are only
+    /// given when parsing, but with `--strict` they fail every run, as its programs are cached apart
+    #[arg(long, value_name = "DIR", env = "CHEM_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Add a constraint to the target, like `--constraint "100 science"`
+    #[arg(long, value_name = "TERMS")]
+    pub constraint: Vec<String>,
+
+    /// Add an input to the target
+    #[arg(long, value_name = "SYMBOL")]
+    pub input: Vec<String>,
+
+    /// Replace the goal of the target, like `--goal reactions` or `--goal "resources water"`
+    #[arg(long, value_name = "GOAL")]
+    pub goal: Option<String>,
+
+    /// The goal of targets without one, which are warned about
+    #[arg(long, value_name = "GOAL", default_value = "reactions")]
+    pub default_goal: String,
+
+    /// Replace the time the target's constraints have to be met in
+    #[arg(long, value_name = "TIME")]
+    pub in_time: Option<usize>,
+
+    /// Solve with several solvers at once, like `--portfolio cbc,highs,scip`, taking the result of
+    /// the first to finish and stopping the others. Which solver is fastest varies a lot between models
+    #[arg(long, value_name = "SOLVERS", value_delimiter = ',')]
+    pub portfolio: Vec<String>,
+
+    /// What to solve models with. Besides minizinc, which solves them with the solvers
+    /// `--portfolio` picks, these are the solvers integrations are registered for
+    #[arg(long, value_name = "NAME", default_value = "minizinc")]
+    pub backend: String,
+
+    /// Arguments to give to the solver (through minizinc)
+    #[arg(long, short, value_name = "SOLVER_ARGS", env="SOLVER_ARGS")]
+    pub solver_arguments: Option<String>,
+
+    /// Enable a (disabled) constraint group of the target
+    #[arg(long, value_name = "GROUP")]
+    pub enable_group: Vec<String>,
+
+    /// Disable a constraint group of the target
+    #[arg(long, value_name = "GROUP")]
+    pub disable_group: Vec<String>,
+
+    /// Allow reactions marked `#[optional(FEATURE)]` to be used
+    #[arg(long, value_name = "FEATURE")]
+    pub enable: Vec<String>,
+
+    /// Solve this target first, and keep at least the machines it needs when solving the target.
+    /// Plans expanding an existing factory, instead of building one from scratch
+    #[arg(long, value_name = "TARGET")]
+    pub freeze: Option<String>,
+
+    /// Only allow whole numbers of machines. The float relaxation is solved first, in-process
+    /// (or with the backend, for targets that aren't linear programs, like with zones),
+    /// and minizinc starts from it rounded up
+    #[arg(long)]
+    pub integer: bool,
+
+    /// With --integer, leave out the reactions the float relaxation uses at most this many machines of.
+    /// Makes large models faster to solve, but the solution may not be the best one.
+    /// When nothing is left out, or the rest has no solution, the whole model is solved
+    #[arg(long, value_name = "MACHINES", requires = "integer")]
+    pub prune_threshold: Option<f64>,
+
+    /// Only check whether the target can be met: find any solution meeting its constraints,
+    /// without optimizing its goal. Much faster for big models
+    #[arg(long, conflicts_with_all = ["all_optimal", "critical", "sample", "minimize_change"])]
+    pub feasible_only: bool,
+
+    /// Find the most of a symbol the target can create, by solving it with ever larger amounts
+    /// in its constraint on the symbol, and then show the solution for that amount
+    #[arg(long, value_name = "SYMBOL")]
+    pub maximize_constraint: Option<String>,
+
+    /// Upper bound on machines per reaction, used to model fixed costs and minimum batches
+    #[arg(long, value_name = "M", default_value_t = 1_000_000.0)]
+    pub big_m: f64,
+
+    /// Require the plan to work out whichever way reactions with random outcomes turn out,
+    /// instead of on average
+    #[arg(long)]
+    pub strict_branches: bool,
+
+    /// Show the solution as a tree of what is needed to create the target, and how
+    #[arg(long)]
+    pub tree: bool,
+
+    /// How to show the solution
+    #[arg(long, value_name = "FORMAT", default_value = "text", conflicts_with = "tree")]
+    pub output_format: OutputFormat,
+
+    /// The most decimals to show numbers in the solution with
+    #[arg(long, value_name = "DIGITS", default_value_t = 5)]
+    pub precision: usize,
+
+    /// Show numbers in the solution with this decimal separator
+    #[arg(long, value_name = "CHAR", default_value_t = '.')]
+    pub decimal_separator: char,
+
+    /// Group the thousands of numbers in the solution with this separator, like `--thousands-separator ,`
+    #[arg(long, value_name = "CHAR")]
+    pub thousands_separator: Option<char>,
+
+    /// Show numbers in the solution in scientific notation, like 1.5e3
+    #[arg(long)]
+    pub scientific: bool,
+
+    /// Also show how much of each raw material the solution uses per time unit
+    #[arg(long)]
+    pub bom: bool,
+
+    /// Group the reactions in the output by their tags or machine types, with subtotals
+    #[arg(long, value_name = "BY")]
+    pub group_by: Option<GroupBy>,
+
+    /// Also show what the solver reports about solving, like how long it took, on stderr
+    #[arg(long)]
+    pub statistics: bool,
+
+    /// Also draw how many machines each reaction needs as a bar chart, in this svg file
+    #[arg(long, value_name = "FILE")]
+    pub chart: Option<PathBuf>,
+
+    /// Also show a schedule repeating the solution: which machine runs which reaction when.
+    /// Reactions with the same machine type share machines. Works best with --integer
+    #[arg(long)]
+    pub schedule: bool,
+
+    /// Also write the schedule of --schedule as CSV to this file
+    #[arg(long, value_name = "FILE")]
+    pub schedule_csv: Option<PathBuf>,
+
+    /// Save the solution in this JSON file, to show or compare with later
+    #[arg(long, value_name = "FILE")]
+    pub save_solution: Option<PathBuf>,
+
+    /// Instead of solving, show a solution saved with --save-solution,
+    /// checking that it still meets the target
+    #[arg(long, value_name = "FILE", conflicts_with = "diff_solution")]
+    pub load_solution: Option<PathBuf>,
+
+    /// Show how the solution differs from one saved with --save-solution,
+    /// which is also given to the solver as a starting point
+    #[arg(long, value_name = "FILE")]
+    pub diff_solution: Option<PathBuf>,
+
+    /// Instead of the goal, change a solution saved with --save-solution as little as possible
+    /// to meet the target, counting every machine added or removed
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["load_solution", "diff_solution"])]
+    pub minimize_change: Option<PathBuf>,
+
+    /// Check that a solution given with --load-solution, --diff-solution or --minimize-change was
+    /// solved from the same chem files, options and solver version, which its stamp records
+    #[arg(long)]
+    pub verify_stamp: bool,
+
+    /// Rescale the model before solving it, which can help when the numbers in it are far apart
+    #[arg(long)]
+    pub auto_scale: bool,
+
+    /// Also show by how much the solution exceeds each target and balance constraint,
+    /// marking those it meets exactly, which are what limits it
+    #[arg(long)]
+    pub slack: bool,
+
+    /// Make warnings about the chem file errors, and also check that every symbol reactions
+    /// use is produced or declared raw, and that every reaction creates something that's used.
+    /// For checking chem files in CI
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Comment the generated model with where each constraint comes from in the chem file
+    #[arg(long)]
+    pub annotate: bool,
+
+    /// Find this many different feasible (but not necessarily optimal) solutions,
+    /// by solving with random objectives instead of the goal
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+
+    /// Seed for the random objectives of --sample
+    #[arg(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+
+    /// Also find up to CAP other solutions with (nearly) the optimal objective,
+    /// using different reactions, and show how they differ
+    #[arg(long, value_name = "CAP", num_args = 0..=1, default_missing_value = "10")]
+    pub all_optimal: Option<usize>,
+
+    /// How far from the optimal objective alternatives may be, relative to it
+    #[arg(long, value_name = "EPSILON", default_value_t = 1e-6)]
+    pub optimal_epsilon: f64,
+
+    /// Find out which reactions the solution depends on most,
+    /// by solving again without each of them
+    #[arg(long)]
+    pub critical: bool,
+
+    /// Only generate the model, and print the minizinc invocation instead of running it
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the generated model instead of solving it
+    #[arg(long, value_name = "FORMAT")]
+    pub emit: Option<Emit>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// A GitHub-flavored table of the reactions used, and a summary
+    Markdown,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Emit {
+    /// The minizinc model, in a stable order so it can be diffed between versions of a program
+    Mzn,
+}
+
+/// What to group reactions by in the output
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum GroupBy {
+    Tag,
+    Machine,
+}
+
+impl SolveOptions {
+    pub fn number_format(&self) -> NumberFormat {
+        NumberFormat {
+            precision: self.precision,
+            decimal_separator: self.decimal_separator,
+            thousands_separator: self.thousands_separator,
+            scientific: self.scientific,
+        }
+    }
+}
+
+fn parse_define(define: &str) -> Result<(String, f64), String> {
+    let (key, value) = define.split_once('=').ok_or("expected KEY=VALUE")?;
+    let value = value.trim().parse().map_err(|_| format!("'{value}' is not a number"))?;
+
+    Ok((key.trim().to_string(), value))
+}
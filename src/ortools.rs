@@ -0,0 +1,107 @@
+use std::env;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use itertools::Itertools;
+use crate::ast::Target;
+use crate::backend::{write_mps, Backend, DryRun, Error};
+use crate::codegen::Options;
+use crate::lp::LinearProgram;
+use crate::minizinc::{Outcome, Status};
+use crate::model::Model;
+use crate::options::SolveOptions;
+use crate::sources::Sources;
+use crate::stamp::version_of;
+
+/// The environment variable to point at or-tools' `solve` binary with, when it's not on the `PATH`
+pub const ORTOOLS_VAR: &str = "ORTOOLS_SOLVE";
+
+/// Solving the target as a linear program with google or-tools, without going through minizinc:
+/// with GLOP, or with CP-SAT when some variables are integers, like with `--integer` or fixed costs.
+///
+/// Runs the `solve` binary of the or-tools distribution on the program written in MPS format.
+pub struct OrTools;
+
+impl Backend for OrTools {
+    fn name(&self) -> &str {
+        "or-tools"
+    }
+
+    fn solve(&self, _sources: &Sources, model: &Model, target: &Target, options: &Options, _args: &SolveOptions) -> Result<Outcome, Error> {
+        let lp = LinearProgram::new(model, target, options)?;
+        let mps = write_mps(&lp)?;
+        let sol = mps.with_file_name("solution.sol");
+        let _ = std::fs::remove_file(&sol);
+
+        let mut cmd = solve_command(&lp, &mps, &sol);
+        let output = cmd.output().map_err(|e| Error::Failed {
+            message: format!("while spawning or-tools' 'solve' process ({}): {e}", cmd.get_program().to_string_lossy()),
+            output: None,
+            help: Some(format!("install or-tools (https://developers.google.com/optimization), and put its bin directory on the PATH or set {ORTOOLS_VAR} to its solve binary")),
+        })?;
+
+        // the status is logged by name, like `MPSOLVER_OPTIMAL`
+        let log = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        let status = [
+            ("MPSOLVER_OPTIMAL", Status::Optimal),
+            ("MPSOLVER_FEASIBLE", Status::Satisfied),
+            ("MPSOLVER_INFEASIBLE", Status::Unsatisfiable),
+            ("MPSOLVER_UNBOUNDED", Status::Unbounded),
+            ("MPSOLVER_NOT_SOLVED", Status::Unknown),
+        ]
+        .into_iter()
+        .find_map(|(name, status)| log.contains(name).then_some(status));
+        let Some(status) = status.filter(|_| output.status.success()) else {
+            return Err(Error::Failed {
+                message: "while running or-tools' 'solve' process".to_string(),
+                output: Some(log),
+                help: None,
+            });
+        };
+
+        let solution = match status {
+            Status::Optimal | Status::Satisfied => read_to_string(&sol).ok().and_then(|sol| {
+                // `=obj= 12.5`, and then a line with the value of each variable that isn't zero
+                let mut values = vec![0.0; lp.variables.len()];
+                for line in sol.lines().filter(|line| !line.starts_with("=obj=")) {
+                    let (var, value) = line.split_whitespace().collect_tuple()?;
+                    values[var.strip_prefix('C')?.parse::<usize>().ok()?] = value.parse().ok()?;
+                }
+                Some(lp.solution(&values))
+            }),
+            _ => None,
+        };
+
+        Ok(Outcome { status, solution, errors: Vec::new(), statistics: Vec::new() })
+    }
+
+    fn dry_run(&self, _sources: &Sources, model: &Model, target: &Target, options: &Options, _args: &SolveOptions) -> Result<DryRun, Error> {
+        let lp = LinearProgram::new(model, target, options)?;
+        let mps = write_mps(&lp)?;
+        let cmd = solve_command(&lp, &mps, &mps.with_file_name("solution.sol"));
+        Ok(DryRun { model: mps, commands: vec![cmd] })
+    }
+
+    fn version(&self) -> String {
+        static VERSION: OnceLock<String> = OnceLock::new();
+        VERSION.get_or_init(|| version_of(&find_solve())).clone()
+    }
+}
+
+/// Find or-tools' `solve` binary: the one [`ORTOOLS_VAR`] points at, or else the first on the `PATH`
+fn find_solve() -> PathBuf {
+    env::var_os(ORTOOLS_VAR).map_or_else(|| PathBuf::from("solve"), PathBuf::from)
+}
+
+/// Solve the program written to `mps`, with GLOP or CP-SAT, writing the solution to `sol`
+fn solve_command(lp: &LinearProgram, mps: &Path, sol: &Path) -> Command {
+    let solver = if lp.variables.iter().any(|v| v.integer) { "sat" } else { "glop" };
+    let mut cmd = Command::new(find_solve());
+    cmd
+        .arg("--input").arg(mps)
+        .args(["--solver", solver])
+        .arg("--sol_file").arg(sol)
+        .current_dir(mps.parent().expect("the model is in the work directory"));
+    cmd
+}
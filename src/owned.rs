@@ -0,0 +1,69 @@
+//! Programs that own what they borrow from, so they can be kept around after parsing,
+//! like in a cache of parsed programs or by a server answering requests about them.
+
+use crate::ast::Program;
+use crate::builder::ProgramBuilder;
+use crate::{parse_checked, Diagnostic};
+
+/// What an [`OwnedProgram`]'s program borrows its strings from
+enum Owner {
+    Source(Box<str>),
+    Builder(Box<ProgramBuilder>),
+}
+
+/// A [`Program`] together with the source or builder it borrows from
+pub struct OwnedProgram {
+    // declared before the owner, so it's dropped before what it borrows
+    program: Program<'static>,
+    /// Never changed while the program is alive. What the program borrows is on the heap,
+    /// so it stays where it is when `self` is moved.
+    owner: Owner,
+}
+
+impl OwnedProgram {
+    /// Parse a chem file, like [`parse_checked`], keeping the source
+    pub fn parse(source: String) -> Result<Self, Vec<Diagnostic>> {
+        let source = source.into_boxed_str();
+        // SAFETY: the program lives as long as the box it borrows from, see the fields
+        let text: &'static str = unsafe { &*(source.as_ref() as *const str) };
+        let program = parse_checked(text)?;
+
+        Ok(Self { program, owner: Owner::Source(source) })
+    }
+
+    /// Build a program, like [`ProgramBuilder::build`], keeping the builder
+    pub fn build(builder: ProgramBuilder) -> Result<Self, Vec<Diagnostic>> {
+        let builder = Box::new(builder);
+        // SAFETY: the program lives as long as the box it borrows from, see the fields
+        let borrowed: &'static ProgramBuilder = unsafe { &*(builder.as_ref() as *const ProgramBuilder) };
+        let program = borrowed.build()?;
+
+        Ok(Self { program, owner: Owner::Builder(builder) })
+    }
+
+    pub fn program(&self) -> &Program<'_> {
+        &self.program
+    }
+
+    /// The source the program was parsed from, unless it was built
+    pub fn source(&self) -> Option<&str> {
+        match &self.owner {
+            Owner::Source(source) => Some(source),
+            Owner::Builder(_) => None,
+        }
+    }
+
+    /// The builder that built the program, unless it was parsed
+    pub fn builder(&self) -> Option<&ProgramBuilder> {
+        match &self.owner {
+            Owner::Source(_) => None,
+            Owner::Builder(builder) => Some(builder),
+        }
+    }
+
+    /// Change the program, like when composing a target from the command line.
+    /// Only strings that live as long as any program can be put in it, like string literals.
+    pub fn with_program_mut<R>(&mut self, f: impl for<'s> FnOnce(&mut Program<'s>) -> R) -> R {
+        f(&mut self.program)
+    }
+}
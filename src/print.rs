@@ -0,0 +1,320 @@
+//! Printing programs back as chem source, for formatting chem files, and for tools rewriting them.
+//!
+//! What's printed parses to the same program, and printing that again gives the same source.
+//! It's not the source the program was parsed from: comments and aliases are gone, conditional
+//! blocks are left out or in, constants are replaced by their values, and sums of terms are sorted.
+//! Numbers are printed like `2.0`, as some places only take floats.
+//! Negative numbers, which only constants can give, can't be printed back.
+
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Result, Write};
+use itertools::Itertools;
+use crate::ast::{Comparison, ConstraintGroup, Goal, LinearConstraint, Priority, Program, Quantity, QuantumSubject, Reaction, ReactionTerms, Suite, Symbol, Target, Tech};
+
+impl Program<'_> {
+    /// The program as chem source, see [the module](crate::print)
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Reaction<'_> {
+    /// The reaction as chem source, see [the module](crate::print)
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+
+    /// What the reaction is called in output and diagnostics: its label, or else what it turns into what,
+    /// like `2 iron-ore → iron-plate`
+    pub fn name(&self) -> Cow<'_, str> {
+        if let Some(label) = &self.label {
+            return Cow::Borrowed(label);
+        }
+
+        let outputs = match self.branches.is_empty() {
+            true => terms(&self.outputs),
+            false => self.branches.iter().map(|b| format!("{:?} {}", b.probability, terms(&b.outputs)).trim_end().to_string()).join(" | "),
+        };
+        Cow::Owned([terms(&self.inputs), "→".to_string(), outputs].into_iter().filter(|part| !part.is_empty()).join(" "))
+    }
+}
+
+impl Target<'_> {
+    /// The target as chem source, see [the module](crate::print)
+    pub fn to_source(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Program<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mut sections = Vec::new();
+
+        if !self.raw.is_empty() {
+            sections.push(format!("raw {};\n", self.raw.iter().map(|s| s.0).sorted().join(", ")));
+        }
+        if !self.transport.is_empty() {
+            let transport = self.transport.iter().sorted_by_key(|(s, _)| s.0);
+            sections.push(transport.map(|(s, cost)| format!("transport {} {cost:?};\n", s.0)).collect());
+        }
+        if !self.techs.is_empty() {
+            sections.push(self.techs.iter().map(Tech::to_string).collect());
+        }
+        if !self.compositions.is_empty() {
+            let compositions = self.compositions.iter().sorted_by_key(|(s, _)| s.0).map(|(s, elements)| {
+                let elements = elements.iter().sorted().map(|(element, amount)| format!("{element}: {amount}")).join(", ");
+                format!("element {} {{ {elements} }}\n", s.0)
+            });
+            sections.push(compositions.collect());
+        }
+        if !self.symbols.is_empty() {
+            let symbols = self.symbols.iter().sorted_by_key(|(s, _)| s.0).map(|(s, info)| {
+                let display = info.display.as_ref().map(|display| format!(" display {};", quoted(display)));
+                let icon = info.icon.as_ref().map(|icon| format!(" icon {};", quoted(icon)));
+                format!("symbol {} {{{}{} }}\n", s.0, display.unwrap_or_default(), icon.unwrap_or_default())
+            });
+            sections.push(symbols.collect());
+        }
+
+        let reactions = self.reactions.iter().filter(|r| r.zone.is_none()).map(|r| r.to_string()).collect::<String>();
+        if !reactions.is_empty() {
+            sections.push(reactions);
+        }
+        for zone in &self.zones {
+            let mut section = format!("zone {} {{\n", zone.name);
+            if let Some(capacity) = zone.capacity {
+                writeln!(section, "    capacity {capacity:?};")?;
+            }
+            for reaction in self.reactions.iter().filter(|r| r.zone == Some(zone.name)) {
+                write!(section, "    {reaction}")?;
+            }
+            section.push_str("}\n");
+            sections.push(section);
+        }
+
+        sections.extend(self.targets.values().sorted_by_key(|t| (t.span, t.name)).map(Target::to_string));
+        sections.extend(self.suites.values().sorted_by_key(|s| (s.span, s.name)).map(Suite::to_string));
+
+        write!(f, "{}", sections.join("\n"))
+    }
+}
+
+impl Display for Reaction<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if let Some(message) = &self.deprecated {
+            write!(f, "#[deprecated({})] ", quoted(message))?;
+        }
+        if let Some(feature) = self.feature {
+            write!(f, "#[optional({feature})] ")?;
+        }
+        if let Some(label) = &self.label {
+            write!(f, "{}: ", quoted(label))?;
+        }
+
+        // a space is needed before `-(`, as identifiers can end in a `-`
+        write!(f, "{}-({})->", spaced(terms(&self.inputs)), self.cost.time)?;
+        if self.branches.is_empty() && !self.outputs.is_empty() {
+            write!(f, " {}", terms(&self.outputs))?;
+        } else if !self.branches.is_empty() {
+            let branches = self.branches.iter().map(|b| format!("{:?} {}", b.probability, terms(&b.outputs)).trim_end().to_string());
+            write!(f, " {}", branches.format(" | "))?;
+        }
+
+        if let Some(cost) = self.fixed_cost {
+            write!(f, " fixed-cost {cost:?}")?;
+        }
+        if let Some(batch) = self.min_batch {
+            write!(f, " min-batch {batch:?}")?;
+        }
+        if let Some(machines) = self.machines {
+            write!(f, " machines {machines:?}")?;
+        }
+        if let Some(curve) = &self.cost_curve {
+            write!(f, " cost-curve {}", curve.iter().map(|(machines, cost)| format!("{machines:?}: {cost:?}")).join(", "))?;
+        }
+        if !self.cost.other.is_empty() {
+            let costs = self.cost.other.iter().sorted_by_key(|(name, _)| **name);
+            write!(f, " cost {{ {} }}", costs.map(|(name, cost)| format!("{name}: {cost:?}")).join(", "))?;
+        }
+        for tag in &self.tags {
+            write!(f, " tag {}", quoted(tag))?;
+        }
+        if let Some(machine) = &self.machine {
+            write!(f, " machine {}", quoted(machine))?;
+        }
+        if !self.requires.is_empty() {
+            write!(f, " requires {}", self.requires.join(", "))?;
+        }
+        if let Some(enthalpy) = self.enthalpy {
+            write!(f, " enthalpy {enthalpy:?}kJ")?;
+        }
+
+        writeln!(f, ";")
+    }
+}
+
+impl Display for Target<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "target {} {{", self.name)?;
+
+        if !self.inputs.is_empty() {
+            writeln!(f, "    using {};", self.inputs.iter().map(|s| s.0).unique().join(", "))?;
+        }
+        for (symbol, amount) in self.constraints.iter().sorted_by_key(|(s, _)| s.0) {
+            let window = self.windows.get(symbol).copied();
+            writeln!(f, "    {};", create(self.comparison(symbol), *amount, symbol.0, window))?;
+        }
+        if self.in_time != 1 {
+            writeln!(f, "    in {};", self.in_time)?;
+        }
+        if let Some(goal) = &self.goal {
+            writeln!(f, "    minimize {};", goal_source(goal))?;
+        }
+        for variant in &self.variants {
+            writeln!(f, "    minimize {}: {};", variant.name, goal_source(&variant.goal))?;
+        }
+        for group in &self.groups {
+            write!(f, "{}", group_source(group))?;
+        }
+        for preference in &self.preferences {
+            writeln!(f, "    prefer {} else penalty {};", terms(&preference.terms), preference.penalty)?;
+        }
+        for quantum in &self.quanta {
+            let subject = match &quantum.subject {
+                QuantumSubject::Symbol(s) => s.0.to_string(),
+                QuantumSubject::Reaction(label) => quoted(label),
+            };
+            writeln!(f, "    quantum {} {subject};", quantum.quantum)?;
+        }
+        if let Some(weight) = self.transport_weight {
+            writeln!(f, "    transport weight {weight:?};")?;
+        }
+        if let Some(budget) = self.transport_budget {
+            writeln!(f, "    transport budget {budget:?};")?;
+        }
+        for (name, budget) in self.budgets.iter().sorted_by_key(|(name, _)| **name) {
+            writeln!(f, "    budget {name} {budget:?};")?;
+        }
+        if !self.features.is_empty() {
+            writeln!(f, "    enable {};", self.features.iter().unique().join(", "))?;
+        }
+        for constraint in &self.linear_constraints {
+            writeln!(f, "    {};", linear_source(constraint))?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Suite<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "suite {} {{", self.name)?;
+        if !self.targets.is_empty() {
+            writeln!(f, "    targets {};", self.targets.iter().map(|(name, _)| name).join(", "))?;
+        }
+        if let Some(in_time) = self.in_time {
+            writeln!(f, "    in {in_time};")?;
+        }
+        if let Some(goal) = &self.goal {
+            writeln!(f, "    minimize {};", goal_source(goal))?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Tech<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "tech {} cost {:?}", self.name, self.cost)?;
+        if !self.requires.is_empty() {
+            write!(f, " requires {}", self.requires.join(", "))?;
+        }
+        writeln!(f, ";")
+    }
+}
+
+/// A sum of terms sorted by symbol, like `2 a + b`
+fn terms(terms: &ReactionTerms) -> String {
+    terms
+        .iter()
+        .sorted_by_key(|(s, _)| s.0)
+        .map(|(s, amount)| match amount {
+            1 => s.0.to_string(),
+            amount => format!("{amount} {}", s.0),
+        })
+        .join(" + ")
+}
+
+/// Some source followed by a space, unless it's empty
+fn spaced(source: String) -> String {
+    if source.is_empty() {
+        source
+    } else {
+        format!("{source} ")
+    }
+}
+
+/// A `create` item, leaving out the comparison and window when they're the default
+fn create(comparison: Comparison, amount: usize, symbol: &str, window: Option<usize>) -> String {
+    let comparison = match comparison {
+        Comparison::AtLeast => String::new(),
+        comparison => format!(" {comparison}"),
+    };
+    let window = window.map_or(String::new(), |window| format!(" in {window}"));
+    let terms = terms(&ReactionTerms::from([(Symbol(symbol), amount)]));
+    format!("create{comparison} {terms}{window}")
+}
+
+fn goal_source(goal: &Goal) -> String {
+    match goal {
+        Goal::Resources(resources) => format!("resources {}", terms(resources)).trim_end().to_string(),
+        Goal::Reactions => "reactions".to_string(),
+        Goal::Time => "time".to_string(),
+        Goal::Cost(name) => format!("cost {name}"),
+        Goal::Techs => "techs".to_string(),
+    }
+}
+
+fn group_source(group: &ConstraintGroup) -> String {
+    let disabled = if group.enabled { "" } else { " disabled" };
+    let priority = match group.priority {
+        Priority::Hard => "",
+        Priority::High => " priority high",
+        Priority::Low => " priority low",
+    };
+    let mut res = format!("    group {}{disabled}{priority} {{\n", quoted(&group.name));
+    for (symbol, amount) in group.constraints.iter().sorted_by_key(|(s, _)| s.0) {
+        let comparison = group.comparisons.get(symbol).copied().unwrap_or_default();
+        res.push_str(&format!("        {};\n", create(comparison, *amount, symbol.0, group.windows.get(symbol).copied())));
+    }
+    res.push_str("    }\n");
+    res
+}
+
+fn linear_source(constraint: &LinearConstraint) -> String {
+    let side = |side: &[(f64, Quantity)]| {
+        side.iter()
+            .map(|(scale, quantity)| match quantity {
+                Quantity::One => format!("{scale:?}"),
+                Quantity::Own => format!("{scale:?} * objective"),
+                Quantity::Of(name, _) => format!("{scale:?} * objective({name})"),
+                Quantity::Net(symbol, _) => format!("{scale:?} * {}", symbol.0),
+            })
+            .join(" + ")
+    };
+    let operator = if constraint.at_most { "<=" } else { ">=" };
+
+    format!("constraint {} {operator} {}", side(&constraint.lhs), side(&constraint.rhs))
+}
+
+/// A string literal with the given contents, for labels, which may not be identifiers.
+/// Strings don't have escapes, a `\"` stays as it is, so only contents with a quote that isn't
+/// preceded by a backslash have to be in the other kind of quotes.
+fn quoted(contents: &str) -> String {
+    let unescaped = |quote: char| contents.match_indices(quote).any(|(idx, _)| !contents[..idx].ends_with('\\'));
+
+    if unescaped('"') && !unescaped('\'') {
+        format!("'{contents}'")
+    } else {
+        format!("\"{contents}\"")
+    }
+}
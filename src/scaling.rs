@@ -0,0 +1,89 @@
+use crate::model::{SymbolId, Model};
+
+/// Factors to rescale the model by, to improve its numerical conditioning.
+///
+/// A reaction's machine variable is replaced by a scaled one in the balance of symbols, and
+/// the balance of each symbol is multiplied by a factor. The original variables stay defined in
+/// terms of the scaled ones, so the solution doesn't have to be unscaled.
+/// Factors are powers of two, so scaling doesn't introduce rounding errors.
+pub struct Scaling {
+    /// per reaction, what its scaled variable is multiplied by
+    columns: Vec<f64>,
+    /// per symbol, what its balance is multiplied by
+    rows: Vec<f64>,
+}
+
+impl Scaling {
+    /// Not scaling anything
+    pub fn none(model: &Model) -> Self {
+        Self {
+            columns: vec![1.0; model.reactions().len()],
+            rows: vec![1.0; model.symbols().count()],
+        }
+    }
+
+    /// Scale the rates of every reaction, and then the rates in the balance of every symbol,
+    /// so that their geometric mean is close to 1
+    pub fn geometric(model: &Model) -> Self {
+        let mut res = Self::none(model);
+
+        for (reaction, r) in model.program().reactions.iter().enumerate() {
+            let time = r.cost.time as f64;
+            let rates = r.inputs
+                .values()
+                .map(|&amount| amount as f64)
+                .chain(r.outputs.values().map(|&amount| amount as f64))
+                .chain(r.branches.iter().flat_map(|b| b.outputs.values()).map(|&amount| amount as f64))
+                .map(|amount| amount / time);
+
+            res.columns[reaction] = balancing_factor(rates);
+        }
+
+        for id in model.symbols() {
+            let rates = model.producers(id)
+                .iter()
+                .chain(model.consumers(id))
+                .map(|u| u.rate * res.columns[u.reaction]);
+
+            res.rows[id.0 as usize] = balancing_factor(rates);
+        }
+
+        res
+    }
+
+    /// What the scaled variable of a reaction is multiplied by to get the number of machines
+    pub fn column(&self, reaction: usize) -> f64 {
+        self.columns[reaction]
+    }
+
+    /// What the balance of a symbol is multiplied by
+    pub fn row(&self, id: SymbolId) -> f64 {
+        self.rows[id.0 as usize]
+    }
+
+    /// The variable to use for a reaction's machines in the balance of symbols
+    pub fn var(&self, model: &Model, reaction: usize) -> String {
+        if self.columns[reaction] == 1.0 {
+            model.var_name(reaction).to_string()
+        } else {
+            scaled_var(model.var_name(reaction))
+        }
+    }
+}
+
+/// The scaled version of a reaction's variable
+pub fn scaled_var(var: &str) -> String {
+    format!("scaled_{var}")
+}
+
+/// The power of two bringing the geometric mean of the smallest and largest value closest to 1
+fn balancing_factor(values: impl Iterator<Item=f64>) -> f64 {
+    let (min, max) = values
+        .filter(|v| *v > 0.0)
+        .fold((f64::INFINITY, 0.0f64), |(min, max), v| (min.min(v), max.max(v)));
+    if max == 0.0 {
+        return 1.0;
+    }
+
+    2f64.powi(-(min * max).sqrt().log2().round() as i32)
+}
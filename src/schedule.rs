@@ -0,0 +1,131 @@
+use std::fmt::Write;
+use itertools::Itertools;
+use crate::ast::Program;
+use crate::codegen::SUPPORT_EPSILON;
+use crate::model::Model;
+use crate::solution::Solution;
+
+/// The longest period a schedule can repeat after, in time units
+const MAX_PERIOD: usize = 100_000;
+
+/// The width of the bars in [`Schedule::render`]
+const BAR_WIDTH: usize = 40;
+
+/// Runs of a reaction, one after the other on one machine
+pub struct Slot {
+    /// Like `assembler 2`, the second machine of the type. Reactions without a machine type
+    /// get machines of their own, named after the reaction.
+    pub machine: String,
+    pub reaction: usize,
+    pub start: usize,
+    pub end: usize,
+    pub runs: usize,
+}
+
+/// Which machine runs which reaction when, repeated every period
+pub struct Schedule {
+    pub period: usize,
+    pub slots: Vec<Slot>,
+}
+
+/// Turn how many machines each reaction needs in a solution into a cyclic schedule. The period
+/// is the least common multiple of the times of the reactions used, so they run a whole number of
+/// times in it. Reactions of the same machine type share its machines, which each run
+/// whole runs of reactions one after the other, so with fractional machines, the runs
+/// are rounded up and a schedule can produce a little more than the solution.
+pub fn schedule(program: &Program, solution: &Solution, strict_branches: bool) -> Result<Schedule, String> {
+    let model = Model::new(program, strict_branches);
+    let used = (0..program.reactions.len())
+        .filter_map(|idx| Some((idx, solution.get(model.var_name(idx)).filter(|&m| m > 0.0)?)))
+        .collect_vec();
+
+    let mut period = 1;
+    for &(idx, _) in &used {
+        let time = program.reactions[idx].cost.time;
+        period = period / gcd(period, time) * time;
+        if period > MAX_PERIOD {
+            return Err(format!("the times of the reactions used have no common multiple up to {MAX_PERIOD}, to repeat a schedule after"));
+        }
+    }
+
+    let groups = used
+        .into_iter()
+        .into_group_map_by(|&(idx, _)| program.reactions[idx].machine.as_deref().unwrap_or(model.name(idx)).to_string());
+
+    let mut slots = Vec::new();
+    for (machine_type, reactions) in groups.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+        // the machine being filled, and until when it's busy
+        let (mut machine, mut busy) = (1, 0);
+        for (idx, machines) in reactions {
+            let time = program.reactions[idx].cost.time;
+            let mut runs = (machines * (period / time) as f64 - SUPPORT_EPSILON).ceil().max(0.0) as usize;
+            while runs > 0 {
+                if busy + time > period {
+                    machine += 1;
+                    busy = 0;
+                }
+                let fit = runs.min((period - busy) / time);
+                slots.push(Slot {
+                    machine: format!("{machine_type} {machine}"),
+                    reaction: idx,
+                    start: busy,
+                    end: busy + fit * time,
+                    runs: fit,
+                });
+                busy += fit * time;
+                runs -= fit;
+            }
+        }
+    }
+
+    Ok(Schedule { period, slots })
+}
+
+impl Schedule {
+    /// A Gantt chart of the schedule, a row for each slot with a bar of when it runs in the period
+    pub fn render(&self, program: &Program, strict_branches: bool) -> String {
+        let model = Model::new(program, strict_branches);
+        let machine_width = self.slots.iter().map(|s| s.machine.chars().count()).max().unwrap_or(0);
+        let reaction_width = self.slots.iter().map(|s| model.name(s.reaction).chars().count()).max().unwrap_or(0);
+        let mut out = format!("schedule, repeating every {} time units:\n", self.period);
+
+        for slot in &self.slots {
+            // every slot shows, even if it's shorter than a column
+            let from = slot.start * BAR_WIDTH / self.period;
+            let to = (slot.end * BAR_WIDTH).div_ceil(self.period).max(from + 1);
+            let bar = (0..BAR_WIDTH).map(|col| if (from..to).contains(&col) { '#' } else { '.' }).collect::<String>();
+            writeln!(
+                out,
+                "    {:<machine_width$}  {:<reaction_width$}  {:>6}..{:<6} {:>5} runs  |{bar}|",
+                slot.machine, model.name(slot.reaction), slot.start, slot.end, slot.runs,
+            ).unwrap();
+        }
+
+        out
+    }
+
+    /// The schedule as CSV, with a header and a row for each slot
+    pub fn to_csv(&self, program: &Program, strict_branches: bool) -> String {
+        let model = Model::new(program, strict_branches);
+        let mut out = "machine,reaction,start,end,runs\n".to_string();
+        for slot in &self.slots {
+            writeln!(out, "{},{},{},{},{}", csv_field(&slot.machine), csv_field(model.name(slot.reaction)), slot.start, slot.end, slot.runs).unwrap();
+        }
+        out
+    }
+}
+
+/// A field quoted when it needs to be
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
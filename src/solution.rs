@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs::{read_to_string, write};
+use std::path::Path;
+use itertools::Itertools;
+use minos::{Label, Report, ReportKind, Source};
+use serde::{Deserialize, Serialize, Serializer};
+use crate::Failure;
+use crate::stamp::Stamp;
+
+/// The values minizinc assigned to the model's variables
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Solution {
+    #[serde(serialize_with = "sorted")]
+    pub values: HashMap<String, f64>,
+}
+
+/// A solution saved with `--save-solution`, and the target it solves
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    target: String,
+    /// Solutions saved before stamps were added don't have one
+    stamp: Option<Stamp>,
+    values: Solution,
+}
+
+/// The values by name, so saved solutions are the same when they are
+fn sorted<S: Serializer>(values: &HashMap<String, f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(values.iter().sorted_by(|(a, _), (b, _)| a.cmp(b)))
+}
+
+impl Solution {
+    pub fn get(&self, var: &str) -> Option<f64> {
+        self.values.get(var).copied()
+    }
+
+    /// Save the solution of a target with `--save-solution`, as JSON like
+    ///
+    /// ```json
+    /// {
+    ///   "target": "science",
+    ///   "stamp": {
+    ///     "sources": "5d3ac9e1b2f04a77",
+    ///     "options": "0e6f2b9c81d4a353",
+    ///     "solver": "MiniZinc to FlatZinc converter, version 2.8.3, build 1"
+    ///   },
+    ///   "values": {
+    ///     "objective": 3,
+    ///     "machine_1iron_into_1gear": 1.5
+    ///   }
+    /// }
+    /// ```
+    pub fn write(&self, path: &Path, target: &str, stamp: &Stamp) -> Result<(), Failure> {
+        let saved = Saved { target: target.to_string(), stamp: Some(stamp.clone()), values: self.clone() };
+        let json = serde_json::to_string_pretty(&saved).expect("solutions are made of strings and numbers");
+
+        write(path, format!("{json}\n")).map_err(|e| {
+            let name = path.to_string_lossy().to_string();
+            Failure::new(
+                Report::build(ReportKind::Error)
+                    .with_message(e.to_string())
+                    .with_label(Label::new(0..name.chars().count()).with_message("while writing this file"))
+                    .finish(),
+                Source::from(name)
+            )
+        })
+    }
+
+    /// Read a solution saved by [`Solution::write`], the name of the target it solves,
+    /// and its stamp. Solutions saved before stamps were added don't have one.
+    pub fn read(path: &Path) -> Result<(String, Option<Stamp>, Self), Failure> {
+        let name = path.to_string_lossy().to_string();
+        let input = read_to_string(path).map_err(|e| Failure::new(
+            Report::build(ReportKind::Error)
+                .with_message(e.to_string())
+                .with_label(Label::new(0..name.chars().count()).with_message("while reading this file"))
+                .finish(),
+            Source::from(name.clone())
+        ))?;
+
+        let saved: Saved = serde_json::from_str(&input).map_err(|e| {
+            let offset = offset(&input, e.line(), e.column());
+            Failure::new(
+                Report::build(ReportKind::Error)
+                    .with_message("invalid saved solution")
+                    .with_label(Label::new(offset..offset + 1).with_message(e.to_string()))
+                    .finish(),
+                Source::from(input.clone())
+                    .with_filename(name)
+            )
+        })?;
+        Ok((saved.target, saved.stamp, saved.values))
+    }
+}
+
+/// The offset of a (1-based) line and column in the text, like serde_json reports errors at
+fn offset(text: &str, line: usize, column: usize) -> usize {
+    let start = text.split_inclusive('\n').take(line.saturating_sub(1)).map(str::len).sum::<usize>();
+    (start + column.saturating_sub(1)).min(text.len().saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,41 @@
+//! Saved solutions read back the same as they were written.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::process;
+use crate::solution::Solution;
+use crate::stamp::Stamp;
+
+#[test]
+fn saved_solutions_load_the_same() {
+    let solution = Solution {
+        values: HashMap::from([
+            ("objective".to_string(), 3.0),
+            ("machine_1iron_into_1gear".to_string(), 1.5),
+            ("machine_2gear_into_1engine".to_string(), 1.0 / 3.0),
+        ]),
+    };
+    let stamp = Stamp { sources: "5d3ac9e1b2f04a77".to_string(), options: "0e6f2b9c81d4a353".to_string(), solver: "unknown".to_string() };
+    let path = env::temp_dir().join(format!("reaction-solver-{}-solution.json", process::id()));
+
+    assert!(solution.write(&path, "science", &stamp).is_ok(), "write the solution");
+    let Ok((target, loaded_stamp, loaded)) = Solution::read(&path) else {
+        panic!("read the saved solution");
+    };
+    fs::remove_file(&path).expect("remove the saved solution");
+
+    assert_eq!(target, "science");
+    assert_eq!(loaded_stamp, Some(stamp));
+    assert_eq!(loaded.values, solution.values);
+}
+
+#[test]
+fn invalid_saved_solutions_are_errors() {
+    let path = env::temp_dir().join(format!("reaction-solver-{}-invalid.json", process::id()));
+    fs::write(&path, "{\"target\": 3}").expect("write the invalid solution");
+    let read = Solution::read(&path);
+    fs::remove_file(&path).expect("remove the invalid solution");
+
+    assert!(read.is_err());
+}
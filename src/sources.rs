@@ -0,0 +1,121 @@
+use std::fs::read_to_string;
+use std::ops::Range;
+use std::path::PathBuf;
+use elsa::sync::FrozenVec;
+use minos::{Label, Report, ReportKind, Source};
+use crate::Failure;
+
+/// The chem files making up a program, one after the other.
+///
+/// Spans in the program point into the combined text, and are mapped
+/// back to the file they're in when reporting.
+pub struct Sources {
+    text: String,
+    files: Vec<SourceFile>,
+    /// Bytes programs borrow from besides the text, like cached ones
+    kept: FrozenVec<Vec<u8>>,
+}
+
+struct SourceFile {
+    name: String,
+    range: Range<usize>,
+    kind: FileKind,
+}
+
+/// What a chem file is for
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FileKind {
+    /// Reactions, targets, and everything else
+    Chem,
+    /// Only targets and suites, see `--targets-file`
+    Targets,
+    /// Patches the files before it, see `--patch`
+    Patch,
+}
+
+impl Sources {
+    /// Files by name, contents and what they're for
+    pub fn new<'a>(files: impl IntoIterator<Item=(String, &'a str, FileKind)>) -> Self {
+        let mut res = Self { text: String::new(), files: Vec::new(), kept: FrozenVec::new() };
+
+        for (name, contents, kind) in files {
+            let start = res.text.len();
+            res.text.push_str(contents);
+            res.files.push(SourceFile { name, range: start..res.text.len(), kind });
+            res.text.push('\n');
+        }
+
+        res
+    }
+
+    /// Read all files, those with only targets, and the patches to apply to them,
+    /// returning the report of the first one that can't be read
+    pub fn read(paths: &[PathBuf], targets: &[PathBuf], patches: &[PathBuf]) -> Result<Self, Failure> {
+        let files = paths.iter().chain(targets).chain(patches).map(|path| {
+            let name = path.to_string_lossy().to_string();
+
+            match read_to_string(path) {
+                Ok(contents) => Ok((name, contents)),
+                Err(e) => Err(Failure::new(
+                    Report::build(ReportKind::Error)
+                        .with_message(e.to_string())
+                        .with_label(Label::new(0..name.chars().count()).with_message("while reading this file"))
+                        .finish(),
+                    Source::from(name)
+                )),
+            }
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let kind = |idx: usize| match idx {
+            idx if idx < paths.len() => FileKind::Chem,
+            idx if idx < paths.len() + targets.len() => FileKind::Targets,
+            _ => FileKind::Patch,
+        };
+        Ok(Self::new(files.iter().enumerate().map(|(idx, (name, contents))| (name.clone(), contents.as_str(), kind(idx)))))
+    }
+
+    /// All files one after the other
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Keep bytes for as long as the sources, so a program can borrow from them
+    pub fn keep(&self, bytes: Vec<u8>) -> &[u8] {
+        self.kept.push_get(bytes)
+    }
+
+    /// The names of the files, where they are in [`text`](Self::text), and what they're for
+    pub fn files(&self) -> impl Iterator<Item=(&str, Range<usize>, FileKind)> {
+        self.files.iter().map(|f| (f.name.as_str(), f.range.clone(), f.kind))
+    }
+
+    fn file(&self, offset: usize) -> &SourceFile {
+        let idx = self.files.partition_point(|f| f.range.end < offset);
+        &self.files[idx.min(self.files.len() - 1)]
+    }
+
+    /// The name of the file an offset is in
+    pub fn file_name(&self, offset: usize) -> &str {
+        &self.file(offset).name
+    }
+
+    /// A label on a span of the program, within the file it's in
+    pub fn label(&self, span: (usize, usize)) -> Label {
+        let start = self.file(span.0).range.start;
+        Label::new(span.0 - start..span.1 - start)
+    }
+
+    /// The file a span is in, to report on it
+    pub fn source(&self, span: (usize, usize)) -> Source {
+        let file = self.file(span.0);
+        Source::from(self.text[file.range.clone()].to_string())
+            .with_filename(&file.name)
+    }
+
+    /// Where an offset in the program is, like `real.chem:12`
+    pub fn location(&self, offset: usize) -> String {
+        let file = self.file(offset);
+        let line = self.text[file.range.start..offset].matches('\n').count() + 1;
+        format!("{}:{line}", file.name)
+    }
+}
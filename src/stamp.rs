@@ -0,0 +1,65 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::process::Command;
+use serde::{Deserialize, Serialize};
+
+/// What a model, report or saved solution was made from: hashes of the chem files and of the
+/// options that change the model, and the version of the solver that solved it.
+/// Two runs with the same stamp solve the same model the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stamp {
+    pub sources: String,
+    pub options: String,
+    pub solver: String,
+}
+
+impl Stamp {
+    /// Stamp the (concatenated) text of the chem files, a description of the options,
+    /// and the version of the backend's solver, like [`Backend::version`](crate::backend::Backend::version)
+    pub fn new(sources: &str, options: &str, solver: String) -> Self {
+        Self {
+            sources: hash(sources),
+            options: hash(options),
+            solver,
+        }
+    }
+
+    /// What's different between the inputs of two stamps, like "the chem files"
+    pub fn differences(&self, other: &Stamp) -> Vec<&'static str> {
+        [
+            (self.sources != other.sources, "the chem files"),
+            (self.options != other.options, "the options"),
+            (self.solver != other.solver, "the solver version"),
+        ]
+            .into_iter()
+            .filter_map(|(differs, what)| differs.then_some(what))
+            .collect()
+    }
+}
+
+impl Display for Stamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sources {}, options {}, solver {}", self.sources, self.options, self.solver)
+    }
+}
+
+/// A 64-bit FNV-1a hash of some text, in hex. Unlike the hashers of std,
+/// it's the same on every platform and version, so stamps can be compared between machines.
+pub fn hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// The first line of a solver's `--version`, like "MiniZinc to FlatZinc converter, version 2.8.3, build 1"
+/// for minizinc, or "unknown" when it can't be run
+pub fn version_of(solver: &Path) -> String {
+    Command::new(solver)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| Some(String::from_utf8_lossy(&output.stdout).lines().next()?.trim().to_string()))
+        .filter(|line| !line.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
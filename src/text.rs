@@ -0,0 +1,163 @@
+use std::fmt::Write;
+use itertools::Itertools;
+use crate::ast::{Comparison, Goal, Program, Target};
+use crate::codegen::{cost_var, raw_needed_var, surplus_balance_var, surplus_target_var, tech_var, SUPPORT_EPSILON};
+use crate::format::NumberFormat;
+use crate::model::{Model, Use};
+use crate::options::GroupBy;
+use crate::solution::Solution;
+
+/// Render a solution the way it's shown by default: how many machines each used reaction needs,
+/// followed by the time, the objective per resource, the heat released, costs, and with `--bom` and `--slack`, the raw materials and slack
+/// the model computed.
+pub fn render_text(program: &Program, target: &Target, solution: &Solution, strict_branches: bool, group_by: Option<GroupBy>, format: NumberFormat) -> String {
+    let model = Model::new(program, strict_branches);
+    let machines = |idx: usize| solution.get(model.var_name(idx)).unwrap_or(0.0);
+    let width = (0..program.reactions.len()).map(|idx| model.name(idx).chars().count()).max().unwrap_or(0);
+    let mut out = String::new();
+
+    let row = |out: &mut String, indent: &str, name: &str, amount: f64| {
+        writeln!(out, "{indent}{name:<width$} ={:>8}", format.amount(amount)).unwrap();
+    };
+
+    match group_by {
+        None => {
+            for idx in (0..program.reactions.len()).filter(|&idx| machines(idx) > 0.0) {
+                row(&mut out, "", model.name(idx), machines(idx));
+            }
+        }
+        Some(group_by) => {
+            // reactions can have several tags, and are shown under each of them
+            let groups = program.reactions
+                .iter()
+                .enumerate()
+                .flat_map(|(idx, reaction)| {
+                    let keys = match group_by {
+                        GroupBy::Tag => reaction.tags.iter().map(|t| Some(t.as_ref())).collect_vec(),
+                        GroupBy::Machine => vec![reaction.machine.as_deref()],
+                    };
+                    let keys = if keys.is_empty() { vec![None] } else { keys };
+                    keys.into_iter().map(move |key| (key, idx))
+                })
+                .into_group_map();
+
+            // ungrouped reactions come last
+            for (key, reactions) in groups.into_iter().sorted_by_key(|(key, _)| (key.is_none(), *key)) {
+                let subtotal = reactions.iter().map(|&idx| machines(idx)).sum::<f64>();
+                if subtotal <= 0.0 {
+                    continue;
+                }
+
+                let header = match (group_by, key) {
+                    (GroupBy::Tag, Some(tag)) => format!("tag {tag}"),
+                    (GroupBy::Tag, None) => "untagged".to_string(),
+                    (GroupBy::Machine, Some(machine)) => format!("machine {machine}"),
+                    (GroupBy::Machine, None) => "no machine type".to_string(),
+                };
+                writeln!(out, "{header}:").unwrap();
+                for &idx in reactions.iter().filter(|&&idx| machines(idx) > 0.0) {
+                    row(&mut out, "    ", model.name(idx), machines(idx));
+                }
+                row(&mut out, "    ", "subtotal", subtotal);
+            }
+        }
+    }
+
+    if let Some(Goal::Techs) = target.goal {
+        for tech in program.techs.iter().filter(|tech| solution.get(&tech_var(tech.name)).is_some_and(|x| x > 0.0)) {
+            writeln!(out, "unlock {}", tech.name).unwrap();
+        }
+    }
+
+    if let Some(Goal::Time) = target.goal {
+        match solution.get("throughput").filter(|&throughput| throughput > 0.0) {
+            Some(throughput) => row(&mut out, "", "time", 1.0 / throughput),
+            None => writeln!(out, "{:<width$} = infinite", "time").unwrap(),
+        }
+    }
+
+    // what each resource adds to the objective, to check the weights by. Like the objective,
+    // the amounts are per machine, and inputs of ranges of attributes aren't counted.
+    if let Some(Goal::Resources(resources)) = &target.goal {
+        if resources.len() > 1 {
+            let total = |uses: &[Use]| uses.iter().map(|u| u.amount * machines(u.reaction)).sum::<f64>();
+            let rows = resources
+                .iter()
+                .sorted_by_key(|(symbol, _)| symbol.0)
+                .map(|(symbol, &weight)| {
+                    let net = model.id(symbol).map_or(0.0, |id| total(model.consumers(id)) - total(model.producers(id)));
+                    (program.display_name(*symbol), net, weight)
+                })
+                .collect_vec();
+            let width = rows.iter().map(|(name, _, _)| name.chars().count()).chain(["total".len()]).max().unwrap_or(0);
+            writeln!(out, "\nobjective per resource, consumed minus produced times weight:").unwrap();
+            for &(name, net, weight) in &rows {
+                writeln!(out, "    {name:<width$} ={:>8} * {weight:<4} ={:>8}", format.amount(net), format.amount(net * weight as f64)).unwrap();
+            }
+            let objective = rows.iter().map(|&(_, net, weight)| net * weight as f64).sum::<f64>();
+            writeln!(out, "    {:<pad$} ={:>8}", "total", format.amount(objective), pad = width + 17).unwrap();
+        }
+    }
+
+    // reactions run as often per time unit as they have machines
+    if program.reactions.iter().any(|r| r.enthalpy.is_some()) {
+        let absorbed = program.reactions.iter().enumerate().map(|(idx, r)| r.enthalpy.unwrap_or(0.0) * machines(idx)).sum::<f64>();
+        writeln!(out, "\nheat per time unit, released minus absorbed: {} kJ", format.amount(-absorbed)).unwrap();
+    }
+
+    let costs = program.reactions.iter().flat_map(|r| r.cost.other.keys()).unique().sorted();
+    for name in costs {
+        if let Some(total) = solution.get(&cost_var(name)) {
+            row(&mut out, "", name, total);
+        }
+    }
+
+    // raw materials are only in the solution with `--bom`, and only shown when some is needed
+    let raw = model.symbols()
+        .map(|id| model.symbol(id))
+        .filter_map(|symbol| Some((program.display_name(symbol), solution.get(&raw_needed_var(&symbol))?)))
+        .sorted_by_key(|&(name, _)| name)
+        .collect_vec();
+    if !raw.is_empty() {
+        let width = raw.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        writeln!(out, "\nraw materials per time unit:").unwrap();
+        for (name, needed) in raw.into_iter().filter(|&(_, needed)| needed > 0.0) {
+            writeln!(out, "    {name:<width$} ={:>8}", format.amount(needed)).unwrap();
+        }
+    }
+
+    // in the same order as the constraints in the model
+    let mut slacks = Vec::new();
+    for symbol in target.active_constraints().into_keys().sorted_by_key(|symbol| symbol.0) {
+        if let Some(slack) = solution.get(&surplus_target_var(&symbol)) {
+            let create = match target.comparison(&symbol) {
+                Comparison::AtLeast => format!("create {}", program.display_name(symbol)),
+                comparison => format!("create {comparison} {}", program.display_name(symbol)),
+            };
+            slacks.push((create, slack));
+        }
+    }
+    for symbol in model.symbols().map(|id| model.symbol(id)) {
+        if let Some(slack) = solution.get(&surplus_balance_var(&symbol, None)) {
+            slacks.push((format!("balance of {}", program.display_name(symbol)), slack));
+        }
+        // the last zone is the default zone
+        for zone in 0..=program.zones.len() {
+            if let Some(slack) = solution.get(&surplus_balance_var(&symbol, Some(zone))) {
+                let zone = program.zones.get(zone).map_or("outside of any zone".to_string(), |z| format!("in zone {}", z.name));
+                slacks.push((format!("balance of {} {zone}", program.display_name(symbol)), slack));
+            }
+        }
+    }
+    if !slacks.is_empty() {
+        // constraints that are met exactly are what limits the solution
+        let width = slacks.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        writeln!(out, "\nslack per time unit:").unwrap();
+        for (name, slack) in slacks {
+            let tight = if slack <= SUPPORT_EPSILON { " (tight)" } else { "" };
+            writeln!(out, "    {name:<width$} ={:>8}{tight}", format.amount(slack)).unwrap();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use crate::ast::{Comparison, Goal, Program, Symbol, Target};
+use crate::format::NumberFormat;
+use crate::model::{SymbolId, Model};
+use crate::solution::Solution;
+
+/// Render a solution as a tree, starting from what the target creates and
+/// going down through the reactions producing it and what they need in turn.
+///
+/// Amounts are what is needed over the target's time. A symbol needed by several
+/// parents is expanded once, the first time it is encountered, for the total
+/// amount the whole plan needs of it.
+pub fn render_tree(program: &Program, target: &Target, solution: &Solution, strict_branches: bool, format: NumberFormat) -> String {
+    let model = Model::new(program, strict_branches);
+    let time = match target.goal {
+        Some(Goal::Time) => 1.0 / solution.get("throughput").unwrap_or(f64::NAN),
+        _ => target.in_time as f64,
+    };
+
+    let mut tree = Tree {
+        target,
+        solution,
+        model,
+        time,
+        format,
+        expanded: HashSet::new(),
+        out: String::new(),
+    };
+
+    // what's created at most isn't needed for anything
+    let mut roots = target.active_constraints()
+        .into_iter()
+        .filter(|(symbol, _)| target.comparison(symbol) != Comparison::AtMost)
+        .collect::<Vec<_>>();
+    roots.sort_by_key(|(symbol, _)| symbol.0);
+    for (symbol, _) in roots {
+        let amount = tree.required(symbol).unwrap_or(0.0);
+        tree.symbol(symbol, amount, 0);
+    }
+
+    tree.out
+}
+
+struct Tree<'a, 's> {
+    target: &'a Target<'s>,
+    solution: &'a Solution,
+    model: Model<'a, 's>,
+    time: f64,
+    format: NumberFormat,
+    expanded: HashSet<Symbol<'s>>,
+    out: String,
+}
+
+impl<'s> Tree<'_, 's> {
+    fn line(&mut self, depth: usize, line: String) {
+        writeln!(self.out, "{:indent$}{line}", "", indent = depth * 4).unwrap();
+    }
+
+    fn machines(&self, reaction: usize) -> f64 {
+        self.solution.get(self.model.var_name(reaction)).unwrap_or(0.0)
+    }
+
+    /// How much of a symbol the target needs over its time, if any. Constraints with a time window
+    /// of their own need as much over the target's time as they need at the same rate over their window.
+    fn required(&self, symbol: Symbol<'s>) -> Option<f64> {
+        if self.target.comparison(&symbol) == Comparison::AtMost {
+            return None;
+        }
+
+        let amount = *self.target.active_constraints().get(&symbol)? as f64;
+        Some(amount * self.time / self.target.window(&symbol) as f64)
+    }
+
+    /// How much of a symbol the whole plan consumes over the target's time,
+    /// and how many reactions (or the target itself) it is consumed by
+    fn demand(&self, id: SymbolId, symbol: Symbol<'s>) -> (f64, usize) {
+        let mut needed = 0.0;
+        let mut parents = 0;
+
+        if let Some(amount) = self.required(symbol) {
+            needed += amount;
+            parents += 1;
+        }
+        for u in self.model.consumers(id) {
+            let machines = self.machines(u.reaction);
+            if machines > 0.0 {
+                needed += u.rate * machines * self.time;
+                parents += 1;
+            }
+        }
+
+        (needed, parents)
+    }
+
+    fn symbol(&mut self, symbol: Symbol<'s>, amount: f64, depth: usize) {
+        let name = format!("{} {}", self.format.amount(amount), self.model.program().display_name(symbol));
+
+        if self.target.inputs.contains(&symbol) {
+            self.line(depth, format!("{name} (input)"));
+            return;
+        }
+        if self.model.program().raw.contains(&symbol) {
+            self.line(depth, format!("{name} (raw)"));
+            return;
+        }
+        if !self.expanded.insert(symbol) {
+            self.line(depth, format!("{name} (see above)"));
+            return;
+        }
+
+        let Some(id) = self.model.id(&symbol) else {
+            self.line(depth, format!("{name} (not produced)"));
+            return;
+        };
+
+        // how much each reaction produces of this symbol over the target's time
+        let producers = self.model
+            .producers(id)
+            .iter()
+            .map(|u| (u.reaction, u.rate * self.machines(u.reaction) * self.time))
+            .filter(|(_, produced)| *produced > 0.0)
+            .collect::<Vec<_>>();
+        let total: f64 = producers.iter().map(|(_, produced)| produced).sum();
+
+        if producers.is_empty() {
+            self.line(depth, format!("{name} (not produced)"));
+            return;
+        }
+
+        // shared sub-chains are expanded for everything that needs them
+        let (needed, parents) = self.demand(id, symbol);
+        let amount = if parents > 1 {
+            self.line(depth, format!("{name} ({} in total, shared)", self.format.amount(needed)));
+            needed
+        } else {
+            self.line(depth, name);
+            amount
+        };
+
+        for (reaction, produced) in producers {
+            // the part of this reaction that's needed for `amount`
+            let share = (amount * produced / total) / produced;
+            let r = self.model.reaction(reaction);
+            let machines = self.machines(reaction) * share;
+
+            self.line(depth + 1, format!("via {} ({} machines)", self.model.name(reaction), self.format.amount(machines)));
+
+            let mut inputs = r.inputs.iter().map(|(&s, &i)| (s, i)).collect::<Vec<_>>();
+            inputs.sort_by_key(|(symbol, _)| symbol.0);
+            for (input, i) in inputs {
+                let needed = i as f64 * machines / r.cost.time as f64 * self.time;
+                if input.is_range() {
+                    self.line(depth + 2, format!("{} {} (any in range)", self.format.amount(needed), input.0));
+                } else {
+                    self.symbol(input, needed, depth + 2);
+                }
+            }
+        }
+    }
+}
+
+/// Format an amount with at most 5 decimals
+pub fn amount_str(amount: f64) -> String {
+    let res = format!("{amount:.5}");
+    let res = res.trim_end_matches('0').trim_end_matches('.');
+    if res == "-0" { "0".to_string() } else { res.to_string() }
+}
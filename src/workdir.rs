@@ -0,0 +1,39 @@
+use std::fs::{create_dir, remove_dir_all};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The directory of this run, holding the model and the solver's scratch files,
+/// so runs at the same time (like in a CI matrix) don't overwrite each other's files
+static WORK_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Whether to leave the work directory when exiting, like for `--dry-run`
+static KEEP: AtomicBool = AtomicBool::new(false);
+
+/// The work directory of this run, created the first time it's needed
+pub fn work_dir() -> io::Result<&'static Path> {
+    if let Some(dir) = WORK_DIR.get() {
+        return Ok(dir);
+    }
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let dir = std::env::temp_dir().join(format!("reaction-solver-{}-{nanos}", process::id()));
+    create_dir(&dir)?;
+
+    Ok(WORK_DIR.get_or_init(|| dir))
+}
+
+/// Don't remove the work directory when exiting, because its files are still needed
+pub fn keep_work_dir() {
+    KEEP.store(true, Ordering::Relaxed);
+}
+
+/// Remove the work directory, if this run made one. Called before exiting.
+pub fn remove_work_dir() {
+    if let (Some(dir), false) = (WORK_DIR.get(), KEEP.load(Ordering::Relaxed)) {
+        // not being able to clean up isn't worth failing over
+        let _ = remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,31 @@
+//! The examples are documentation of the DSL, and tests of the whole solver:
+//! they all have to generate a model, and, when minizinc is installed, have a solution.
+
+use std::env;
+use std::process::Command;
+
+fn run_examples(extra: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_reaction-solver"))
+        .arg("run-examples")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/examples"))
+        .args(extra)
+        .status()
+        .expect("run reaction-solver")
+        .success()
+}
+
+#[test]
+fn examples_generate_models() {
+    assert!(run_examples(&["--dry-run"]));
+}
+
+#[test]
+fn examples_solve() {
+    let minizinc = env::var_os("MINIZINC").unwrap_or_else(|| "minizinc".into());
+    if Command::new(minizinc).arg("--version").output().is_err() {
+        eprintln!("minizinc can't be run, so the examples aren't solved");
+        return;
+    }
+
+    assert!(run_examples(&[]));
+}
@@ -0,0 +1,145 @@
+//! Printing a parsed program gives chem source that parses to the same program,
+//! so printing that again gives the same source.
+
+use std::fs;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use reaction_solver::parse_checked;
+
+/// Parse, print, parse and print again, checking both prints are the same
+fn assert_round_trips(source: &str) {
+    let printed = match parse_checked(source) {
+        Ok(program) => program.to_source(),
+        Err(diagnostics) => panic!("{diagnostics:?} in:\n{source}"),
+    };
+    let reprinted = match parse_checked(&printed) {
+        Ok(program) => program.to_source(),
+        Err(diagnostics) => panic!("{diagnostics:?} in the printed:\n{printed}\nof:\n{source}"),
+    };
+
+    assert_eq!(printed, reprinted, "printed differently the second time, from:\n{source}");
+}
+
+#[test]
+fn examples_round_trip() {
+    for dir in ["examples", "problems"] {
+        let dir = format!("{}/{dir}", env!("CARGO_MANIFEST_DIR"));
+        for entry in fs::read_dir(dir).expect("read the directory") {
+            let source = fs::read_to_string(entry.expect("read the directory").path()).expect("read the chem file");
+            assert_round_trips(&source);
+        }
+    }
+}
+
+// `group` and `time` are keywords too, where they start something
+const SYMBOLS: &[&str] = &["a", "b", "group", "iron-ore", "steam@165", "steam@500", "time", "water"];
+const LABELS: &[&str] = &["smelt", "\"mine copper\"", "'the \"best\" recipe'", "\"it's\""];
+
+/// A random chem file using most of the language, which mostly parses
+fn random_source(rng: &mut StdRng) -> String {
+    let mut source = String::new();
+    let terms = |rng: &mut StdRng, min: usize| {
+        let count = rng.gen_range(min..4);
+        let symbols = SYMBOLS.choose_multiple(rng, count).collect::<Vec<_>>();
+        symbols.iter().map(|s| if rng.gen() { format!("{} {s}", rng.gen_range(0..1000)) } else { s.to_string() }).collect::<Vec<_>>().join(" + ")
+    };
+
+    if rng.gen_bool(0.3) {
+        source.push_str("raw water;\ntransport a 0.5;\ntech basics;\ntech advanced cost 20 requires basics;\n");
+    }
+    if rng.gen_bool(0.2) {
+        source.push_str("alias h2o = water;\n");
+    }
+    for idx in 0..rng.gen_range(1..6) {
+        let mut reaction = String::new();
+        if rng.gen_bool(0.1) {
+            reaction.push_str("#[deprecated(\"use another\")] ");
+        }
+        if rng.gen_bool(0.1) {
+            reaction.push_str("#[optional(fancy)] ");
+        }
+        if rng.gen() {
+            reaction.push_str(&format!("{}: ", LABELS[idx % LABELS.len()]));
+        }
+        let input = if rng.gen_bool(0.1) { " + steam@100..200".to_string() } else { String::new() };
+        reaction.push_str(&format!("{}{input} -({})->", terms(rng, 0), rng.gen_range(1..100)));
+        if rng.gen_bool(0.2) {
+            reaction.push_str(&format!(" 0.25 {} | 0.5 {}", terms(rng, 1), terms(rng, 0)));
+        } else {
+            reaction.push_str(&format!(" {}", terms(rng, 0)));
+        }
+        let attributes = [
+            "fixed-cost 10".to_string(),
+            "min-batch 2.5".to_string(),
+            "machines 4".to_string(),
+            "cost-curve 0: 0, 10: 25.5".to_string(),
+            "cost { power: 90, money: 1e3 }".to_string(),
+            "tag smelting tag \"early game\"".to_string(),
+            "machine furnace".to_string(),
+            format!("requires {}", if source.contains("tech") { "basics" } else { "" }),
+        ];
+        let count = rng.gen_range(0..3);
+        for attribute in attributes.choose_multiple(rng, count) {
+            reaction.push_str(&format!(" {attribute}"));
+        }
+
+        if rng.gen_bool(0.2) {
+            source.push_str(&format!("zone z{idx} {{ capacity {}; {reaction}; }}\n", rng.gen_range(1..10)));
+        } else {
+            source.push_str(&format!("{reaction};\n"));
+        }
+    }
+
+    for idx in 0..rng.gen_range(0..3) {
+        let mut target = format!("target t{idx} {{\n");
+        // all constraints on a symbol have to compare the same way, in the same time
+        let comparison = ["", "at-most ", "exactly "].choose(rng).unwrap();
+        let window = if rng.gen() { " in 60" } else { "" };
+        let items = [
+            format!("using {};", SYMBOLS.choose_multiple(rng, 2).cloned().collect::<Vec<_>>().join(", ")),
+            format!("create {comparison}{}{window};", terms(rng, 1)),
+            format!("create {comparison}{}{window};", terms(rng, 1)),
+            format!("in {};", rng.gen_range(1..100)),
+            ["minimize reactions;", "minimize time;", "minimize cost power;", "minimize techs;", "minimize resources 2 a + b;"].choose(rng).unwrap().to_string(),
+            format!("group \"extra {idx}\" disabled {{ create {comparison}{}{window}; }}", terms(rng, 1)),
+            format!("prefer {} else penalty 10;", terms(rng, 1)),
+            "quantum 15 a;".to_string(),
+            "quantum 2 \"smelt\";".to_string(),
+            "transport weight 0.1;".to_string(),
+            "transport budget 100;".to_string(),
+            "budget power 500.5;".to_string(),
+            "enable fancy;".to_string(),
+            "constraint 2 * a + 1 <= 0.5 * objective;".to_string(),
+            "constraint objective >= objective(t0) + 1e-3 * b;".to_string(),
+        ];
+        let count = rng.gen_range(0..8);
+        for item in items.choose_multiple(rng, count) {
+            target.push_str(&format!("    {item}\n"));
+        }
+        source.push_str(&target);
+        source.push_str("}\n");
+    }
+
+    if source.contains("target t1") && rng.gen() {
+        source.push_str("suite all { targets t0, t1; in 10; minimize reactions; }\n");
+    }
+    source
+}
+
+#[test]
+fn random_programs_round_trip() {
+    let mut rng = StdRng::seed_from_u64(683);
+    let mut parsed = 0;
+
+    for _ in 0..500 {
+        let source = random_source(&mut rng);
+        if parse_checked(&source).is_ok() {
+            assert_round_trips(&source);
+            parsed += 1;
+        }
+    }
+
+    // the property holds for nothing if nothing parses
+    assert!(parsed > 250, "only {parsed} of the random programs parse");
+}
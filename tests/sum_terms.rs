@@ -0,0 +1,35 @@
+//! Adding up the terms of sums, which are checked to come to amounts.
+
+use std::collections::HashMap;
+use reaction_solver::ast::{sum_terms, Symbol};
+
+#[test]
+fn terms_add_up() {
+    let sums = [HashMap::from([(Symbol("a"), 2_usize), (Symbol("b"), 1)]), HashMap::from([(Symbol("a"), 1)])];
+    assert_eq!(sum_terms(sums), Ok(HashMap::from([(Symbol("a"), 3), (Symbol("b"), 1)])));
+}
+
+#[test]
+fn terms_cancel_out() {
+    let sums = [HashMap::from([(Symbol("a"), 2_i64), (Symbol("b"), 1)]), HashMap::from([(Symbol("a"), -2)])];
+    assert_eq!(sum_terms(sums), Ok(HashMap::from([(Symbol("a"), 0), (Symbol("b"), 1)])));
+}
+
+#[test]
+fn terms_cancel_out_past_the_largest_amount() {
+    // the first sum alone doesn't fit an amount, but the sum of both does
+    let sums = [HashMap::from([(Symbol("a"), usize::MAX as i128 + 1)]), HashMap::from([(Symbol("a"), -1)])];
+    assert_eq!(sum_terms(sums), Ok(HashMap::from([(Symbol("a"), usize::MAX)])));
+}
+
+#[test]
+fn negative_totals_are_errors() {
+    let sums = [HashMap::from([(Symbol("a"), 1_i64)]), HashMap::from([(Symbol("a"), -2)])];
+    assert_eq!(sum_terms(sums), Err(Symbol("a")));
+}
+
+#[test]
+fn too_large_totals_are_errors() {
+    let sums = [HashMap::from([(Symbol("a"), usize::MAX)]), HashMap::from([(Symbol("a"), 1)])];
+    assert_eq!(sum_terms(sums), Err(Symbol("a")));
+}